@@ -0,0 +1,147 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use shared::player::{PlayerDisplayState, PlayerInputState};
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Behaviour {
+    /// Wanders left and right, with the odd hop.
+    RandomWalk,
+
+    /// Wanders like a random walker, but jumps as often as it can.
+    JumpSpam,
+
+    /// Heads for the nearest other dango, and jumps when it is above.
+    Chase,
+}
+
+impl Behaviour {
+    pub const ALL: [Behaviour; 3] = [Behaviour::RandomWalk, Behaviour::JumpSpam, Behaviour::Chase];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Behaviour::RandomWalk => "random-walk",
+            Behaviour::JumpSpam => "jump-spam",
+            Behaviour::Chase => "chase",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|behaviour| behaviour.name() == name)
+    }
+}
+
+/// How long a random walker sticks with a direction, in seconds.
+const MIN_WALK_DURATION: f64 = 0.5;
+const MAX_WALK_DURATION: f64 = 2.0;
+
+const RANDOM_WALK_JUMP_CHANCE: f64 = 0.2;
+const JUMP_DURATION: f64 = 0.3;
+const JUMP_SPAM_PERIOD: f64 = 0.6;
+
+/// How close a chaser needs to get before it stops walking.
+const CHASE_REACH: f32 = 0.3;
+
+/// How much higher the target needs to be before a chaser jumps after it.
+const CHASE_JUMP_HEIGHT: f32 = 0.5;
+
+/// A chaser that wants to move but goes slower than this is probably stuck against something.
+const CHASE_STUCK_SPEED: f32 = 0.1;
+
+/// Decides what a bot presses, based on what it can see in its client's display state.
+pub struct BotBrain {
+    pub behaviour: Behaviour,
+    rng: StdRng,
+    walk_direction: i8,
+    next_walk_change: f64,
+    jump_until: f64,
+}
+
+impl BotBrain {
+    pub fn new(behaviour: Behaviour) -> Self {
+        Self {
+            behaviour,
+            rng: StdRng::from_entropy(),
+            walk_direction: 0,
+            next_walk_change: 0.0,
+            jump_until: 0.0,
+        }
+    }
+
+    pub fn decide<'a>(
+        &mut self,
+        now: f64,
+        me: &PlayerDisplayState,
+        others: impl Iterator<Item = &'a PlayerDisplayState>,
+    ) -> PlayerInputState {
+        let (direction, jump) = match self.behaviour {
+            Behaviour::RandomWalk => (self.wander(now), now < self.jump_until),
+            Behaviour::JumpSpam => (self.wander(now), now % JUMP_SPAM_PERIOD < JUMP_DURATION),
+            Behaviour::Chase => {
+                let position = me.measurements.center_of_mass;
+                let nearest = others
+                    .map(|other| {
+                        let distance = (other.measurements.center_of_mass - position).norm();
+                        (other, distance)
+                    })
+                    // Note: A dango that blew up into NaNs is nowhere, so it can't be the nearest.
+                    .filter(|(_, distance)| distance.is_finite())
+                    .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+                match nearest {
+                    Some((target, _)) => {
+                        let offset = target.measurements.center_of_mass - position;
+                        let direction = if offset.x > CHASE_REACH {
+                            1
+                        } else if offset.x < -CHASE_REACH {
+                            -1
+                        } else {
+                            0
+                        };
+                        let is_stuck =
+                            direction != 0 && me.measurements.velocity.x.abs() < CHASE_STUCK_SPEED;
+                        (direction, offset.y > CHASE_JUMP_HEIGHT || is_stuck)
+                    }
+                    None => (self.wander(now), now < self.jump_until),
+                }
+            }
+        };
+        PlayerInputState {
+            horizontal: direction * PlayerInputState::MAX_HORIZONTAL,
+            jump,
+            roll: false,
+        }
+    }
+
+    fn wander(&mut self, now: f64) -> i8 {
+        if now >= self.next_walk_change {
+            self.walk_direction = self.rng.gen_range(-1..=1);
+            self.next_walk_change = now + self.rng.gen_range(MIN_WALK_DURATION..MAX_WALK_DURATION);
+            if self.rng.gen_bool(RANDOM_WALK_JUMP_CHANCE) {
+                self.jump_until = now + JUMP_DURATION;
+            }
+        }
+        self.walk_direction
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dango_at(x: f32, y: f32) -> PlayerDisplayState {
+        let mut dango = PlayerDisplayState::default();
+        dango.measurements.center_of_mass.x = x;
+        dango.measurements.center_of_mass.y = y;
+        dango
+    }
+
+    #[test]
+    fn chasers_skip_dangos_that_blew_up() {
+        let mut brain = BotBrain::new(Behaviour::Chase);
+        let others = [dango_at(f32::NAN, f32::NAN), dango_at(5.0, 0.0)];
+        let input = brain.decide(0.0, &dango_at(0.0, 0.0), others.iter());
+        assert_eq!(input.horizontal, PlayerInputState::MAX_HORIZONTAL);
+    }
+}
@@ -0,0 +1,250 @@
+use crate::behaviour::Behaviour;
+use std::{env, fmt::Display, net::SocketAddr, str::FromStr};
+
+pub const USAGE: &str = "\
+Usage: bot <ADDRESS> [OPTIONS]
+
+ADDRESS is the host:port printed by the dedicated server [DANGO_SERVER_ADDRESS].
+
+Options (each can also be set through the environment variable in brackets):
+    --bots <COUNT>                How many bots to connect [DANGO_BOTS] (default: 8)
+    --behaviour <NAME>            What the bots do: random-walk, jump-spam, chase, mixed
+                                  [DANGO_BOT_BEHAVIOUR] (default: mixed)
+    --report-interval <SECONDS>   How often to print each bot's stats [DANGO_BOT_REPORT_INTERVAL]
+                                  (default: 5)
+    --duration <SECONDS>          Stop after this long [DANGO_BOT_DURATION] (default: forever)
+    --help                        Print this message";
+
+/// Which behaviour each bot gets. Mixed hands them out in turn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BehaviourChoice {
+    All(Behaviour),
+    Mixed,
+}
+
+/// Settings for a load test. Command line arguments take precedence over environment variables,
+/// which take precedence over the defaults.
+#[derive(Debug, Clone)]
+pub struct BotConfig {
+    pub server_address: Option<SocketAddr>,
+    pub bot_count: usize,
+    pub behaviour: BehaviourChoice,
+    pub report_interval: f64,
+    pub duration: Option<f64>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    HelpRequested,
+    MissingServerAddress,
+    UnknownArgument(String),
+    MissingValue(String),
+    InvalidValue { option: String, value: String },
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        Self {
+            server_address: None,
+            bot_count: 8,
+            behaviour: BehaviourChoice::Mixed,
+            report_interval: 5.0,
+            duration: None,
+        }
+    }
+}
+
+impl BotConfig {
+    pub fn from_env_and_args() -> Result<Self, ConfigError> {
+        Self::from_sources(|env_var| env::var(env_var).ok(), env::args().skip(1))
+    }
+
+    /// Reads the settings from the given environment variables and command line arguments,
+    /// leaving out the program name.
+    fn from_sources<E, A>(read_env_var: E, args: A) -> Result<Self, ConfigError>
+    where
+        E: Fn(&str) -> Option<String>,
+        A: IntoIterator<Item = String>,
+    {
+        let mut config = Self::default();
+        for (option, env_var) in [
+            ("--server", "DANGO_SERVER_ADDRESS"),
+            ("--bots", "DANGO_BOTS"),
+            ("--behaviour", "DANGO_BOT_BEHAVIOUR"),
+            ("--report-interval", "DANGO_BOT_REPORT_INTERVAL"),
+            ("--duration", "DANGO_BOT_DURATION"),
+        ]
+        .iter()
+        {
+            if let Some(value) = read_env_var(env_var) {
+                config.set(option, value)?;
+            }
+        }
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "--help" || arg == "-h" {
+                return Err(ConfigError::HelpRequested);
+            }
+            if !arg.starts_with("--") {
+                config.set("--server", arg)?;
+                continue;
+            }
+            let (option, value) = match arg.find('=') {
+                Some(index) => (arg[..index].to_string(), arg[index + 1..].to_string()),
+                None => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| ConfigError::MissingValue(arg.clone()))?;
+                    (arg, value)
+                }
+            };
+            config.set(&option, value)?;
+        }
+
+        if config.server_address.is_none() {
+            return Err(ConfigError::MissingServerAddress);
+        }
+        Ok(config)
+    }
+
+    fn set(&mut self, option: &str, value: String) -> Result<(), ConfigError> {
+        match option {
+            "--server" => self.server_address = Some(parse(option, value)?),
+            "--bots" => self.bot_count = parse(option, value)?,
+            "--behaviour" => {
+                self.behaviour = if value == "mixed" {
+                    BehaviourChoice::Mixed
+                } else {
+                    BehaviourChoice::All(Behaviour::from_name(&value).ok_or_else(|| {
+                        ConfigError::InvalidValue {
+                            option: option.to_string(),
+                            value,
+                        }
+                    })?)
+                }
+            }
+            "--report-interval" => self.report_interval = parse_seconds(option, value)?,
+            "--duration" => self.duration = Some(parse_seconds(option, value)?),
+            _ => return Err(ConfigError::UnknownArgument(option.to_string())),
+        }
+        Ok(())
+    }
+
+    pub fn behaviour_for(&self, bot_index: usize) -> Behaviour {
+        match self.behaviour {
+            BehaviourChoice::All(behaviour) => behaviour,
+            BehaviourChoice::Mixed => Behaviour::ALL[bot_index % Behaviour::ALL.len()],
+        }
+    }
+}
+
+fn parse<T: FromStr>(option: &str, value: String) -> Result<T, ConfigError> {
+    value.parse().map_err(|_| ConfigError::InvalidValue {
+        option: option.to_string(),
+        value,
+    })
+}
+
+fn parse_seconds(option: &str, value: String) -> Result<f64, ConfigError> {
+    let seconds: f64 = parse(option, value.clone())?;
+    if seconds <= 0.0 || !seconds.is_finite() {
+        return Err(ConfigError::InvalidValue {
+            option: option.to_string(),
+            value,
+        });
+    }
+    Ok(seconds)
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::HelpRequested => write!(f, "{}", USAGE),
+            ConfigError::MissingServerAddress => {
+                write!(f, "Missing the server's address\n\n{}", USAGE)
+            }
+            ConfigError::UnknownArgument(arg) => write!(f, "Unknown argument {}\n\n{}", arg, USAGE),
+            ConfigError::MissingValue(option) => {
+                write!(f, "Missing a value for {}\n\n{}", option, USAGE)
+            }
+            ConfigError::InvalidValue { option, value } => {
+                write!(f, "Invalid value {:?} for {}\n\n{}", value, option, USAGE)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(env_vars: &[(&str, &str)], args: &[&str]) -> Result<BotConfig, ConfigError> {
+        BotConfig::from_sources(
+            |env_var| {
+                env_vars
+                    .iter()
+                    .find(|(name, _)| *name == env_var)
+                    .map(|(_, value)| value.to_string())
+            },
+            args.iter().map(|arg| arg.to_string()),
+        )
+    }
+
+    #[test]
+    fn reads_the_server_address_as_a_bare_argument() {
+        let config = config(&[], &["127.0.0.1:14191", "--bots=3"]).unwrap();
+        assert_eq!(
+            config.server_address,
+            Some("127.0.0.1:14191".parse().unwrap())
+        );
+        assert_eq!(config.bot_count, 3);
+        assert_eq!(config.behaviour, BehaviourChoice::Mixed);
+        assert_eq!(config.duration, None);
+    }
+
+    #[test]
+    fn arguments_take_precedence_over_the_environment() {
+        let config = config(
+            &[
+                ("DANGO_SERVER_ADDRESS", "127.0.0.1:14191"),
+                ("DANGO_BOT_BEHAVIOUR", "chase"),
+            ],
+            &["--behaviour", "jump-spam", "--duration", "30"],
+        )
+        .unwrap();
+        assert_eq!(config.behaviour, BehaviourChoice::All(Behaviour::JumpSpam));
+        assert_eq!(config.duration, Some(30.0));
+    }
+
+    #[test]
+    fn hands_out_behaviours_in_turn_when_mixed() {
+        let config = config(&[], &["127.0.0.1:14191"]).unwrap();
+        for bot_index in 0..Behaviour::ALL.len() * 2 {
+            assert_eq!(
+                config.behaviour_for(bot_index),
+                Behaviour::ALL[bot_index % Behaviour::ALL.len()]
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(matches!(
+            config(&[], &[]),
+            Err(ConfigError::MissingServerAddress)
+        ));
+        assert!(matches!(
+            config(&[], &["127.0.0.1:14191", "--behaviour", "dance"]),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            config(&[], &["127.0.0.1:14191", "--report-interval", "-1"]),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            config(&[], &["127.0.0.1:14191", "--bots"]),
+            Err(ConfigError::MissingValue(_))
+        ));
+    }
+}
@@ -0,0 +1,197 @@
+use behaviour::{Behaviour, BotBrain};
+use bevy::{
+    core::{CorePlugin, DefaultTaskPoolOptions},
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, ComputeTaskPool, IoTaskPool},
+};
+use config::{BotConfig, ConfigError};
+use crystalorb_bevy_networking_turbulence::{
+    bevy_networking_turbulence::NetworkResource,
+    crystalorb::client::{
+        stage::{Stage as ClientStage, StageMut as ClientStageMut},
+        Client,
+    },
+    CrystalOrbClientPlugin, WrappedNetworkResource,
+};
+use report::{print_reports, BotReport, StatsTracker};
+use shared::{
+    appearance::PlayerAppearance,
+    physics_multiplayer::{PhysicsCommand, PhysicsDisplayState, PhysicsWorld},
+    player::PlayerId,
+    player_input::{self, InputSender, LocalPlayers, RequestedAppearance, Spectator},
+    session::LocalSessions,
+    settings,
+};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+mod behaviour;
+mod config;
+mod report;
+
+struct Bot {
+    brain: BotBrain,
+    input_sender: InputSender,
+}
+
+fn main() {
+    let config = match BotConfig::from_env_and_args() {
+        Ok(config) => config,
+        Err(ConfigError::HelpRequested) => {
+            println!("{}", ConfigError::HelpRequested);
+            return;
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(2);
+        }
+    };
+    let server_address = config.server_address.unwrap();
+
+    // Note: Every bot is an app of its own, but they share one set of task pools rather than
+    // each starting a thread per core.
+    let mut task_pools = World::default();
+    DefaultTaskPoolOptions::default().create_default_pools(&mut task_pools);
+
+    println!("Connecting {} bots to {}", config.bot_count, server_address);
+    let mut bots: Vec<App> = (0..config.bot_count)
+        .map(|index| {
+            let mut app = App::build();
+            app.insert_resource(IoTaskPool(
+                task_pools.get_resource::<IoTaskPool>().unwrap().0.clone(),
+            ))
+            .insert_resource(ComputeTaskPool(
+                task_pools
+                    .get_resource::<ComputeTaskPool>()
+                    .unwrap()
+                    .0
+                    .clone(),
+            ))
+            .insert_resource(AsyncComputeTaskPool(
+                task_pools
+                    .get_resource::<AsyncComputeTaskPool>()
+                    .unwrap()
+                    .0
+                    .clone(),
+            ))
+            .add_plugin(CorePlugin::default())
+            .add_plugin(CrystalOrbClientPlugin::<PhysicsWorld>::new(
+                settings::NETWORKED_PHYSICS_CONFIG,
+            ))
+            .insert_resource(RequestedAppearance(PlayerAppearance {
+                nickname: format!("bot {}", index),
+                ..Default::default()
+            }))
+            .insert_resource(Bot {
+                brain: BotBrain::new(config.behaviour_for(index)),
+                input_sender: InputSender::default(),
+            })
+            .init_resource::<LocalPlayers>()
+            .init_resource::<Spectator>()
+            .init_resource::<LocalSessions>()
+            .add_system(player_input::player_join_system.system())
+            .add_system(bot_input_system.system());
+            app.world_mut()
+                .get_resource_mut::<NetworkResource>()
+                .unwrap()
+                .connect(server_address.to_string());
+            app.app
+        })
+        .collect();
+
+    let mut stats_trackers: Vec<StatsTracker> =
+        bots.iter().map(|_| StatsTracker::default()).collect();
+    let start = Instant::now();
+    let frame_duration = Duration::from_secs_f64(settings::TIMESTEP);
+    let mut next_report = config.report_interval;
+    loop {
+        let frame_start = Instant::now();
+        let elapsed = start.elapsed().as_secs_f64();
+        if config
+            .duration
+            .map_or(false, |duration| elapsed >= duration)
+        {
+            break;
+        }
+
+        for bot in bots.iter_mut() {
+            bot.update();
+        }
+
+        if elapsed >= next_report {
+            next_report += config.report_interval;
+            let reports: Vec<BotReport> = bots
+                .iter()
+                .zip(stats_trackers.iter_mut())
+                .map(|(bot, stats_tracker)| report(bot, stats_tracker, elapsed))
+                .collect();
+            print_reports(elapsed, &reports);
+        }
+
+        if let Some(remaining) = frame_duration.checked_sub(frame_start.elapsed()) {
+            thread::sleep(remaining);
+        }
+    }
+}
+
+fn bot_input_system(
+    mut bot: ResMut<Bot>,
+    time: Res<Time>,
+    mut client: ResMut<Client<PhysicsWorld>>,
+    mut net: ResMut<NetworkResource>,
+) {
+    if let ClientStageMut::Ready(mut ready_client) = client.stage_mut() {
+        let player_id = PlayerId(ready_client.client_id());
+        let now = time.seconds_since_startup();
+        let Bot {
+            brain,
+            input_sender,
+        } = &mut *bot;
+
+        let display_state: &PhysicsDisplayState = ready_client.display_state();
+        let me = match display_state.players().get(&player_id) {
+            Some(me) => me,
+            None => return,
+        };
+        let others = display_state
+            .players()
+            .iter()
+            .filter(|(other_id, _)| **other_id != player_id)
+            .map(|(_, other)| other);
+        let next_inputs = brain.decide(now, me, others);
+
+        input_sender.send(now, &next_inputs, |command| {
+            ready_client.issue_command(
+                PhysicsCommand::PlayerInput { player_id, command },
+                &mut WrappedNetworkResource(&mut *net),
+            );
+        });
+    }
+}
+
+fn report(bot: &App, stats_tracker: &mut StatsTracker, now: f64) -> BotReport {
+    let behaviour: Behaviour = bot.world.get_resource::<Bot>().unwrap().brain.behaviour;
+    let joined = match bot
+        .world
+        .get_resource::<Client<PhysicsWorld>>()
+        .unwrap()
+        .stage()
+    {
+        ClientStage::Ready(ready_client) => {
+            let display_state: &PhysicsDisplayState = ready_client.display_state();
+            display_state
+                .players()
+                .contains_key(&PlayerId(ready_client.client_id()))
+        }
+        _ => false,
+    };
+    let net = bot.world.get_resource::<NetworkResource>().unwrap();
+    let stats = net
+        .connections
+        .keys()
+        .next()
+        .and_then(|handle| net.connection_stats(*handle));
+    stats_tracker.report(now, behaviour, joined, stats)
+}
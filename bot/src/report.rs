@@ -0,0 +1,141 @@
+use crate::behaviour::Behaviour;
+use crystalorb_bevy_networking_turbulence::bevy_networking_turbulence::{
+    ConnectionStats, TrafficStats,
+};
+use std::{collections::HashMap, time::Duration};
+
+pub struct BotReport {
+    pub behaviour: Behaviour,
+    pub joined: bool,
+    pub round_trip_time: Option<Duration>,
+
+    /// How many snapshots reach the bot. CrystalOrb doesn't say how often it actually rolls
+    /// back, but it can only ever do so when a snapshot arrives.
+    pub snapshots_per_second: f64,
+
+    pub snapshot_bytes_per_second: f64,
+    pub total_bytes_per_second: f64,
+}
+
+/// Turns a bot's running totals into rates over each report interval.
+#[derive(Default)]
+pub struct StatsTracker {
+    previous: Option<(f64, ConnectionStats)>,
+}
+
+impl StatsTracker {
+    pub fn report(
+        &mut self,
+        now: f64,
+        behaviour: Behaviour,
+        joined: bool,
+        stats: Option<&ConnectionStats>,
+    ) -> BotReport {
+        let mut report = BotReport {
+            behaviour,
+            joined,
+            round_trip_time: stats.and_then(|stats| stats.round_trip_time),
+            snapshots_per_second: 0.0,
+            snapshot_bytes_per_second: 0.0,
+            total_bytes_per_second: 0.0,
+        };
+        let stats = match stats {
+            Some(stats) => stats,
+            None => {
+                self.previous = None;
+                return report;
+            }
+        };
+        if let Some((previous_time, previous_stats)) = &self.previous {
+            let elapsed = now - previous_time;
+            if elapsed > 0.0 {
+                let received = difference(stats.received, previous_stats.received);
+                report.total_bytes_per_second = received.bytes as f64 / elapsed;
+
+                // Note: The bot can't tell which channel CrystalOrb sends its snapshots on, but
+                // a snapshot is far bigger than anything else the server sends.
+                let by_channel: HashMap<u8, TrafficStats> = stats
+                    .received_by_channel
+                    .iter()
+                    .map(|(channel, traffic)| {
+                        let previous = previous_stats
+                            .received_by_channel
+                            .get(channel)
+                            .copied()
+                            .unwrap_or_default();
+                        (*channel, difference(*traffic, previous))
+                    })
+                    .collect();
+                if let Some(snapshots) = by_channel.values().max_by(|a, b| {
+                    a.mean_packet_len()
+                        .partial_cmp(&b.mean_packet_len())
+                        .unwrap()
+                }) {
+                    report.snapshots_per_second = snapshots.packets as f64 / elapsed;
+                    report.snapshot_bytes_per_second = snapshots.bytes as f64 / elapsed;
+                }
+            }
+        }
+        self.previous = Some((now, stats.clone()));
+        report
+    }
+}
+
+fn difference(current: TrafficStats, previous: TrafficStats) -> TrafficStats {
+    TrafficStats {
+        packets: current.packets.saturating_sub(previous.packets),
+        bytes: current.bytes.saturating_sub(previous.bytes),
+    }
+}
+
+pub fn print_reports(elapsed: f64, reports: &[BotReport]) {
+    println!();
+    println!("After {:.0} seconds:", elapsed);
+    println!(
+        "{:>4}  {:<12} {:<7} {:>8} {:>12} {:>15} {:>12}",
+        "bot", "behaviour", "joined", "rtt", "snapshots/s", "snapshot kB/s", "total kB/s"
+    );
+    for (index, report) in reports.iter().enumerate() {
+        println!(
+            "{:>4}  {:<12} {:<7} {:>8} {:>12.1} {:>15.2} {:>12.2}",
+            index,
+            report.behaviour.name(),
+            if report.joined { "yes" } else { "no" },
+            format_round_trip_time(report.round_trip_time),
+            report.snapshots_per_second,
+            report.snapshot_bytes_per_second / 1000.0,
+            report.total_bytes_per_second / 1000.0,
+        );
+    }
+
+    let joined_count = reports.iter().filter(|report| report.joined).count();
+    let round_trip_times: Vec<Duration> = reports
+        .iter()
+        .filter_map(|report| report.round_trip_time)
+        .collect();
+    let mean_round_trip_time = if round_trip_times.is_empty() {
+        None
+    } else {
+        Some(round_trip_times.iter().sum::<Duration>() / round_trip_times.len() as u32)
+    };
+    let max_round_trip_time = round_trip_times.iter().max().copied();
+    println!(
+        "{} of {} joined, rtt mean {} max {}, {:.2} kB/s received in total",
+        joined_count,
+        reports.len(),
+        format_round_trip_time(mean_round_trip_time),
+        format_round_trip_time(max_round_trip_time),
+        reports
+            .iter()
+            .map(|report| report.total_bytes_per_second)
+            .sum::<f64>()
+            / 1000.0,
+    );
+}
+
+fn format_round_trip_time(round_trip_time: Option<Duration>) -> String {
+    match round_trip_time {
+        Some(round_trip_time) => format!("{} ms", round_trip_time.as_millis()),
+        None => "-".to_string(),
+    }
+}
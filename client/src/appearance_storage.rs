@@ -0,0 +1,68 @@
+use bevy::prelude::*;
+use shared::{
+    appearance::{EyeStyle, PlayerAppearance},
+    color::Rgb,
+    platform::Platform,
+    player_input::RequestedAppearance,
+};
+
+const NICKNAME_KEY: &str = "dango-nickname";
+const COLOR_KEY: &str = "dango-color";
+const SIZE_KEY: &str = "dango-size";
+const EYES_KEY: &str = "dango-eyes";
+
+/// Loads the appearance chosen on a previous visit. Any `nickname`, `color`, `size` or `eyes`
+/// launch parameters take precedence, and get remembered for the next visit.
+pub fn load_requested_appearance(platform: &dyn Platform) -> RequestedAppearance {
+    let mut appearance = PlayerAppearance::default();
+    for (key, param) in [
+        (NICKNAME_KEY, "nickname"),
+        (COLOR_KEY, "color"),
+        (SIZE_KEY, "size"),
+        (EYES_KEY, "eyes"),
+    ]
+    .iter()
+    {
+        if let Some(value) = platform
+            .launch_param(param)
+            .or_else(|| platform.load_setting(key))
+        {
+            apply_value(&mut appearance, key, &value);
+        }
+    }
+
+    for (key, value) in [
+        (NICKNAME_KEY, appearance.nickname.clone()),
+        (COLOR_KEY, appearance.color.to_hex()),
+        (SIZE_KEY, appearance.size.to_string()),
+        (EYES_KEY, appearance.eye_style.name().to_string()),
+    ]
+    .iter()
+    {
+        if let Err(err) = platform.save_setting(key, value) {
+            warn!("Could not remember dango appearance: {}", err);
+            break;
+        }
+    }
+
+    RequestedAppearance(appearance)
+}
+
+fn apply_value(appearance: &mut PlayerAppearance, key: &str, value: &str) {
+    match key {
+        NICKNAME_KEY => appearance.nickname = value.to_string(),
+        COLOR_KEY => match Rgb::from_hex(value) {
+            Some(color) => appearance.color = color,
+            None => warn!("Ignoring invalid dango colour {:?}", value),
+        },
+        SIZE_KEY => match value.parse() {
+            Ok(size) => appearance.size = size,
+            Err(err) => warn!("Ignoring invalid dango size {:?}: {:?}", value, err),
+        },
+        EYES_KEY => match EyeStyle::from_name(value) {
+            Some(eye_style) => appearance.eye_style = eye_style,
+            None => warn!("Ignoring unknown eye style {:?}", value),
+        },
+        _ => unreachable!(),
+    }
+}
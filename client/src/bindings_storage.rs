@@ -0,0 +1,74 @@
+use bevy::prelude::*;
+use shared::{
+    input_bindings::{ActionBindings, BindingName, InputBindings},
+    platform::Platform,
+};
+
+const FIRST_KEYS_KEY: &str = "dango-first-keys";
+const SECOND_KEYS_KEY: &str = "dango-second-keys";
+const GAMEPAD_KEY: &str = "dango-gamepad";
+
+/// Loads the controls chosen on a previous visit, unless `first-keys`, `second-keys` or `gamepad`
+/// launch parameters override them for this visit only. The defaults get saved for anything that
+/// hasn't been stored yet, so that there is always something to edit in the settings file.
+pub fn load_input_bindings(platform: &dyn Platform) -> InputBindings {
+    let mut bindings = InputBindings::default();
+    load(
+        platform,
+        FIRST_KEYS_KEY,
+        "first-keys",
+        &mut bindings.first_keys,
+    );
+    load(
+        platform,
+        SECOND_KEYS_KEY,
+        "second-keys",
+        &mut bindings.second_keys,
+    );
+    load(platform, GAMEPAD_KEY, "gamepad", &mut bindings.gamepad);
+    bindings
+}
+
+fn load<T: BindingName>(
+    platform: &dyn Platform,
+    key: &str,
+    param: &str,
+    bindings: &mut ActionBindings<T>,
+) {
+    let stored = platform.load_setting(key);
+    if stored.is_none() {
+        if let Err(err) = platform.save_setting(key, &bindings.to_setting()) {
+            warn!("Could not remember controls: {}", err);
+        }
+    }
+
+    // Note: A line that doesn't read back is left alone, so that a typo in the settings file can
+    // be fixed rather than being replaced by the defaults.
+    if let Some(value) = platform.launch_param(param).or(stored) {
+        match ActionBindings::from_setting(&value) {
+            Some(loaded_bindings) => *bindings = loaded_bindings,
+            None => warn!("Ignoring invalid controls {:?}", value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_settings_page_only_offers_keys_that_read_back() {
+        let page = include_str!("../index.html");
+        let list_start = page
+            .find("const supportedKeyNames")
+            .expect("page should list the keys it supports");
+        let list = &page[list_start..];
+        let list = &list[..list.find("]);").expect("list should end")];
+        let names: Vec<&str> = list.split('\'').skip(1).step_by(2).collect();
+        assert!(names.contains(&"Space"));
+        for name in names {
+            let key_code = KeyCode::from_name(name).expect(name);
+            assert_eq!(key_code.name(), name);
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use bevy::prelude::*;
+use crystalorb_bevy_networking_turbulence::{
+    crystalorb::client::{stage::Stage as ClientStage, Client},
+    ClientConnectionEvent,
+};
+use shared::{
+    host_migration::{Handoff, CLAIM_KEY, HANDOFF_KEY},
+    physics_multiplayer::{PhysicsDisplayState, PhysicsWorld},
+    platform::{PendingFetch, Platform},
+    session::LocalSessions,
+    signalling::SignallingEndpoint,
+};
+use std::sync::Arc;
+
+/// How long, in seconds, to give the successor to take over the room before reconnecting to it.
+const RECONNECT_DELAY: f64 = 5.0;
+
+/// How long, in seconds, the successor waits for the signalling server to notice that the host
+/// went away, before giving up and reconnecting like everyone else.
+const CLAIM_TIMEOUT: f64 = 25.0;
+
+/// How often, in seconds, the successor checks whether the room is waiting for it yet.
+const CLAIM_CHECK_INTERVAL: f64 = 1.0;
+
+/// Keeps the room going when its host goes away. The successor that the host picked switches
+/// over to hosting the room itself, and everyone else reconnects to it a little later to reclaim
+/// their dangos.
+pub struct HostMigrationPlugin;
+
+impl Plugin for HostMigrationPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(host_migration_system.system());
+    }
+}
+
+#[derive(Default)]
+struct HostMigration {
+    client_id: Option<usize>,
+    handoff: Option<Arc<Handoff>>,
+    reconnect_time: Option<f64>,
+
+    /// When the successor gives up trying to take over the room.
+    claim_give_up_time: Option<f64>,
+    next_claim_check_time: f64,
+
+    /// Whether the room is waiting for a new host yet, once the signalling server answers.
+    room_status: Option<PendingFetch>,
+}
+
+fn host_migration_system(
+    mut migration: Local<HostMigration>,
+    mut client_connection_events: EventReader<ClientConnectionEvent>,
+    client: Res<Client<PhysicsWorld>>,
+    sessions: Res<LocalSessions>,
+    time: Res<Time>,
+    platform: Res<Box<dyn Platform>>,
+) {
+    let now = time.seconds_since_startup();
+    if let ClientStage::Ready(ready_client) = client.stage() {
+        let display_state: &PhysicsDisplayState = ready_client.display_state();
+        migration.client_id = Some(ready_client.client_id());
+        // Note: Keep the last handoff around, since the display state is gone by the time the
+        // connection drops.
+        if let Some(handoff) = display_state.handoff() {
+            migration.handoff = Some(handoff);
+        }
+    }
+
+    for client_connection_event in client_connection_events.iter() {
+        if !matches!(
+            client_connection_event,
+            ClientConnectionEvent::Disconnected(_)
+        ) {
+            continue;
+        }
+        let handoff = match &migration.handoff {
+            Some(handoff) => handoff,
+            None => continue,
+        };
+        let is_successor = handoff.successor.client_id().is_some()
+            && handoff.successor.client_id() == migration.client_id;
+        if is_successor {
+            info!("The host went away - waiting to take over the room");
+            migration.claim_give_up_time = Some(now + CLAIM_TIMEOUT);
+            migration.next_claim_check_time = now;
+        } else {
+            info!(
+                "The host went away - reconnecting in {} seconds",
+                RECONNECT_DELAY
+            );
+            migration.reconnect_time = Some(now + RECONNECT_DELAY);
+        }
+    }
+
+    if let Some(give_up_time) = migration.claim_give_up_time {
+        let claimed = match migration.room_status.as_ref().and_then(PendingFetch::take) {
+            Some(room_status) => {
+                migration.room_status = None;
+                Some(
+                    room_status
+                        .map_err(|err| err.to_string())
+                        .and_then(|room_status| {
+                            claim_room(&migration, room_status.trim(), &sessions, &**platform)
+                        }),
+                )
+            }
+            None if migration.room_status.is_none() && now >= migration.next_claim_check_time => {
+                migration.next_claim_check_time = now + CLAIM_CHECK_INTERVAL;
+                match platform.launch_param("join") {
+                    Some(room_id) => {
+                        let status_url = SignallingEndpoint::from_platform(&**platform)
+                            .room_status_url(&room_id);
+                        migration.room_status = Some(platform.start_fetch(&status_url));
+                        None
+                    }
+                    None => Some(Err("not in a room".to_string())),
+                }
+            }
+            None => None,
+        };
+        match claimed {
+            Some(Ok(true)) => migration.claim_give_up_time = None,
+            Some(Ok(false)) if now < give_up_time => {}
+            Some(Ok(false)) => {
+                warn!("The room never waited for a new host - reconnecting instead");
+                migration.claim_give_up_time = None;
+                migration.reconnect_time = Some(now);
+            }
+            Some(Err(err)) => {
+                warn!(
+                    "Could not take over the room - reconnecting instead: {}",
+                    err
+                );
+                migration.claim_give_up_time = None;
+                migration.reconnect_time = Some(now);
+            }
+            None => {}
+        }
+    }
+
+    if migration
+        .reconnect_time
+        .map_or(false, |reconnect_time| now >= reconnect_time)
+    {
+        migration.reconnect_time = None;
+        if let Err(err) = platform.restart() {
+            warn!("Could not reconnect: {}", err);
+        }
+    }
+}
+
+/// Switches over to hosting the room once the signalling server is holding it for a new host.
+/// Answers `false` while the signalling server still thinks the old host is around, since it
+/// only notices a host that crashed after a while.
+fn claim_room(
+    migration: &HostMigration,
+    room_status: &str,
+    sessions: &LocalSessions,
+    platform: &dyn Platform,
+) -> Result<bool, String> {
+    let room_id = platform
+        .launch_param("join")
+        .ok_or_else(|| "not in a room".to_string())?;
+    let handoff = migration
+        .handoff
+        .as_ref()
+        .ok_or_else(|| "no handoff".to_string())?;
+    // Note: The successor is always the first local player, since the others share its
+    // connection.
+    let claim = sessions
+        .0
+        .first()
+        .map(|session| session.next)
+        .filter(|secret| Some(secret.hash()) == handoff.claim)
+        .ok_or_else(|| "the host picked this client under another session".to_string())?;
+
+    match room_status {
+        "vacated" => {
+            info!("Taking over room {}", room_id);
+            platform
+                .save_setting(HANDOFF_KEY, &handoff.to_setting())
+                .and_then(|_| platform.save_setting(CLAIM_KEY, &claim.0.to_string()))
+                .and_then(|_| platform.host_room(&room_id))
+                .map_err(|err| err.to_string())?;
+            Ok(true)
+        }
+        "hosted" | "full" => Ok(false),
+        status => Err(format!("unexpected room status {:?}", status)),
+    }
+}
@@ -5,11 +5,11 @@ use bevy::{
     render::{pass::ClearColor, render_graph::base::BaseRenderGraphConfig},
 };
 use bevy_kira_audio::{Audio, AudioPlugin, AudioSource};
-use bevy_web_fullscreen::FullViewportPlugin;
-use web_sys::UrlSearchParams;
 
 use bevy_prototype_frameshader::FrameshaderPlugin;
-use bevy_prototype_transform_tracker::{TransformTrackingFollower, TransformTrackingPlugin};
+use bevy_prototype_transform_tracker::{
+    TransformTrackingFollower, TransformTrackingPlugin, TransformTrackingZoom,
+};
 
 #[cfg(feature = "debug-fly-camera")]
 use bevy_fly_camera::{FlyCamera, FlyCameraPlugin};
@@ -17,20 +17,50 @@ use bevy_fly_camera::{FlyCamera, FlyCameraPlugin};
 #[cfg(feature = "web")]
 use bevy_webgl2;
 
+#[cfg(feature = "web")]
+use bevy_web_fullscreen::FullViewportPlugin;
+
+#[cfg(feature = "web")]
+use shared::{platform::PendingFetch, signalling::SignallingEndpoint};
+
 use shared::{
     blinking_eyes,
     camera_2point5d::{Camera2point5dBundle, Camera2point5dPlugin},
-    physics_multiplayer::PhysicsWorld,
-    physics_multiplayer_systems, player_input, settings,
+    level::LevelId,
+    physics_multiplayer::{PhysicsDisplayState, PhysicsWorld},
+    physics_multiplayer_systems,
+    physics_offline::{self, OfflinePhysics},
+    platform::{default_platform, ConnectionStatus, Platform},
+    player_input::{self, LocalPlayers, Spectator},
+    settings,
     wasm_print_diagnostics_plugin::WasmPrintDiagnosticsPlugin,
 };
 
+pub mod appearance_storage;
+pub mod bindings_storage;
+pub mod host_migration;
 pub mod sakura;
+pub mod session_storage;
+pub mod spectator;
+pub mod touch_controls;
 
 use crystalorb_bevy_networking_turbulence::{
-    bevy_networking_turbulence::NetworkResource, ClientConnectionEvent, CrystalOrbClientPlugin,
+    bevy_networking_turbulence::NetworkResource,
+    crystalorb::client::{stage::Stage as ClientStage, Client},
+    ClientConnectionEvent, CrystalOrbClientPlugin,
 };
+use host_migration::HostMigrationPlugin;
 use sakura::SakuraPlugin;
+use spectator::SpectatorPlugin;
+use touch_controls::TouchControlsPlugin;
+
+#[cfg(feature = "native")]
+use std::net::ToSocketAddrs;
+
+/// How often, in seconds, a client that hasn't connected yet asks the signalling server whether
+/// the room is full.
+#[cfg(feature = "web")]
+const ROOM_CHECK_INTERVAL: f64 = 3.0;
 
 #[cfg(feature = "web")]
 const VERTEX_SHADER_PATH: &str = "shaders/frameshader.webgl2.vert";
@@ -45,12 +75,52 @@ const FRAGMENT_SHADER_PATH: &str = "shaders/frameshader.webgl2.frag";
 const FRAGMENT_SHADER_PATH: &str = "shaders/frameshader.wgpu.frag";
 
 fn main() {
-    show_load_complete("wasm");
+    let platform = default_platform();
+    platform.report_load_complete("wasm");
 
+    #[cfg(feature = "web")]
     if log::set_logger(&wasm_bindgen_console_logger::DEFAULT_LOGGER).is_ok() {
         log::set_max_level(log::LevelFilter::Info);
     }
 
+    // Note: Natively, we talk UDP straight to a dedicated server, so there's no signalling server
+    // to find a room hosted from a browser tab through.
+    #[cfg(feature = "native")]
+    if let Some(address) = platform.launch_param("join") {
+        if address
+            .to_socket_addrs()
+            .map_or(true, |mut addresses| addresses.next().is_none())
+        {
+            eprintln!(
+                "Can't join {:?}: desktop clients can only join a dedicated server, using the \
+                 host:port that it prints. Rooms hosted from a browser can only be joined from \
+                 the client page.",
+                address
+            );
+            std::process::exit(2);
+        }
+    }
+
+    // Note: Without a server to join, the client plays on a world of its own.
+    let is_offline = platform.launch_param("join").is_none();
+    let requested_appearance = appearance_storage::load_requested_appearance(&*platform);
+    let input_bindings = bindings_storage::load_input_bindings(&*platform);
+    let local_sessions = session_storage::load_local_sessions(&*platform);
+    // Note: Offline, there is nobody else to watch.
+    let spectator = Spectator {
+        is_spectating: !is_offline
+            && platform
+                .launch_param("spectate")
+                .map_or(false, |value| value != "0" && value != "false"),
+        following: None,
+    };
+    let local_players = LocalPlayers::new(
+        platform
+            .launch_param("local-players")
+            .and_then(|count| count.parse().ok())
+            .unwrap_or(1),
+    );
+
     let mut app = App::build();
 
     // Note: Setup hot reloading first before loading other plugins.
@@ -68,6 +138,7 @@ fn main() {
         .add_plugin(bevy::transform::TransformPlugin::default())
         .add_plugin(bevy::diagnostic::DiagnosticsPlugin::default())
         .add_plugin(bevy::input::InputPlugin::default())
+        .add_plugin(bevy::gilrs::GilrsPlugin::default())
         .add_plugin(bevy::window::WindowPlugin::default())
         .add_plugin(bevy::asset::AssetPlugin::default())
         .add_plugin(bevy::scene::ScenePlugin::default())
@@ -94,9 +165,11 @@ fn main() {
     #[cfg(feature = "web")]
     app.add_plugin(FullViewportPlugin);
 
-    app.add_plugin(CrystalOrbClientPlugin::<PhysicsWorld>::new(
-        settings::NETWORKED_PHYSICS_CONFIG,
-    ));
+    if !is_offline {
+        app.add_plugin(CrystalOrbClientPlugin::<PhysicsWorld>::new(
+            settings::NETWORKED_PHYSICS_CONFIG,
+        ));
+    }
 
     // Order is important.
     // The above plugins provide resources for the plugins below.
@@ -113,13 +186,45 @@ fn main() {
         .add_plugin(TransformTrackingPlugin)
         .add_plugin(Camera2point5dPlugin)
         .add_plugin(SakuraPlugin)
-        .add_system(player_input::player_input_system.system())
-        .add_system(physics_multiplayer_systems::physics_multiplayer_client_sync_system.system())
+        .add_plugin(TouchControlsPlugin)
+        .add_system(player_input::gamepad_connection_system.system())
         .add_system(blinking_eyes::blinking_eyes_system.system())
         .add_system(test_load_progress_system.system())
-        .add_system(update_status_system.system())
         .add_startup_system(setup.system());
 
+    if is_offline {
+        let level = platform
+            .launch_param("level")
+            .and_then(|name| LevelId::from_name(&name))
+            .unwrap_or_default();
+        app.insert_resource(OfflinePhysics::new(level))
+            .add_system(player_input::offline_player_join_system.system())
+            .add_system(player_input::offline_player_input_system.system())
+            .add_system(physics_offline::offline_physics_step_system.system())
+            .add_system(physics_multiplayer_systems::physics_offline_sync_system.system())
+            .add_startup_system(setup_offline.system());
+    } else {
+        app.add_system(player_input::player_join_system.system())
+            .add_system(player_input::player_input_system.system())
+            .add_system(
+                physics_multiplayer_systems::physics_multiplayer_client_sync_system.system(),
+            )
+            .add_system(update_status_system.system())
+            .add_plugin(SpectatorPlugin)
+            .add_plugin(HostMigrationPlugin)
+            .add_startup_system(setup_connection.system());
+
+        #[cfg(feature = "web")]
+        app.add_system(room_full_system.system());
+    }
+
+    app.insert_resource(requested_appearance)
+        .insert_resource(input_bindings)
+        .insert_resource(local_players)
+        .insert_resource(spectator)
+        .insert_resource(local_sessions)
+        .insert_resource(platform);
+
     #[cfg(feature = "debug-fly-camera")]
     app.add_plugin(FlyCameraPlugin);
 
@@ -130,26 +235,29 @@ fn setup_hot_reloading(asset_server: ResMut<AssetServer>) {
     asset_server.watch_for_changes().unwrap();
 }
 
-fn setup(
-    mut commands: Commands,
-    asset_server: ResMut<AssetServer>,
-    audio: Res<Audio>,
-    mut net: ResMut<NetworkResource>,
-) {
-    let host_id = UrlSearchParams::new_with_str(
-        &web_sys::window()
-            .expect("should have global window")
-            .location()
-            .search()
-            .expect("should have search string"),
-    )
-    .expect("should parse valid search params")
-    .get("join")
-    .expect("should have host id");
-    let endpoint_url = format!("https://dango-tribute.up.railway.app/join/{}", host_id);
+fn setup_connection(mut net: ResMut<NetworkResource>, platform: Res<Box<dyn Platform>>) {
+    let host_id = platform.launch_param("join").expect("should have host id");
+
+    #[cfg(feature = "web")]
+    let endpoint_url = SignallingEndpoint::from_platform(&**platform).join_url(&host_id);
+
+    // Note: Natively, we talk UDP straight to a dedicated server rather than going through the
+    // signalling server.
+    #[cfg(feature = "native")]
+    let endpoint_url = host_id;
+
     info!("Starting client - connecting to {}", endpoint_url);
+    platform.report_connection_status(ConnectionStatus::Connecting);
     net.connect(endpoint_url);
+}
 
+fn setup_offline(platform: Res<Box<dyn Platform>>) {
+    info!("Starting client - playing offline");
+    platform.report_connection_status(ConnectionStatus::Offline);
+    platform.report_load_complete("connection");
+}
+
+fn setup(mut commands: Commands, asset_server: ResMut<AssetServer>, audio: Res<Audio>) {
     let bgm = asset_server.load("soundtracks/untitled.ogg");
     audio.play_looped(bgm);
 
@@ -159,7 +267,12 @@ fn setup(
             transform: Transform::from_translation(Vec3::Z * 8.0).looking_at(-Vec3::Z, Vec3::Y),
             ..Default::default()
         })
-        .insert(TransformTrackingFollower);
+        .insert(TransformTrackingFollower)
+        .insert(TransformTrackingZoom {
+            base_z: 8.0,
+            z_per_spread: 1.2,
+            max_z: 30.0,
+        });
 
     #[cfg(feature = "debug-fly-camera")]
     commands.with(FlyCamera::default());
@@ -171,7 +284,7 @@ fn test_load_progress_system(
     _shaders: Res<Assets<Shader>>,
     mut _shader_asset_events: EventReader<AssetEvent<Shader>>,
     asset_server: ResMut<AssetServer>,
-    mut client_connection_events: EventReader<ClientConnectionEvent>,
+    platform: Res<Box<dyn Platform>>,
 ) {
     for _ in audio_asset_events.iter() {
         let mut all_audio_loaded = false;
@@ -184,7 +297,7 @@ fn test_load_progress_system(
             }
         }
         if all_audio_loaded {
-            show_load_complete("audio");
+            platform.report_load_complete("audio");
         }
         break;
     }
@@ -212,40 +325,96 @@ fn test_load_progress_system(
     //    break;
     //}
     // TODO Not all shaders get loaded. To debug. Also - test shader compilation.
-    show_load_complete("shaders");
+    platform.report_load_complete("shaders");
+}
 
-    for client_connection_event in client_connection_events.iter() {
-        if let ClientConnectionEvent::Connected(_) = client_connection_event {
-            show_load_complete("connection");
-            break;
+/// The signalling server turns offers away while the room is full, and the connection never tries
+/// again by itself. So keep asking about the room until it frees up, and then start over.
+#[cfg(feature = "web")]
+fn room_full_system(
+    mut next_check_time: Local<f64>,
+    mut room_status: Local<Option<PendingFetch>>,
+    mut is_room_full: Local<bool>,
+    client: Res<Client<PhysicsWorld>>,
+    time: Res<Time>,
+    platform: Res<Box<dyn Platform>>,
+) {
+    if let Some(status) = room_status.as_ref().and_then(PendingFetch::take) {
+        *room_status = None;
+        match status.as_deref().map(str::trim) {
+            Ok("full") if !*is_room_full => {
+                *is_room_full = true;
+                platform.report_connection_status(ConnectionStatus::RoomFull);
+            }
+            Ok("hosted") if *is_room_full => {
+                info!("A spot freed up in the room - reconnecting");
+                if let Err(err) = platform.restart() {
+                    warn!("Could not reconnect: {}", err);
+                }
+            }
+            _ => {}
         }
     }
-}
 
-fn show_load_complete(component_name: &str) {
-    web_sys::window()
-        .expect("should have global window")
-        .document()
-        .expect("window should have document")
-        .get_element_by_id("loading-screen")
-        .expect("there should be a loading screen")
-        .class_list()
-        .add_1(&format!("load-complete-{}", component_name))
-        .expect("should be able to add a class to span");
+    let now = time.seconds_since_startup();
+    if matches!(client.stage(), ClientStage::Ready(_))
+        || room_status.is_some()
+        || now < *next_check_time
+    {
+        return;
+    }
+    *next_check_time = now + ROOM_CHECK_INTERVAL;
+    if let Some(room_id) = platform.launch_param("join") {
+        let url = SignallingEndpoint::from_platform(&**platform).room_status_url(&room_id);
+        *room_status = Some(platform.start_fetch(&url));
+    }
 }
 
-fn update_status_system(mut client_connection_events: EventReader<ClientConnectionEvent>) {
+fn update_status_system(
+    mut is_queued: Local<bool>,
+    mut is_removed: Local<bool>,
+    mut client_connection_events: EventReader<ClientConnectionEvent>,
+    client: Res<Client<PhysicsWorld>>,
+    local_players: Res<LocalPlayers>,
+    spectator: Res<Spectator>,
+    platform: Res<Box<dyn Platform>>,
+) {
     for client_connection_event in client_connection_events.iter() {
-        let class_name = match client_connection_event {
-            ClientConnectionEvent::Connected(_) => "status-connected",
-            ClientConnectionEvent::Disconnected(_) => "status-disconnected",
-        };
-        web_sys::window()
-            .expect("should have global window")
-            .document()
-            .expect("window should have document")
-            .get_element_by_id("status")
-            .expect("there should be a status span")
-            .set_class_name(class_name);
+        if let ClientConnectionEvent::Connected(_) = client_connection_event {
+            platform.report_load_complete("connection");
+        }
+        *is_queued = false;
+        *is_removed = false;
+        platform.report_connection_status(match client_connection_event {
+            ClientConnectionEvent::Connected(_) => ConnectionStatus::Connected,
+            ClientConnectionEvent::Disconnected(_) => ConnectionStatus::Disconnected,
+        });
+    }
+
+    // Note: The world turns away anyone joining a full room, and the join system keeps asking
+    // until a spot frees up, so there is nothing to do but watch in the meantime.
+    if let ClientStage::Ready(ready_client) = client.stage() {
+        let display_state: &PhysicsDisplayState = ready_client.display_state();
+        if display_state.is_turned_away(ready_client.client_id()) {
+            if !*is_removed {
+                *is_removed = true;
+                platform.report_connection_status(ConnectionStatus::Removed);
+            }
+            return;
+        }
+        let is_waiting = !spectator.is_spectating
+            && display_state.is_full()
+            && local_players
+                .player_ids(ready_client.client_id())
+                .iter()
+                .any(|player_id| !display_state.players().contains_key(player_id));
+        if is_waiting != *is_queued {
+            *is_queued = is_waiting;
+            platform.report_connection_status(if is_waiting {
+                ConnectionStatus::Full
+            } else {
+                ConnectionStatus::Connected
+            });
+        }
     }
 }
@@ -0,0 +1,64 @@
+use bevy::prelude::*;
+use shared::{
+    platform::Platform,
+    player::PlayerId,
+    session::{LocalSession, LocalSessions, SessionSecret},
+};
+
+const SESSIONS_KEY: &str = "dango-sessions";
+
+/// Picks up the sessions from before the last reload, so that reloading the page after dropping
+/// out reclaims the same dangos, and remembers fresh ones for next time. These stay with the tab,
+/// since a second tab would otherwise take the first one's dangos away from it.
+pub fn load_local_sessions(platform: &dyn Platform) -> LocalSessions {
+    let mut sessions = LocalSessions(
+        platform
+            .load_tab_setting(SESSIONS_KEY)
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(|secret| LocalSession {
+                reclaim: secret.parse().ok().map(SessionSecret),
+                next: SessionSecret::random(),
+            })
+            .collect(),
+    );
+    // Note: Start sessions for every possible local player up front, so they all get remembered.
+    sessions.get(PlayerId::MAX_LOCAL_PLAYERS - 1);
+
+    let value: Vec<String> = sessions
+        .0
+        .iter()
+        .map(|session| session.next.0.to_string())
+        .collect();
+    if let Err(err) = platform.save_tab_setting(SESSIONS_KEY, &value.join(" ")) {
+        warn!("Could not remember session: {}", err);
+    }
+
+    sessions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::platform::TestPlatform;
+
+    #[test]
+    fn reloading_reclaims_the_previous_sessions() {
+        let platform = TestPlatform::default();
+        let first = load_local_sessions(&platform);
+        let second = load_local_sessions(&platform);
+        for (first, second) in first.0.iter().zip(second.0.iter()) {
+            assert_eq!(second.reclaim, Some(first.next));
+        }
+    }
+
+    #[test]
+    fn other_tabs_start_their_own_sessions() {
+        let platform = TestPlatform::default();
+        let first = load_local_sessions(&platform.new_tab());
+        let second = load_local_sessions(&platform.new_tab());
+        for (first, second) in first.0.iter().zip(second.0.iter()) {
+            assert_ne!(second.reclaim, Some(first.next));
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use bevy::prelude::*;
+use bevy_prototype_transform_tracker::TransformTrackingFollower;
+use crystalorb_bevy_networking_turbulence::crystalorb::client::{
+    stage::Stage as ClientStage, Client,
+};
+use shared::{
+    physics_multiplayer::{PhysicsDisplayState, PhysicsWorld},
+    player::PlayerId,
+    player_input::Spectator,
+};
+
+/// How fast the free camera pans, in units per second for every unit it is zoomed out.
+const FREE_CAMERA_SPEED: f32 = 1.0;
+const FREE_CAMERA_ZOOM_SPEED: f32 = 10.0;
+const FREE_CAMERA_MIN_Z: f32 = 4.0;
+const FREE_CAMERA_MAX_Z: f32 = 40.0;
+
+/// Lets spectators look around. Tab cycles through following each player and moving the camera
+/// freely, the arrow keys or WASD pan the free camera, minus and equals zoom out and in, and enter
+/// joins the game.
+pub struct SpectatorPlugin;
+
+impl Plugin for SpectatorPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(spectator_system.system());
+    }
+}
+
+fn spectator_system(
+    mut spectator: ResMut<Spectator>,
+    keys: Res<Input<KeyCode>>,
+    time: Res<Time>,
+    client: Res<Client<PhysicsWorld>>,
+    mut camera_query: Query<&mut Transform, With<TransformTrackingFollower>>,
+) {
+    if !spectator.is_spectating {
+        return;
+    }
+    if keys.just_pressed(KeyCode::Return) {
+        info!("Joining the game");
+        spectator.is_spectating = false;
+        spectator.following = None;
+        return;
+    }
+
+    let mut player_ids: Vec<PlayerId> = match client.stage() {
+        ClientStage::Ready(ready_client) => {
+            let display_state: &PhysicsDisplayState = ready_client.display_state();
+            display_state.players().keys().copied().collect()
+        }
+        _ => Vec::new(),
+    };
+    player_ids.sort();
+
+    // Note: Fall back to the free camera when whoever was being followed leaves.
+    if let Some(following) = spectator.following {
+        if !player_ids.contains(&following) {
+            spectator.following = None;
+        }
+    }
+    if keys.just_pressed(KeyCode::Tab) {
+        spectator.following = match spectator.following {
+            None => player_ids.first().copied(),
+            Some(following) => player_ids
+                .iter()
+                .copied()
+                .find(|player_id| *player_id > following),
+        };
+        match spectator.following {
+            Some(following) => info!("Following player {:?}", following),
+            None => info!("Moving the camera freely"),
+        }
+    }
+    if spectator.following.is_some() {
+        return;
+    }
+
+    let axis = |negative: &[KeyCode], positive: &[KeyCode]| {
+        let is_pressed =
+            |keys_to_check: &[KeyCode]| keys_to_check.iter().any(|key| keys.pressed(*key));
+        is_pressed(positive) as i8 as f32 - is_pressed(negative) as i8 as f32
+    };
+    let pan = Vec2::new(
+        axis(&[KeyCode::Left, KeyCode::A], &[KeyCode::Right, KeyCode::D]),
+        axis(&[KeyCode::Down, KeyCode::S], &[KeyCode::Up, KeyCode::W]),
+    );
+    let zoom = axis(&[KeyCode::Equals], &[KeyCode::Minus]);
+    for mut transform in camera_query.iter_mut() {
+        let speed = FREE_CAMERA_SPEED * transform.translation.z * time.delta_seconds();
+        transform.translation.x += pan.x * speed;
+        transform.translation.y += pan.y * speed;
+        transform.translation.z = (transform.translation.z
+            + zoom * FREE_CAMERA_ZOOM_SPEED * time.delta_seconds())
+        .max(FREE_CAMERA_MIN_Z)
+        .min(FREE_CAMERA_MAX_Z);
+    }
+}
@@ -0,0 +1,273 @@
+use bevy::{input::touch::Touches, prelude::*};
+use shared::{platform::Platform, player::PlayerInputState, player_input::TouchInputState};
+
+const MARGIN: f32 = 32.0;
+const JOYSTICK_SIZE: f32 = 160.0;
+const KNOB_SIZE: f32 = 64.0;
+const JUMP_BUTTON_SIZE: f32 = 96.0;
+const ROLL_BUTTON_SIZE: f32 = 72.0;
+
+/// How far from the middle of the joystick a finger needs to be before the dango moves, as a
+/// fraction of the joystick's radius.
+const JOYSTICK_DEADZONE: f32 = 0.15;
+
+/// The joystick is rounded to this many steps in each direction, so that a shaky finger doesn't
+/// issue a new command every frame.
+const JOYSTICK_STEPS: f32 = 8.0;
+
+/// On-screen controls for phones and tablets: a joystick in the bottom left corner, and jump and
+/// roll buttons in the bottom right. They only show up once the platform says it has a touch
+/// screen, or the screen gets touched.
+pub struct TouchControlsPlugin;
+
+impl Plugin for TouchControlsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<TouchInputState>()
+            .add_system(touch_detection_system.system())
+            .add_system(touch_controls_system.system());
+    }
+}
+
+pub struct TouchJoystick;
+pub struct TouchJoystickKnob;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TouchButton {
+    Jump,
+    Roll,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pointer {
+    Touch(u64),
+    Mouse,
+}
+
+/// Which pointer is holding on to the joystick, if any.
+#[derive(Default)]
+struct JoystickGrab(Option<Pointer>);
+
+fn touch_detection_system(
+    mut is_enabled: Local<bool>,
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    touches: Res<Touches>,
+    platform: Res<Box<dyn Platform>>,
+) {
+    if *is_enabled {
+        return;
+    }
+    if platform.has_touch_screen() || touches.iter_just_pressed().next().is_some() {
+        info!("Showing touch controls");
+        *is_enabled = true;
+        spawn_touch_controls(&mut commands, &mut materials);
+    }
+}
+
+fn spawn_touch_controls(commands: &mut Commands, materials: &mut Assets<ColorMaterial>) {
+    let clear = materials.add(Color::rgba(0.0, 0.0, 0.0, 0.0).into());
+    let joystick = materials.add(Color::rgba(1.0, 1.0, 1.0, 0.2).into());
+    let knob = materials.add(Color::rgba(1.0, 1.0, 1.0, 0.5).into());
+    let jump = materials.add(Color::rgba(1.0, 0.6, 0.7, 0.5).into());
+    let roll = materials.add(Color::rgba(0.6, 0.9, 0.7, 0.5).into());
+
+    commands.spawn_bundle(UiCameraBundle::default());
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                ..Default::default()
+            },
+            material: clear,
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(JOYSTICK_SIZE), Val::Px(JOYSTICK_SIZE)),
+                        position_type: PositionType::Absolute,
+                        position: Rect {
+                            left: Val::Px(MARGIN),
+                            bottom: Val::Px(MARGIN),
+                            ..Default::default()
+                        },
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    material: joystick,
+                    ..Default::default()
+                })
+                .insert(TouchJoystick)
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(NodeBundle {
+                            style: Style {
+                                size: Size::new(Val::Px(KNOB_SIZE), Val::Px(KNOB_SIZE)),
+                                ..Default::default()
+                            },
+                            material: knob,
+                            ..Default::default()
+                        })
+                        .insert(TouchJoystickKnob);
+                });
+
+            for (button, size, right, bottom, material) in [
+                (TouchButton::Jump, JUMP_BUTTON_SIZE, MARGIN, MARGIN, jump),
+                (
+                    TouchButton::Roll,
+                    ROLL_BUTTON_SIZE,
+                    MARGIN * 2.0 + JUMP_BUTTON_SIZE,
+                    MARGIN,
+                    roll,
+                ),
+            ]
+            .iter()
+            .cloned()
+            {
+                parent
+                    .spawn_bundle(NodeBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(size), Val::Px(size)),
+                            position_type: PositionType::Absolute,
+                            position: Rect {
+                                right: Val::Px(right),
+                                bottom: Val::Px(bottom),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        },
+                        material,
+                        ..Default::default()
+                    })
+                    .insert(button);
+            }
+        });
+}
+
+/// Works out what the fingers on the screen are pressing. A mouse counts as a finger too, so that
+/// the controls can be tried out on a desktop.
+fn touch_controls_system(
+    mut joystick_grab: Local<JoystickGrab>,
+    mut platform_touch_ids: Local<Vec<u64>>,
+    touches: Res<Touches>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    platform: Res<Box<dyn Platform>>,
+    mut touch_input: ResMut<TouchInputState>,
+    joystick_query: Query<(&Node, &GlobalTransform), With<TouchJoystick>>,
+    mut knob_query: Query<&mut Style, With<TouchJoystickKnob>>,
+    button_query: Query<(&TouchButton, &Node, &GlobalTransform)>,
+) {
+    let (joystick_node, joystick_transform) = match joystick_query.iter().next() {
+        Some(joystick) => joystick,
+        None => return,
+    };
+
+    // Note: bevy's winit backend only flips touches to count from the bottom of the window on
+    // phones, whereas the UI and the cursor count from the bottom everywhere.
+    let window_height = windows.get_primary().map_or(0.0, |window| window.height());
+    let from_bottom = |position: Vec2| {
+        if cfg!(any(target_os = "android", target_os = "ios")) {
+            position
+        } else {
+            Vec2::new(position.x, window_height - position.y)
+        }
+    };
+    let mut pointers: Vec<(Pointer, Vec2, bool)> = match platform.touches() {
+        Some(platform_touches) => {
+            let pointers = platform_touches
+                .iter()
+                .map(|(id, [x, y])| {
+                    (
+                        Pointer::Touch(*id),
+                        Vec2::new(*x, *y),
+                        !platform_touch_ids.contains(id),
+                    )
+                })
+                .collect();
+            *platform_touch_ids = platform_touches.iter().map(|(id, _)| *id).collect();
+            pointers
+        }
+        None => touches
+            .iter()
+            .map(|touch| {
+                (
+                    Pointer::Touch(touch.id()),
+                    from_bottom(touch.position()),
+                    touches.just_pressed(touch.id()),
+                )
+            })
+            .collect(),
+    };
+    // Note: Browsers pass the first finger on as a mouse as well, so only go by the mouse when
+    // there are no fingers on the screen.
+    if pointers.is_empty() && mouse_buttons.pressed(MouseButton::Left) {
+        if let Some(cursor_position) = windows
+            .get_primary()
+            .and_then(|window| window.cursor_position())
+        {
+            pointers.push((
+                Pointer::Mouse,
+                cursor_position,
+                mouse_buttons.just_pressed(MouseButton::Left),
+            ));
+        }
+    }
+
+    // Note: The joystick only follows a finger that started on it, and keeps following it after
+    // it slides off, so that other fingers can press buttons in the meantime.
+    let is_still_grabbed = joystick_grab.0.map_or(false, |grab| {
+        pointers.iter().any(|(pointer, ..)| *pointer == grab)
+    });
+    if !is_still_grabbed {
+        joystick_grab.0 = pointers
+            .iter()
+            .find(|(_, position, just_pressed)| {
+                *just_pressed && contains(joystick_node, joystick_transform, *position)
+            })
+            .map(|(pointer, ..)| *pointer);
+    }
+    let joystick_offset = joystick_grab
+        .0
+        .and_then(|grab| pointers.iter().find(|(pointer, ..)| *pointer == grab))
+        .map_or(0.0, |(_, position, _)| {
+            let radius = joystick_node.size.x / 2.0;
+            ((position.x - joystick_transform.translation.x) / radius)
+                .max(-1.0)
+                .min(1.0)
+        });
+    let horizontal = if joystick_offset.abs() > JOYSTICK_DEADZONE {
+        let beyond_deadzone =
+            (joystick_offset.abs() - JOYSTICK_DEADZONE) / (1.0 - JOYSTICK_DEADZONE);
+        joystick_offset.signum() * (beyond_deadzone * JOYSTICK_STEPS).round() / JOYSTICK_STEPS
+    } else {
+        0.0
+    };
+    for mut knob_style in knob_query.iter_mut() {
+        knob_style.position.left =
+            Val::Px(joystick_offset * (joystick_node.size.x - KNOB_SIZE) / 2.0);
+    }
+
+    let is_pressed = |button: TouchButton| {
+        button_query.iter().any(|(other_button, node, transform)| {
+            *other_button == button
+                && pointers.iter().any(|(pointer, position, _)| {
+                    Some(*pointer) != joystick_grab.0 && contains(node, transform, *position)
+                })
+        })
+    };
+    touch_input.0 = PlayerInputState {
+        horizontal: PlayerInputState::quantise_horizontal(horizontal),
+        jump: is_pressed(TouchButton::Jump),
+        roll: is_pressed(TouchButton::Roll),
+    };
+}
+
+/// UI nodes are positioned by their centre, in pixels from the bottom left of the window.
+fn contains(node: &Node, transform: &GlobalTransform, position: Vec2) -> bool {
+    let offset = position - transform.translation.truncate();
+    offset.x.abs() <= node.size.x / 2.0 && offset.y.abs() <= node.size.y / 2.0
+}
@@ -4,6 +4,21 @@ pub struct TransformTrackingPlugin;
 pub struct TransformTrackingTarget;
 pub struct TransformTrackingFollower;
 
+/// Lets a follower back away along z when there are several targets, so that all of them stay
+/// in view.
+pub struct TransformTrackingZoom {
+    /// How far along z the follower sits when the targets are all in one place.
+    pub base_z: f32,
+
+    /// How much further back to go for every unit that the targets are spread apart.
+    pub z_per_spread: f32,
+
+    /// Never back away further than this.
+    pub max_z: f32,
+}
+
+const TRACKING_SPEED: f32 = 0.1;
+
 impl Plugin for TransformTrackingPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.add_system(transform_tracking_system.system());
@@ -13,16 +28,34 @@ impl Plugin for TransformTrackingPlugin {
 pub fn transform_tracking_system(
     target_query: Query<(&TransformTrackingTarget, &Transform)>,
     mut follower_query: Query<
-        (&TransformTrackingFollower, &mut Transform),
+        (
+            &TransformTrackingFollower,
+            &mut Transform,
+            Option<&TransformTrackingZoom>,
+        ),
         Without<TransformTrackingTarget>,
     >,
 ) {
-    for (_, target_transform) in target_query.iter() {
-        let target_pos = target_transform.translation;
-        for (_, mut follower_transform) in follower_query.iter_mut() {
-            let follower_pos = &mut follower_transform.translation;
-            follower_pos.x += (target_pos.x - follower_pos.x) * 0.1;
-            follower_pos.y += (target_pos.y - follower_pos.y) * 0.1;
+    let mut targets = target_query
+        .iter()
+        .map(|(_, transform)| transform.translation);
+    let first_target = match targets.next() {
+        Some(first_target) => first_target,
+        None => return,
+    };
+    let (min, max) = targets.fold((first_target, first_target), |(min, max), target| {
+        (min.min(target), max.max(target))
+    });
+    let center = (min + max) / 2.0;
+    let spread = (max.x - min.x).max(max.y - min.y);
+
+    for (_, mut follower_transform, zoom) in follower_query.iter_mut() {
+        let follower_pos = &mut follower_transform.translation;
+        follower_pos.x += (center.x - follower_pos.x) * TRACKING_SPEED;
+        follower_pos.y += (center.y - follower_pos.y) * TRACKING_SPEED;
+        if let Some(zoom) = zoom {
+            let target_z = (zoom.base_z + spread * zoom.z_per_spread).min(zoom.max_z);
+            follower_pos.z += (target_z - follower_pos.z) * TRACKING_SPEED;
         }
     }
 }
@@ -16,7 +16,9 @@ use std::{
 
 use naia_client_socket::ClientSocket;
 // #[cfg(not(target_arch = "wasm32"))]
-use naia_server_socket::{MessageSender as ServerSender, NextEvent, ServerSocket};
+use naia_server_socket::{
+    MessageSender as ServerSender, NextEvent, ServerSocket, ServerSocketTrait,
+};
 
 pub use naia_client_socket::LinkConditionerConfig;
 // #[cfg(not(target_arch = "wasm32"))]
@@ -33,14 +35,21 @@ pub use turbulence::{
     reliable_channel::Settings as ReliableChannelSettings,
 };
 
+#[cfg(feature = "use-webrtc")]
 use wasm_bindgen_futures::spawn_local;
 
 mod channels;
+mod loopback;
+mod stats;
 mod transport;
 use self::{
     channels::{SimpleBufferPool, TaskPoolRuntime},
+    loopback::{LoopbackConnection, LoopbackListener},
+    stats::{Heartbeat, HEARTBEAT_MESSAGE_SETTINGS},
     transport::MultiplexedPacket,
 };
+pub use loopback::LoopbackNetwork;
+pub use stats::{ConnectionStats, TrafficStats};
 pub use transport::{Connection, ConnectionChannelsBuilder, Packet};
 
 pub type ConnectionHandle = u32;
@@ -75,6 +84,7 @@ pub struct NetworkResource {
     pending_disconnections: Arc<Mutex<Vec<Option<SocketAddr>>>>,
     connection_sequence: atomic::AtomicU32,
     pub connections: HashMap<ConnectionHandle, Box<dyn Connection>>,
+    connection_stats: HashMap<ConnectionHandle, ConnectionStats>,
 
     // #[cfg(not(target_arch = "wasm32"))]
     listeners: Arc<Mutex<Vec<ServerListener>>>,
@@ -98,78 +108,31 @@ struct ServerListener {
     sender: ServerSender,
 }
 
-#[derive(Debug)]
-pub enum NetworkEvent {
-    Connected(ConnectionHandle),
-    Disconnected(ConnectionHandle),
-    Packet(ConnectionHandle, Packet),
-    Hosted(String),
-    Error(ConnectionHandle, NetworkError),
-}
-
-#[derive(Debug)]
-pub enum NetworkError {
-    TurbulenceChannelError(IncomingTrySendError<MultiplexedPacket>),
-    IoError(Box<dyn Error + Sync + Send>),
-    Disconnected,
+/// Everything a listening socket needs to hand new connections over to the [`NetworkResource`].
+struct ListenerState {
+    server_channels: Arc<RwLock<HashMap<SocketAddr, Sender<Result<Packet, NetworkError>>>>>,
+    pending_connections: Arc<Mutex<Vec<Box<dyn Connection>>>>,
+    pending_disconnections: Arc<Mutex<Vec<Option<SocketAddr>>>>,
+    task_pool: TaskPool,
+    listeners: Arc<Mutex<Vec<ServerListener>>>,
 }
 
-#[cfg(target_arch = "wasm32")]
-unsafe impl Send for NetworkResource {}
-
-#[cfg(target_arch = "wasm32")]
-unsafe impl Sync for NetworkResource {}
-
-impl NetworkResource {
-    pub fn new(task_pool: TaskPool, link_conditioner: Option<LinkConditionerConfig>) -> Self {
-        let runtime = TaskPoolRuntime::new(task_pool.clone());
-        let packet_pool =
-            MuxPacketPool::new(BufferPacketPool::new(SimpleBufferPool(MAX_PACKET_LEN)));
-
-        NetworkResource {
+impl ListenerState {
+    fn serve(self, mut server_socket: Box<dyn ServerSocketTrait>) {
+        let ListenerState {
+            server_channels,
+            pending_connections,
+            pending_disconnections,
             task_pool,
-            connections: HashMap::new(),
-            connection_sequence: atomic::AtomicU32::new(0),
-            pending_connections: Arc::new(Mutex::new(Vec::new())),
-            pending_disconnections: Arc::new(Mutex::new(Vec::new())),
-            // #[cfg(not(target_arch = "wasm32"))]
-            listeners: Arc::new(Mutex::new(Vec::new())),
-            // #[cfg(not(target_arch = "wasm32"))]
-            server_channels: Arc::new(RwLock::new(HashMap::new())),
-            runtime,
-            packet_pool,
-            channels_builder_fn: None,
+            listeners,
+        } = self;
 
-            link_conditioner,
-            endpoint_id: Arc::new(Mutex::new(None)),
-        }
-    }
-
-    pub fn listen(&mut self, signalling_server_url: String) {
-        let server_channels = self.server_channels.clone();
-        let pending_connections = self.pending_connections.clone();
-        let pending_disconnections = self.pending_disconnections.clone();
-        let task_pool = self.task_pool.clone();
-        let listeners = self.listeners.clone();
-        let endpoint_id = self.endpoint_id.clone();
-
-        let link_conditioner = self.link_conditioner.take();
+        let sender = server_socket.get_sender();
 
-        spawn_local(async move {
-            let mut server_socket = {
-                let (id, socket) = ServerSocket::listen(signalling_server_url).await;
-                endpoint_id.lock().unwrap().replace(id);
-
-                if let Some(ref conditioner) = link_conditioner {
-                    socket.with_link_conditioner(conditioner)
-                } else {
-                    socket
-                }
-            };
-            let sender = server_socket.get_sender();
-
-            let task_pool_clone = task_pool.clone();
-            let _receiver_task = task_pool.spawn(async move {
+        let task_pool_clone = task_pool.clone();
+        // Note: The receiver task needs to outlive this function, otherwise it gets cancelled.
+        task_pool
+            .spawn(async move {
                 loop {
                     match server_socket.receive().await {
                         NextEvent::ReceivedPacket(Ok(packet)) => {
@@ -249,14 +212,165 @@ impl NetworkResource {
                         }
                     }
                 }
-            });
+            })
+            .detach();
 
-            listeners.lock().unwrap().push(ServerListener {
-                //receiver_task,
-                sender,
-            });
+        listeners.lock().unwrap().push(ServerListener {
+            //receiver_task,
+            sender,
         });
     }
+}
+
+#[derive(Debug)]
+pub enum NetworkEvent {
+    Connected(ConnectionHandle),
+    Disconnected(ConnectionHandle),
+    Packet(ConnectionHandle, Packet),
+    Hosted(String),
+    Error(ConnectionHandle, NetworkError),
+}
+
+#[derive(Debug)]
+pub enum NetworkError {
+    TurbulenceChannelError(IncomingTrySendError<MultiplexedPacket>),
+    IoError(Box<dyn Error + Sync + Send>),
+    Disconnected,
+}
+
+#[cfg(target_arch = "wasm32")]
+unsafe impl Send for NetworkResource {}
+
+#[cfg(target_arch = "wasm32")]
+unsafe impl Sync for NetworkResource {}
+
+impl NetworkResource {
+    pub fn new(task_pool: TaskPool, link_conditioner: Option<LinkConditionerConfig>) -> Self {
+        let runtime = TaskPoolRuntime::new(task_pool.clone());
+        let packet_pool =
+            MuxPacketPool::new(BufferPacketPool::new(SimpleBufferPool(MAX_PACKET_LEN)));
+
+        NetworkResource {
+            task_pool,
+            connections: HashMap::new(),
+            connection_stats: HashMap::new(),
+            connection_sequence: atomic::AtomicU32::new(0),
+            pending_connections: Arc::new(Mutex::new(Vec::new())),
+            pending_disconnections: Arc::new(Mutex::new(Vec::new())),
+            // #[cfg(not(target_arch = "wasm32"))]
+            listeners: Arc::new(Mutex::new(Vec::new())),
+            // #[cfg(not(target_arch = "wasm32"))]
+            server_channels: Arc::new(RwLock::new(HashMap::new())),
+            runtime,
+            packet_pool,
+            channels_builder_fn: None,
+
+            link_conditioner,
+            endpoint_id: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Listens for connections that get brokered by the signalling server, and reports the
+    /// endpoint id that clients should join with through [`NetworkEvent::Hosted`].
+    #[cfg(feature = "use-webrtc")]
+    pub fn listen(&mut self, signalling_server_url: String) {
+        let listener = self.listener_state();
+        let endpoint_id = self.endpoint_id.clone();
+        let link_conditioner = self.link_conditioner.take();
+
+        spawn_local(async move {
+            let server_socket = {
+                let (id, socket) = ServerSocket::listen(signalling_server_url).await;
+                endpoint_id.lock().unwrap().replace(id);
+
+                if let Some(ref conditioner) = link_conditioner {
+                    socket.with_link_conditioner(conditioner)
+                } else {
+                    socket
+                }
+            };
+            listener.serve(server_socket);
+        });
+    }
+
+    /// Listens for UDP packets on the given address. The address is reported back through
+    /// [`NetworkEvent::Hosted`] once the socket is bound.
+    #[cfg(not(feature = "use-webrtc"))]
+    pub fn listen(&mut self, socket_address: SocketAddr) {
+        let listener = self.listener_state();
+        let server_socket = futures_lite::future::block_on(ServerSocket::listen(
+            socket_address,
+            socket_address,
+            socket_address,
+        ));
+        let server_socket = if let Some(ref conditioner) = self.link_conditioner {
+            server_socket.with_link_conditioner(conditioner)
+        } else {
+            server_socket
+        };
+        self.endpoint_id
+            .lock()
+            .unwrap()
+            .replace(socket_address.to_string());
+        listener.serve(server_socket);
+    }
+
+    /// Listens for clients on the given [`LoopbackNetwork`] rather than on a socket. The name is
+    /// what clients pass to [`NetworkResource::connect_loopback`], and is reported back through
+    /// [`NetworkEvent::Hosted`].
+    pub fn listen_loopback(&mut self, network: &LoopbackNetwork, name: &str) {
+        network.add_listener(
+            name.to_string(),
+            LoopbackListener {
+                task_pool: self.task_pool.clone(),
+                pending_connections: self.pending_connections.clone(),
+                link_conditioner: self.link_conditioner.clone(),
+            },
+        );
+        self.endpoint_id.lock().unwrap().replace(name.to_string());
+    }
+
+    /// Connects to a server that is listening on the given [`LoopbackNetwork`]. Both ends apply
+    /// their own link conditioner to the packets they receive.
+    pub fn connect_loopback(
+        &mut self,
+        network: &LoopbackNetwork,
+        name: &str,
+    ) -> Result<(), NetworkError> {
+        let listener = network.listener(name).ok_or_else(|| {
+            NetworkError::IoError(Box::new(std::io::Error::new(
+                std::io::ErrorKind::ConnectionRefused,
+                format!("Nobody is listening on {}", name),
+            )))
+        })?;
+        let (client_connection, server_connection) = LoopbackConnection::pair(
+            self.task_pool.clone(),
+            self.link_conditioner.clone(),
+            listener.task_pool,
+            listener.link_conditioner,
+            network.next_client_address(),
+        );
+        listener
+            .pending_connections
+            .lock()
+            .unwrap()
+            .push(Box::new(server_connection));
+        self.pending_connections
+            .lock()
+            .unwrap()
+            .push(Box::new(client_connection));
+        Ok(())
+    }
+
+    fn listener_state(&self) -> ListenerState {
+        ListenerState {
+            server_channels: self.server_channels.clone(),
+            pending_connections: self.pending_connections.clone(),
+            pending_disconnections: self.pending_disconnections.clone(),
+            task_pool: self.task_pool.clone(),
+            listeners: self.listeners.clone(),
+        }
+    }
 
     pub fn connect(&mut self, socket_address: String) {
         let mut client_socket = {
@@ -352,6 +466,45 @@ impl NetworkResource {
             None => None,
         }
     }
+
+    /// What has come in through the given connection so far, and how long it takes to get there
+    /// and back.
+    pub fn connection_stats(&self, handle: ConnectionHandle) -> Option<&ConnectionStats> {
+        self.connection_stats.get(&handle)
+    }
+
+    /// Answers the other end's heartbeats, and sends our own every now and then to keep the
+    /// round trip times up to date.
+    fn exchange_heartbeats(&mut self) {
+        let now = instant::Instant::now();
+        let NetworkResource {
+            connections,
+            connection_stats,
+            ..
+        } = self;
+        for (handle, connection) in connections.iter_mut() {
+            let stats = connection_stats.entry(*handle).or_default();
+            if !stats.has_heartbeat_channel {
+                continue;
+            }
+            let channels = match connection.channels() {
+                Some(channels) => channels,
+                None => continue,
+            };
+            while let Some(heartbeat) = channels.recv::<Heartbeat>() {
+                match heartbeat {
+                    Heartbeat::Ping(sequence) => {
+                        channels.send(Heartbeat::Pong(sequence));
+                    }
+                    Heartbeat::Pong(sequence) => stats.receive_pong(sequence, now),
+                }
+            }
+            if let Some(sequence) = stats.next_ping(now) {
+                channels.send(Heartbeat::Ping(sequence));
+            }
+            channels.flush::<Heartbeat>();
+        }
+    }
 }
 
 pub fn receive_packets(
@@ -368,14 +521,22 @@ pub fn receive_packets(
         let handle: ConnectionHandle = net
             .connection_sequence
             .fetch_add(1, atomic::Ordering::Relaxed);
+        let mut stats = ConnectionStats::default();
         if let Some(channels_builder_fn) = net.channels_builder_fn.as_ref() {
             conn.build_channels(
-                channels_builder_fn,
+                &|builder: &mut ConnectionChannelsBuilder| {
+                    channels_builder_fn(builder);
+                    builder
+                        .register::<Heartbeat>(HEARTBEAT_MESSAGE_SETTINGS)
+                        .expect("heartbeat channel should be free");
+                },
                 net.runtime.clone(),
                 net.packet_pool.clone(),
             );
+            stats.has_heartbeat_channel = true;
         }
         net.connections.insert(handle, conn);
+        net.connection_stats.insert(handle, stats);
         network_events.send(NetworkEvent::Connected(handle));
     }
 
@@ -407,14 +568,25 @@ pub fn receive_packets(
             handle
         );
         net.connections.remove(&handle);
+        net.connection_stats.remove(&handle);
         network_events.send(NetworkEvent::Disconnected(handle));
     }
 
     let packet_pool = net.packet_pool.clone();
-    for (handle, connection) in net.connections.iter_mut() {
+    let NetworkResource {
+        connections,
+        connection_stats,
+        ..
+    } = &mut *net;
+    for (handle, connection) in connections.iter_mut() {
         while let Some(result) = connection.receive() {
             match result {
                 Ok(packet) => {
+                    connection_stats
+                        .entry(*handle)
+                        .or_default()
+                        .record_received(&packet);
+
                     let message = String::from_utf8_lossy(&packet);
                     log::debug!("Received on [{}] {} RAW: {}", handle, packet.len(), message);
                     if let Some(channels_rx) = connection.channels_rx() {
@@ -446,4 +618,6 @@ pub fn receive_packets(
             }
         }
     }
+
+    net.exchange_heartbeats();
 }
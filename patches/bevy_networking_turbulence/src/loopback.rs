@@ -0,0 +1,205 @@
+use bevy_tasks::TaskPool;
+use crossbeam_channel::{unbounded, Receiver, Sender, TryRecvError};
+use std::{
+    collections::HashMap,
+    error::Error,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU16, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use naia_socket_shared::{link_condition_logic, LinkConditionerConfig, TimeQueue};
+
+use turbulence::{
+    buffer::BufferPacketPool,
+    message_channels::{MessageChannels, MessageChannelsBuilder},
+    packet_multiplexer::{IncomingMultiplexedPackets, MuxPacketPool, PacketMultiplexer},
+};
+
+use futures_lite::StreamExt;
+
+use super::{
+    channels::{SimpleBufferPool, TaskPoolRuntime},
+    transport::{Connection, ConnectionChannelsBuilder, MultiplexedPacket, Packet},
+    NetworkError,
+};
+
+/// An in-process stand-in for the network, so that a server [`NetworkResource`] and any number of
+/// client [`NetworkResource`]s can talk to each other without any sockets, e.g. inside a test.
+/// Clones share the same set of listeners.
+///
+/// [`NetworkResource`]: crate::NetworkResource
+#[derive(Clone, Default)]
+pub struct LoopbackNetwork {
+    listeners: Arc<Mutex<HashMap<String, LoopbackListener>>>,
+    connection_sequence: Arc<AtomicU16>,
+}
+
+#[derive(Clone)]
+pub(crate) struct LoopbackListener {
+    pub(crate) task_pool: TaskPool,
+    pub(crate) pending_connections: Arc<Mutex<Vec<Box<dyn Connection>>>>,
+    pub(crate) link_conditioner: Option<LinkConditionerConfig>,
+}
+
+impl LoopbackNetwork {
+    pub(crate) fn add_listener(&self, name: String, listener: LoopbackListener) {
+        self.listeners.lock().unwrap().insert(name, listener);
+    }
+
+    pub(crate) fn listener(&self, name: &str) -> Option<LoopbackListener> {
+        self.listeners.lock().unwrap().get(name).cloned()
+    }
+
+    pub(crate) fn next_client_address(&self) -> SocketAddr {
+        // Note: Nothing ever gets sent to this address. It only tells the server's connections
+        // apart.
+        let sequence = self.connection_sequence.fetch_add(1, Ordering::Relaxed);
+        SocketAddr::from(([127, 0, 0, 1], 1024u16.wrapping_add(sequence)))
+    }
+}
+
+/// One end of a pair of channels. Packets get delayed or dropped on the way in, the same way the
+/// naia sockets apply their link conditioner to incoming packets.
+pub(crate) struct LoopbackConnection {
+    task_pool: TaskPool,
+
+    sender: Sender<Packet>,
+    receiver: Receiver<Packet>,
+    remote_address: Option<SocketAddr>,
+
+    link_conditioner: Option<LinkConditionerConfig>,
+    time_queue: TimeQueue<Packet>,
+
+    channels: Option<MessageChannels>,
+    channels_rx: Option<IncomingMultiplexedPackets<MultiplexedPacket>>,
+
+    disconnected: bool,
+}
+
+impl LoopbackConnection {
+    /// Creates both ends of a connection. The first one belongs to the client, and the second one
+    /// to the server.
+    pub(crate) fn pair(
+        client_task_pool: TaskPool,
+        client_link_conditioner: Option<LinkConditionerConfig>,
+        server_task_pool: TaskPool,
+        server_link_conditioner: Option<LinkConditionerConfig>,
+        client_address: SocketAddr,
+    ) -> (Self, Self) {
+        let (client_tx, server_rx) = unbounded();
+        let (server_tx, client_rx) = unbounded();
+        (
+            LoopbackConnection::new(
+                client_task_pool,
+                client_tx,
+                client_rx,
+                None,
+                client_link_conditioner,
+            ),
+            LoopbackConnection::new(
+                server_task_pool,
+                server_tx,
+                server_rx,
+                Some(client_address),
+                server_link_conditioner,
+            ),
+        )
+    }
+
+    fn new(
+        task_pool: TaskPool,
+        sender: Sender<Packet>,
+        receiver: Receiver<Packet>,
+        remote_address: Option<SocketAddr>,
+        link_conditioner: Option<LinkConditionerConfig>,
+    ) -> Self {
+        LoopbackConnection {
+            task_pool,
+            sender,
+            receiver,
+            remote_address,
+            link_conditioner,
+            time_queue: TimeQueue::new(),
+            channels: None,
+            channels_rx: None,
+            disconnected: false,
+        }
+    }
+}
+
+impl Connection for LoopbackConnection {
+    fn disconnected(&self) -> bool {
+        self.disconnected
+    }
+
+    fn remote_address(&self) -> Option<SocketAddr> {
+        self.remote_address
+    }
+
+    fn send(&mut self, payload: Packet) -> Result<(), Box<dyn Error + Sync + Send>> {
+        self.sender
+            .send(payload)
+            .map_err(|error| Box::new(error) as Box<dyn Error + Sync + Send>)
+    }
+
+    fn receive(&mut self) -> Option<Result<Packet, NetworkError>> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(packet) => match self.link_conditioner {
+                    Some(ref config) => {
+                        link_condition_logic::process_packet(config, &mut self.time_queue, packet)
+                    }
+                    None => return Some(Ok(packet)),
+                },
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if !self.disconnected && !self.time_queue.has_item() {
+                        self.disconnected = true;
+                        return Some(Err(NetworkError::Disconnected));
+                    }
+                    break;
+                }
+            }
+        }
+        self.time_queue.pop_item().map(Ok)
+    }
+
+    fn build_channels(
+        &mut self,
+        builder_fn: &(dyn Fn(&mut ConnectionChannelsBuilder) + Send + Sync),
+        runtime: TaskPoolRuntime,
+        pool: MuxPacketPool<BufferPacketPool<SimpleBufferPool>>,
+    ) {
+        let mut builder = MessageChannelsBuilder::new(runtime, pool);
+        builder_fn(&mut builder);
+
+        let mut multiplexer = PacketMultiplexer::new();
+        self.channels = Some(builder.build(&mut multiplexer));
+        let (channels_rx, mut channels_tx) = multiplexer.start();
+        self.channels_rx = Some(channels_rx);
+
+        let sender = self.sender.clone();
+        self.task_pool
+            .spawn(async move {
+                while let Some(packet) = channels_tx.next().await {
+                    if sender.send(Packet::copy_from_slice(&*packet)).is_err() {
+                        log::info!("Loopback peer disconnected");
+                        return;
+                    }
+                }
+                log::info!("Connection channel disconnected");
+            })
+            .detach();
+    }
+
+    fn channels(&mut self) -> Option<&mut MessageChannels> {
+        self.channels.as_mut()
+    }
+
+    fn channels_rx(&mut self) -> Option<&mut IncomingMultiplexedPackets<MultiplexedPacket>> {
+        self.channels_rx.as_mut()
+    }
+}
@@ -0,0 +1,110 @@
+use instant::Instant;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, time::Duration};
+use turbulence::message_channels::{MessageChannelMode, MessageChannelSettings};
+
+/// How much each new round trip time sample moves the smoothed estimate, as in TCP.
+const ROUND_TRIP_TIME_SMOOTHING: f64 = 0.125;
+
+pub(crate) const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Heartbeats go through their own channel on every connection that has message channels, so
+/// that round trip times can be measured without the application's help. The channel number is
+/// picked from the far end of the range to stay out of the application's way.
+pub(crate) const HEARTBEAT_MESSAGE_SETTINGS: MessageChannelSettings = MessageChannelSettings {
+    channel: 255,
+    channel_mode: MessageChannelMode::Unreliable,
+    message_buffer_size: 8,
+    packet_buffer_size: 8,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) enum Heartbeat {
+    Ping(u32),
+    Pong(u32),
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TrafficStats {
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+impl TrafficStats {
+    pub(crate) fn record(&mut self, packet_len: usize) {
+        self.packets += 1;
+        self.bytes += packet_len as u64;
+    }
+
+    pub fn mean_packet_len(&self) -> f64 {
+        if self.packets == 0 {
+            0.0
+        } else {
+            self.bytes as f64 / self.packets as f64
+        }
+    }
+}
+
+/// What has come in through a connection so far.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionStats {
+    pub received: TrafficStats,
+
+    /// Incoming traffic split up by message channel, for connections that have them. Every
+    /// multiplexed packet carries messages from just one channel.
+    pub received_by_channel: HashMap<u8, TrafficStats>,
+
+    /// A smoothed estimate, once at least one heartbeat has made it there and back.
+    pub round_trip_time: Option<Duration>,
+
+    pub(crate) has_heartbeat_channel: bool,
+    pub(crate) last_ping: Option<(u32, Instant)>,
+    pub(crate) next_ping_sequence: u32,
+}
+
+impl ConnectionStats {
+    pub(crate) fn record_received(&mut self, packet: &[u8]) {
+        self.received.record(packet.len());
+        if self.has_heartbeat_channel {
+            if let Some(channel) = packet.first() {
+                self.received_by_channel
+                    .entry(*channel)
+                    .or_default()
+                    .record(packet.len());
+            }
+        }
+    }
+
+    pub(crate) fn record_round_trip(&mut self, sample: Duration) {
+        self.round_trip_time = Some(match self.round_trip_time {
+            Some(estimate) => Duration::from_secs_f64(
+                estimate.as_secs_f64() * (1.0 - ROUND_TRIP_TIME_SMOOTHING)
+                    + sample.as_secs_f64() * ROUND_TRIP_TIME_SMOOTHING,
+            ),
+            None => sample,
+        });
+    }
+
+    /// Starts a new ping if it is time to, and returns its sequence number.
+    pub(crate) fn next_ping(&mut self, now: Instant) -> Option<u32> {
+        let is_due = self
+            .last_ping
+            .map_or(true, |(_, sent_at)| now - sent_at >= HEARTBEAT_INTERVAL);
+        if !is_due {
+            return None;
+        }
+        let sequence = self.next_ping_sequence;
+        self.next_ping_sequence = self.next_ping_sequence.wrapping_add(1);
+        self.last_ping = Some((sequence, now));
+        Some(sequence)
+    }
+
+    pub(crate) fn receive_pong(&mut self, sequence: u32, now: Instant) {
+        // Note: Only the latest ping counts, so a late reply to an older one doesn't skew things.
+        if let Some((ping_sequence, sent_at)) = self.last_ping {
+            if ping_sequence == sequence {
+                self.record_round_trip(now - sent_at);
+            }
+        }
+    }
+}
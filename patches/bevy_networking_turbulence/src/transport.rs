@@ -135,23 +135,25 @@ impl Connection for ServerConnection {
 
         let mut sender = self.sender.take().unwrap();
         let client_address = self.client_address;
-        /*self.channels_task =*/
-        Some(self.task_pool.spawn(async move {
-            loop {
-                match channels_tx.next().await {
-                    Some(packet) => {
-                        sender
-                            .send(ServerPacket::new(client_address, (*packet).into()))
-                            .await
-                            .unwrap();
-                    }
-                    None => {
-                        log::info!("Connection channel disconnected");
-                        return; // exit task
+        // Note: Dropping the task would cancel it, so let it run for as long as the channels do.
+        self.task_pool
+            .spawn(async move {
+                loop {
+                    match channels_tx.next().await {
+                        Some(packet) => {
+                            sender
+                                .send(ServerPacket::new(client_address, (*packet).into()))
+                                .await
+                                .unwrap();
+                        }
+                        None => {
+                            log::info!("Connection channel disconnected");
+                            return; // exit task
+                        }
                     }
                 }
-            }
-        }));
+            })
+            .detach();
     }
 
     fn channels(&mut self) -> Option<&mut MessageChannels> {
@@ -239,25 +241,22 @@ impl Connection for ClientConnection {
         self.channels_rx = Some(channels_rx);
 
         let mut sender = self.sender.take().unwrap();
-        #[allow(unused_variables)]
-        let channels_task = self.task_pool.spawn(async move {
-            loop {
-                match channels_tx.next().await {
-                    Some(packet) => {
-                        sender.send(ClientPacket::new((*packet).into())).unwrap();
-                    }
-                    None => {
-                        log::error!("Channel stream Disconnected");
-                        return; // exit task
+        // Note: Dropping the task would cancel it, so let it run for as long as the channels do.
+        self.task_pool
+            .spawn(async move {
+                loop {
+                    match channels_tx.next().await {
+                        Some(packet) => {
+                            sender.send(ClientPacket::new((*packet).into())).unwrap();
+                        }
+                        None => {
+                            log::error!("Channel stream Disconnected");
+                            return; // exit task
+                        }
                     }
                 }
-            }
-        });
-
-        //#[cfg(not(target_arch = "wasm32"))]
-        {
-            //self.channels_task = Some(channels_task);
-        }
+            })
+            .detach();
     }
 
     fn channels(&mut self) -> Option<&mut MessageChannels> {
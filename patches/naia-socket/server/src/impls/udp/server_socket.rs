@@ -9,9 +9,10 @@ use std::{
 
 use naia_socket_shared::LinkConditionerConfig;
 
-use crate::{error::NaiaServerSocketError, Packet, ServerSocketTrait};
-
-use crate::{link_conditioner::LinkConditioner, message_sender::MessageSender};
+use crate::{
+    error::NaiaServerSocketError, link_conditioner::LinkConditioner, message_sender::MessageSender,
+    NextEvent, Packet, ServerSocketTrait,
+};
 
 const CLIENT_CHANNEL_SIZE: usize = 8;
 
@@ -48,7 +49,7 @@ impl ServerSocket {
 
 #[async_trait]
 impl ServerSocketTrait for ServerSocket {
-    async fn receive(&mut self) -> Result<Packet, NaiaServerSocketError> {
+    async fn receive(&mut self) -> NextEvent {
         enum Next {
             FromClientMessage(Result<(usize, SocketAddr), IoError>),
             ToClientMessage(Packet),
@@ -83,10 +84,15 @@ impl ServerSocketTrait for ServerSocket {
                             .iter()
                             .cloned()
                             .collect();
-                        return Ok(Packet::new_raw(message_address, payload.into_boxed_slice()));
+                        return NextEvent::ReceivedPacket(Ok(Packet::new_raw(
+                            message_address,
+                            payload.into_boxed_slice(),
+                        )));
                     }
                     Err(err) => {
-                        return Err(NaiaServerSocketError::Wrapped(Box::new(err)));
+                        return NextEvent::ReceivedPacket(Err(NaiaServerSocketError::Wrapped(
+                            Box::new(err),
+                        )));
                     }
                 },
                 Next::ToClientMessage(packet) => {
@@ -94,7 +100,9 @@ impl ServerSocketTrait for ServerSocket {
 
                     match self.socket.send_to(packet.payload(), address).await {
                         Err(_) => {
-                            return Err(NaiaServerSocketError::SendError(address));
+                            return NextEvent::ReceivedPacket(Err(
+                                NaiaServerSocketError::SendError(address),
+                            ));
                         }
                         _ => {}
                     }
@@ -30,7 +30,8 @@ pub use naia_socket_shared::LinkConditionerConfig;
 
 mod error;
 mod impls;
-// mod link_conditioner;
+#[cfg(feature = "use-udp")]
+mod link_conditioner;
 mod message_sender;
 mod packet;
 mod server_socket_trait;
@@ -6,8 +6,9 @@ use std::time::Duration;
 use naia_socket_shared::{link_condition_logic, LinkConditionerConfig, TimeQueue};
 
 use super::{
-    error::NaiaServerSocketError, message_sender::MessageSender, packet::Packet,
-    server_socket_trait::ServerSocketTrait,
+    message_sender::MessageSender,
+    packet::Packet,
+    server_socket_trait::{NextEvent, ServerSocketTrait},
 };
 
 pub struct LinkConditioner {
@@ -28,9 +29,9 @@ impl LinkConditioner {
 
 #[async_trait]
 impl ServerSocketTrait for LinkConditioner {
-    async fn receive(&mut self) -> Result<Packet, NaiaServerSocketError> {
+    async fn receive(&mut self) -> NextEvent {
         enum Next {
-            Event(Result<Packet, NaiaServerSocketError>),
+            Event(NextEvent),
             BufferedEvent,
         }
 
@@ -70,17 +71,17 @@ impl ServerSocketTrait for LinkConditioner {
             };
 
             match next {
-                Next::Event(result) => match result {
-                    Ok(packet) => {
+                Next::Event(event) => match event {
+                    NextEvent::ReceivedPacket(Ok(packet)) => {
                         self.process_packet(packet);
                     }
-                    Err(err) => {
-                        return Err(err);
+                    event => {
+                        return event;
                     }
                 },
                 Next::BufferedEvent => {
                     if let Some(packet) = self.time_queue.pop_item() {
-                        return Ok(packet);
+                        return NextEvent::ReceivedPacket(Ok(packet));
                     }
                 }
             }
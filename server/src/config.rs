@@ -0,0 +1,238 @@
+use shared::{level::LevelId, palette::Palette, settings};
+use std::{env, fmt::Display, net::SocketAddr, str::FromStr};
+
+pub const USAGE: &str = "\
+Usage: server [OPTIONS]
+
+Options (each can also be set through the environment variable in brackets):
+    --bind <ADDRESS>        UDP address to listen on [DANGO_BIND_ADDRESS] (default: 0.0.0.0:14191)
+    --tick-rate <HZ>        How many times per second the server updates [DANGO_TICK_RATE] (default: 60)
+    --level <NAME>          Level to play on: meadow, playground [DANGO_LEVEL] (default: meadow)
+    --max-players <COUNT>   How many players can join at once [DANGO_MAX_PLAYERS] (default: 100)
+    --palette <NAME>        Colours to hand out: standard, colorblind [DANGO_PALETTE] (default: standard)
+    --npcs <COUNT>          How many dangos the server plays itself [DANGO_NPCS] (default: 0)
+    --help                  Print this message";
+
+/// Settings for running a dedicated server. Command line arguments take precedence over
+/// environment variables, which take precedence over the defaults.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub bind_address: SocketAddr,
+    pub tick_rate: f64,
+    pub level: LevelId,
+    pub max_players: usize,
+    pub palette: Palette,
+    pub npc_count: usize,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    HelpRequested,
+    UnknownArgument(String),
+    MissingValue(String),
+    InvalidValue { option: String, value: String },
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: "0.0.0.0:14191".parse().unwrap(),
+            tick_rate: 1.0 / settings::TIMESTEP,
+            level: LevelId::default(),
+            max_players: settings::MAX_PLAYERS,
+            palette: Palette::default(),
+            npc_count: 0,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn from_env_and_args() -> Result<Self, ConfigError> {
+        Self::from_sources(|env_var| env::var(env_var).ok(), env::args().skip(1))
+    }
+
+    /// Reads the settings from the given environment variables and command line arguments,
+    /// leaving out the program name.
+    fn from_sources<E, A>(read_env_var: E, args: A) -> Result<Self, ConfigError>
+    where
+        E: Fn(&str) -> Option<String>,
+        A: IntoIterator<Item = String>,
+    {
+        let mut config = Self::default();
+        for (option, env_var) in [
+            ("--bind", "DANGO_BIND_ADDRESS"),
+            ("--tick-rate", "DANGO_TICK_RATE"),
+            ("--level", "DANGO_LEVEL"),
+            ("--max-players", "DANGO_MAX_PLAYERS"),
+            ("--palette", "DANGO_PALETTE"),
+            ("--npcs", "DANGO_NPCS"),
+        ]
+        .iter()
+        {
+            if let Some(value) = read_env_var(env_var) {
+                config.set(option, value)?;
+            }
+        }
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "--help" || arg == "-h" {
+                return Err(ConfigError::HelpRequested);
+            }
+            let (option, value) = match arg.find('=') {
+                Some(index) => (arg[..index].to_string(), arg[index + 1..].to_string()),
+                None => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| ConfigError::MissingValue(arg.clone()))?;
+                    (arg, value)
+                }
+            };
+            config.set(&option, value)?;
+        }
+
+        Ok(config)
+    }
+
+    fn set(&mut self, option: &str, value: String) -> Result<(), ConfigError> {
+        match option {
+            "--bind" => self.bind_address = parse(option, value)?,
+            "--tick-rate" => {
+                self.tick_rate = parse(option, value.clone())?;
+                if self.tick_rate <= 0.0 || !self.tick_rate.is_finite() {
+                    return Err(ConfigError::InvalidValue {
+                        option: option.to_string(),
+                        value,
+                    });
+                }
+            }
+            "--level" => {
+                self.level =
+                    LevelId::from_name(&value).ok_or_else(|| ConfigError::InvalidValue {
+                        option: option.to_string(),
+                        value,
+                    })?
+            }
+            "--max-players" => self.max_players = parse(option, value)?,
+            "--palette" => {
+                self.palette =
+                    Palette::from_name(&value).ok_or_else(|| ConfigError::InvalidValue {
+                        option: option.to_string(),
+                        value,
+                    })?
+            }
+            "--npcs" => self.npc_count = parse(option, value)?,
+            _ => return Err(ConfigError::UnknownArgument(option.to_string())),
+        }
+        Ok(())
+    }
+}
+
+fn parse<T: FromStr>(option: &str, value: String) -> Result<T, ConfigError> {
+    value.parse().map_err(|_| ConfigError::InvalidValue {
+        option: option.to_string(),
+        value,
+    })
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::HelpRequested => write!(f, "{}", USAGE),
+            ConfigError::UnknownArgument(arg) => write!(f, "Unknown argument {}\n\n{}", arg, USAGE),
+            ConfigError::MissingValue(option) => {
+                write!(f, "Missing a value for {}\n\n{}", option, USAGE)
+            }
+            ConfigError::InvalidValue { option, value } => {
+                write!(f, "Invalid value {:?} for {}\n\n{}", value, option, USAGE)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(env_vars: &[(&str, &str)], args: &[&str]) -> Result<ServerConfig, ConfigError> {
+        ServerConfig::from_sources(
+            |env_var| {
+                env_vars
+                    .iter()
+                    .find(|(name, _)| *name == env_var)
+                    .map(|(_, value)| value.to_string())
+            },
+            args.iter().map(|arg| arg.to_string()),
+        )
+    }
+
+    #[test]
+    fn defaults_without_any_settings() {
+        let config = config(&[], &[]).unwrap();
+        assert_eq!(config.bind_address, "0.0.0.0:14191".parse().unwrap());
+        assert_eq!(config.level, LevelId::default());
+        assert_eq!(config.max_players, settings::MAX_PLAYERS);
+        assert_eq!(config.npc_count, 0);
+    }
+
+    #[test]
+    fn reads_options_with_and_without_equals_signs() {
+        let config = config(
+            &[],
+            &[
+                "--bind",
+                "127.0.0.1:9000",
+                "--level=playground",
+                "--max-players",
+                "8",
+                "--palette=colorblind",
+                "--npcs=3",
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.bind_address, "127.0.0.1:9000".parse().unwrap());
+        assert_eq!(config.level, LevelId::Playground);
+        assert_eq!(config.max_players, 8);
+        assert_eq!(config.palette, Palette::ColorBlindSafe);
+        assert_eq!(config.npc_count, 3);
+    }
+
+    #[test]
+    fn arguments_take_precedence_over_the_environment() {
+        let config = config(
+            &[("DANGO_MAX_PLAYERS", "4"), ("DANGO_NPCS", "2")],
+            &["--max-players", "16"],
+        )
+        .unwrap();
+        assert_eq!(config.max_players, 16);
+        assert_eq!(config.npc_count, 2);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(matches!(
+            config(&[], &["--help"]),
+            Err(ConfigError::HelpRequested)
+        ));
+        assert!(matches!(
+            config(&[], &["--volume", "11"]),
+            Err(ConfigError::UnknownArgument(_))
+        ));
+        assert!(matches!(
+            config(&[], &["--level"]),
+            Err(ConfigError::MissingValue(_))
+        ));
+        assert!(matches!(
+            config(&[], &["--level", "moon"]),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            config(&[], &["--tick-rate", "0"]),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            config(&[("DANGO_NPCS", "lots")], &[]),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+    }
+}
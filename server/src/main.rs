@@ -1,21 +1,70 @@
-use bevy::{app::ScheduleRunnerSettings, diagnostic::FrameTimeDiagnosticsPlugin, prelude::*};
-use bevy_prototype_transform_tracker::TransformTrackingFollower;
+use bevy::{diagnostic::FrameTimeDiagnosticsPlugin, prelude::*};
 use crystalorb_bevy_networking_turbulence::{
     bevy_networking_turbulence::{NetworkEvent, NetworkResource},
-    CrystalOrbServerPlugin,
+    crystalorb::server::Server,
+    CrystalOrbServerPlugin, WrappedNetworkResource,
 };
 use shared::{
-    physics_multiplayer::PhysicsWorld, physics_multiplayer_systems, settings,
+    admin,
+    npc::{self, Npcs},
+    physics_multiplayer::{PhysicsCommand, PhysicsWorld},
+    physics_multiplayer_server,
+    platform::{default_platform, Platform},
+    settings,
     wasm_print_diagnostics_plugin::WasmPrintDiagnosticsPlugin,
 };
+
+#[cfg(not(feature = "debug-window"))]
+use bevy::app::ScheduleRunnerSettings;
+
+#[cfg(not(feature = "debug-window"))]
 use std::time::Duration;
-use web_sys::Url;
+
+#[cfg(feature = "debug-window")]
+use bevy_prototype_transform_tracker::{TransformTrackingFollower, TransformTrackingPlugin};
+
+#[cfg(feature = "debug-window")]
+use shared::physics_multiplayer_systems;
+
+#[cfg(feature = "web")]
+use shared::{
+    host_migration::{Handoff, CLAIM_KEY, HANDOFF_KEY, ROOM_PARAM},
+    palette::Palette,
+    session::{SessionHash, SessionSecret},
+    signalling::{HostKey, SignallingEndpoint},
+};
+
+#[cfg(feature = "native")]
+use config::{ConfigError, ServerConfig};
+
+#[cfg(feature = "native")]
+mod config;
 
 fn main() {
+    #[cfg(feature = "web")]
     if log::set_logger(&wasm_bindgen_console_logger::DEFAULT_LOGGER).is_ok() {
         log::set_max_level(log::LevelFilter::Info);
     }
 
+    #[cfg(feature = "native")]
+    let config = match ServerConfig::from_env_and_args() {
+        Ok(config) => config,
+        Err(ConfigError::HelpRequested) => {
+            println!("{}", ConfigError::HelpRequested);
+            return;
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(2);
+        }
+    };
+
+    #[cfg(feature = "native")]
+    let tick_rate = config.tick_rate;
+
+    #[cfg(not(feature = "native"))]
+    let tick_rate = 60.0;
+
     let mut app = App::build();
 
     // Note: Setup hot reloading first before loading other plugins.
@@ -33,25 +82,30 @@ fn main() {
     .add_plugin(bevy::asset::AssetPlugin::default())
     .add_plugin(bevy::scene::ScenePlugin::default());
 
-    // if SHOW_DEBUG_WINDOW {
-    //     app.add_resource(ClearColor(Color::WHITE))
-    //         .add_plugin(bevy::transform::TransformPlugin::default())
-    //         .add_plugin(bevy::input::InputPlugin::default())
-    //         .add_plugin(bevy::window::WindowPlugin::default())
-    //         .add_plugin(bevy::render::RenderPlugin::default())
-    //         .add_plugin(bevy::sprite::SpritePlugin::default())
-    //         .add_plugin(bevy::pbr::PbrPlugin::default())
-    //         .add_plugin(bevy::ui::UiPlugin::default())
-    //         .add_plugin(bevy::text::TextPlugin::default())
-    //         .add_plugin(bevy::gltf::GltfPlugin::default())
-    //         .add_plugin(bevy::winit::WinitPlugin::default())
-    //         .add_plugin(bevy::wgpu::WgpuPlugin::default());
-    // } else {
-    app.insert_resource(ScheduleRunnerSettings::run_loop(Duration::from_secs_f32(
-        1.0 / 60.0,
+    // Note: The debug window shows the server's own view of the world, and runs at whatever rate
+    // the window refreshes at instead of the tick rate.
+    #[cfg(feature = "debug-window")]
+    app.insert_resource(ClearColor(Color::WHITE))
+        .add_plugin(bevy::transform::TransformPlugin::default())
+        .add_plugin(bevy::input::InputPlugin::default())
+        .add_plugin(bevy::window::WindowPlugin::default())
+        .add_plugin(bevy::render::RenderPlugin::default())
+        .add_plugin(bevy::sprite::SpritePlugin::default())
+        .add_plugin(bevy::winit::WinitPlugin::default())
+        .add_plugin(bevy::wgpu::WgpuPlugin::default())
+        .add_plugin(TransformTrackingPlugin);
+
+    #[cfg(feature = "debug-window")]
+    info!(
+        "Ticking along with the debug window rather than {} times a second",
+        tick_rate
+    );
+
+    #[cfg(not(feature = "debug-window"))]
+    app.insert_resource(ScheduleRunnerSettings::run_loop(Duration::from_secs_f64(
+        1.0 / tick_rate,
     )))
     .add_plugin(bevy::app::ScheduleRunnerPlugin::default());
-    // }
 
     app.add_plugin(CrystalOrbServerPlugin::<PhysicsWorld>::new(
         settings::NETWORKED_PHYSICS_CONFIG,
@@ -64,18 +118,31 @@ fn main() {
 
     app.add_plugin(FrameTimeDiagnosticsPlugin::default())
         //.add_plugin(WasmPrintDiagnosticsPlugin::default())
+        .insert_resource(default_platform())
         .add_system(show_shareable_url_system.system())
-        .add_system(
-            physics_multiplayer_systems::physics_multiplayer_server_spawn_despawn_system.system(),
-        )
+        .add_system(physics_multiplayer_server::physics_multiplayer_server_despawn_system.system())
+        .add_system(physics_multiplayer_server::physics_multiplayer_server_handoff_system.system())
+        .init_resource::<Npcs>()
+        .add_system(npc::npc_system.system())
+        .add_system(admin::admin_command_system.system())
         .add_startup_system(setup.system());
 
-    // if SHOW_DEBUG_WINDOW {
-    //     app.add_system(
-    //         physics_multiplayer_systems::physics_multiplayer_server_diagnostic_sync_system.system(),
-    //     )
-    //     .add_startup_system(debug_window_setup.system());
-    // }
+    #[cfg(feature = "native")]
+    app.insert_resource(config);
+
+    #[cfg(feature = "web")]
+    app.insert_resource(SignallingRoom {
+        key: HostKey::random(),
+        id: None,
+    })
+    .add_system(report_room_full_system.system())
+    .add_system(report_successor_system.system());
+
+    #[cfg(feature = "debug-window")]
+    app.add_system(
+        physics_multiplayer_systems::physics_multiplayer_server_diagnostic_sync_system.system(),
+    )
+    .add_startup_system(debug_window_setup.system());
 
     app.run();
 }
@@ -84,12 +151,196 @@ fn setup_hot_reloading(asset_server: ResMut<AssetServer>) {
     asset_server.watch_for_changes().unwrap();
 }
 
-fn setup(mut net: ResMut<NetworkResource>) {
-    let endpoint_url = "wss://dango-tribute.up.railway.app/host".to_string();
+/// The room this server hosts on the signalling server.
+#[cfg(feature = "web")]
+struct SignallingRoom {
+    key: HostKey,
+
+    /// Only known once the signalling server hands it out.
+    id: Option<String>,
+}
+
+#[cfg(feature = "web")]
+fn setup(
+    mut net: ResMut<NetworkResource>,
+    mut server: ResMut<Server<PhysicsWorld>>,
+    mut npcs: ResMut<Npcs>,
+    room: Res<SignallingRoom>,
+    platform: Res<Box<dyn Platform>>,
+) {
+    // Note: A handoff is only meant for the room it came from, and only for the first page load
+    // after it was left behind.
+    let handoff = platform
+        .load_setting(HANDOFF_KEY)
+        .and_then(|setting| Handoff::from_setting(&setting));
+    let claim = platform
+        .load_setting(CLAIM_KEY)
+        .and_then(|setting| setting.parse().ok())
+        .map(SessionSecret);
+    for key in [HANDOFF_KEY, CLAIM_KEY].iter() {
+        if let Err(err) = platform.save_setting(key, "") {
+            warn!("Could not clear the handoff: {}", err);
+        }
+    }
+
+    let signalling_endpoint = SignallingEndpoint::from_platform(&**platform);
+    let room_id = platform.launch_param(ROOM_PARAM);
+    let endpoint_url = match &room_id {
+        Some(room_id) => signalling_endpoint.rehost_url(room_id, &room.key, claim),
+        None => signalling_endpoint.host_url(&room.key),
+    };
     info!("Starting server - listening at {}", endpoint_url);
     net.listen(endpoint_url);
+    if let (Some(room_id), Some(handoff)) = (room_id, handoff) {
+        info!("Taking over room {} from the previous host", room_id);
+        server.issue_command(
+            PhysicsCommand::Restore(handoff.snapshot),
+            &mut WrappedNetworkResource(&mut net),
+        );
+    }
+
+    if let Some(palette_name) = platform.launch_param("palette") {
+        match Palette::from_name(&palette_name) {
+            Some(palette) => {
+                info!("Using the {} palette", palette.name());
+                server.issue_command(
+                    PhysicsCommand::SetPalette(palette),
+                    &mut WrappedNetworkResource(&mut net),
+                );
+            }
+            None => warn!("Ignoring unknown palette {:?}", palette_name),
+        }
+    }
+
+    if let Some(max_players) = platform.launch_param("max-players") {
+        match max_players.parse() {
+            Ok(max_players) => {
+                info!("Letting up to {} players join", max_players);
+                server.issue_command(
+                    PhysicsCommand::SetMaxPlayers(max_players),
+                    &mut WrappedNetworkResource(&mut net),
+                );
+            }
+            Err(_) => warn!("Ignoring invalid player count {:?}", max_players),
+        }
+    }
+
+    if let Some(npc_count) = platform.launch_param("npcs") {
+        match npc_count.parse() {
+            Ok(npc_count) => {
+                info!("Adding {} NPCs", npc_count);
+                *npcs = Npcs::new(npc_count);
+            }
+            Err(_) => warn!("Ignoring invalid NPC count {:?}", npc_count),
+        }
+    }
+}
+
+/// Lets the signalling server turn newcomers away while every spot in the room is taken, rather
+/// than have them connect only to find out they can't join.
+#[cfg(feature = "web")]
+fn report_room_full_system(
+    mut reported_is_full: Local<Option<bool>>,
+    mut network_events: EventReader<NetworkEvent>,
+    mut room: ResMut<SignallingRoom>,
+    server: Res<Server<PhysicsWorld>>,
+    platform: Res<Box<dyn Platform>>,
+) {
+    for network_event in network_events.iter() {
+        if let NetworkEvent::Hosted(endpoint_id) = network_event {
+            room.id = Some(endpoint_id.clone());
+            *reported_is_full = None;
+        }
+    }
+    let room_id = match &room.id {
+        Some(room_id) => room_id,
+        None => return,
+    };
+    // Note: Dangos waiting to be reclaimed don't count, so that their clients can still get
+    // through to reclaim them. Anyone else who slips through in the meantime waits in the room.
+    let is_full = server.display_state().is_full_without_away_players();
+    if *reported_is_full == Some(is_full) {
+        return;
+    }
+    *reported_is_full = Some(is_full);
+    let url = SignallingEndpoint::from_platform(&**platform).room_full_url(room_id, &room.key);
+    if let Err(err) = platform.send_request("PUT", &url, &is_full.to_string()) {
+        warn!(
+            "Could not tell the signalling server whether the room is full: {}",
+            err
+        );
+    }
+}
+
+/// Tells the signalling server whose session can take over the room if this host goes away, so
+/// that nobody else can.
+#[cfg(feature = "web")]
+fn report_successor_system(
+    mut reported_claim: Local<Option<SessionHash>>,
+    room: Res<SignallingRoom>,
+    server: Res<Server<PhysicsWorld>>,
+    platform: Res<Box<dyn Platform>>,
+) {
+    let room_id = match &room.id {
+        Some(room_id) => room_id,
+        None => return,
+    };
+    let claim = match server
+        .display_state()
+        .handoff()
+        .and_then(|handoff| handoff.claim)
+    {
+        Some(claim) => claim,
+        None => return,
+    };
+    if *reported_claim == Some(claim) {
+        return;
+    }
+    *reported_claim = Some(claim);
+    let url = SignallingEndpoint::from_platform(&**platform).successor_url(room_id, &room.key);
+    if let Err(err) = platform.send_request("PUT", &url, &claim.to_hex()) {
+        warn!(
+            "Could not tell the signalling server who takes over the room: {}",
+            err
+        );
+    }
+}
+
+#[cfg(feature = "native")]
+fn setup(
+    mut net: ResMut<NetworkResource>,
+    mut server: ResMut<Server<PhysicsWorld>>,
+    mut npcs: ResMut<Npcs>,
+    config: Res<ServerConfig>,
+) {
+    info!("Starting server - listening at {}", config.bind_address);
+    net.listen(config.bind_address);
+
+    info!(
+        "Playing {} with up to {} players using the {} palette",
+        config.level.name(),
+        config.max_players,
+        config.palette.name()
+    );
+    for command in [
+        PhysicsCommand::SetLevel(config.level),
+        PhysicsCommand::SetMaxPlayers(config.max_players),
+        PhysicsCommand::SetPalette(config.palette),
+    ]
+    .iter()
+    {
+        server.issue_command(command.clone(), &mut WrappedNetworkResource(&mut net));
+    }
+
+    if config.npc_count > 0 {
+        info!("Adding {} NPCs", config.npc_count);
+        *npcs = Npcs::new(config.npc_count);
+    }
+
+    println!("Type `help` to see what you can do to moderate the room");
 }
 
+#[cfg(feature = "debug-window")]
 fn debug_window_setup(mut commands: Commands) {
     commands
         .spawn_bundle(OrthographicCameraBundle {
@@ -103,33 +354,15 @@ fn debug_window_setup(mut commands: Commands) {
         .insert(TransformTrackingFollower);
 }
 
-fn show_shareable_url_system(mut network_events: EventReader<NetworkEvent>) {
+fn show_shareable_url_system(
+    mut network_events: EventReader<NetworkEvent>,
+    platform: Res<Box<dyn Platform>>,
+) {
     for network_event in network_events.iter() {
         if let NetworkEvent::Hosted(endpoint_id) = network_event {
             info!("Found endpoint id");
-            let relative_url = format!("../client/?join={}", endpoint_id);
-            let document = web_sys::window()
-                .expect("should have global window")
-                .document()
-                .expect("window should have document");
-            let document_location: String = document
-                .location()
-                .expect("document should have a location")
-                .to_string()
-                .into();
-            let absolute_url = Url::new_with_base(&relative_url, &document_location)
-                .expect("resulting url should be valid")
-                .href();
-            document
-                .get_element_by_id("join-url")
-                .expect("join-url input should exist")
-                .set_attribute("value", &absolute_url)
-                .expect("setting value attribute should succeed");
-            document
-                .get_element_by_id("client-iframe")
-                .expect("client iframe should exist")
-                .set_attribute("src", &absolute_url)
-                .expect("setting src attribute should succeed");
+            let link = platform.shareable_link(endpoint_id);
+            platform.show_shareable_link(&link);
         }
     }
 }
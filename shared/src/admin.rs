@@ -0,0 +1,197 @@
+use crate::{
+    level::LevelId,
+    physics_multiplayer::{PhysicsCommand, PhysicsDisplayState, PhysicsWorld},
+    platform::Platform,
+    player::PlayerId,
+};
+use bevy::prelude::*;
+use crystalorb_bevy_networking_turbulence::{
+    bevy_networking_turbulence::NetworkResource, crystalorb::server::Server, WrappedNetworkResource,
+};
+
+pub const ADMIN_HELP: &str = "\
+Admin commands:
+    players         List everyone in the room, along with their ids
+    kick <ID>       Remove a player's dango, and don't let their client join again
+                    (they stay connected and can still watch)
+    ban <ID>        Kick a player, and keep them out even if they reload
+    reset           Put everyone back at the spawn point
+    award <ID> <N>  Give a player N points, or take them away if N is negative
+    level <NAME>    Switch to another level: meadow, playground
+    help            Print this message";
+
+/// Something the host can do to keep order in their room, typed in as e.g. `kick 3`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdminCommand {
+    ListPlayers,
+    Kick(PlayerId),
+    Ban(PlayerId),
+    ResetPositions,
+    Award { player_id: PlayerId, points: i32 },
+    SetLevel(LevelId),
+    Help,
+}
+
+impl AdminCommand {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or_default().to_lowercase();
+        let argument = words.next();
+        let second_argument = words.next();
+        let player_id = || {
+            argument
+                .and_then(|id| id.parse().ok())
+                .map(PlayerId)
+                .ok_or_else(|| format!("{} needs a player id, e.g. `{} 3`", name, name))
+        };
+        match name.as_str() {
+            "players" => Ok(AdminCommand::ListPlayers),
+            "kick" => player_id().map(AdminCommand::Kick),
+            "ban" => player_id().map(AdminCommand::Ban),
+            "reset" => Ok(AdminCommand::ResetPositions),
+            "award" => {
+                let player_id = player_id()?;
+                second_argument
+                    .and_then(|points| points.parse().ok())
+                    .map(|points| AdminCommand::Award { player_id, points })
+                    .ok_or_else(|| "award needs a number of points, e.g. `award 3 10`".to_string())
+            }
+            "level" => argument
+                .and_then(LevelId::from_name)
+                .map(AdminCommand::SetLevel)
+                .ok_or_else(|| "level needs a level name, e.g. `level meadow`".to_string()),
+            "help" => Ok(AdminCommand::Help),
+            _ => Err(format!("Unknown command {:?}", line.trim())),
+        }
+    }
+
+    /// What the world needs to do about it, if anything.
+    pub fn physics_command(&self) -> Option<PhysicsCommand> {
+        match self {
+            AdminCommand::Kick(player_id) => Some(PhysicsCommand::Kick(*player_id)),
+            AdminCommand::Ban(player_id) => Some(PhysicsCommand::Ban(*player_id)),
+            AdminCommand::ResetPositions => Some(PhysicsCommand::ResetPositions),
+            AdminCommand::Award { player_id, points } => Some(PhysicsCommand::AwardPoints {
+                player_id: *player_id,
+                points: *points,
+            }),
+            AdminCommand::SetLevel(level) => Some(PhysicsCommand::SetLevel(*level)),
+            AdminCommand::ListPlayers | AdminCommand::Help => None,
+        }
+    }
+}
+
+/// Lists players by id, so that the host knows what to type in.
+pub fn player_list(display_state: &PhysicsDisplayState) -> String {
+    let mut players: Vec<_> = display_state.players().iter().collect();
+    players.sort_by_key(|(player_id, _)| **player_id);
+    if players.is_empty() {
+        return "Nobody is here yet".to_string();
+    }
+    players
+        .into_iter()
+        .map(|(player_id, player)| {
+            let mut line = format!(
+                "{:>10}  {:<16}  score {}",
+                player_id.0, player.nickname, player.score
+            );
+            if let Some(team) = player.team {
+                line.push_str(&format!("  team {}", team.0 + 1));
+            }
+            if player_id.is_npc() {
+                line.push_str("  (NPC)");
+            }
+            if player.is_away {
+                line.push_str("  (away)");
+            }
+            line
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Carries out whatever the host typed in since last time.
+pub fn admin_command_system(
+    mut server: ResMut<Server<PhysicsWorld>>,
+    mut net: ResMut<NetworkResource>,
+    platform: Res<Box<dyn Platform>>,
+) {
+    for line in platform.read_admin_commands() {
+        let command = match AdminCommand::parse(&line) {
+            Ok(command) => command,
+            Err(err) => {
+                platform.show_admin_output(&format!("{}\n\n{}", err, ADMIN_HELP));
+                continue;
+            }
+        };
+        match command.physics_command() {
+            Some(physics_command) => {
+                info!("Admin: {}", line.trim());
+                server.issue_command(physics_command, &mut WrappedNetworkResource(&mut net));
+                platform.show_admin_output(&format!("Done: {}", line.trim()));
+            }
+            None if command == AdminCommand::ListPlayers => {
+                platform.show_admin_output(&player_list(&server.display_state()));
+            }
+            None => platform.show_admin_output(ADMIN_HELP),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands_with_their_arguments() {
+        assert_eq!(
+            AdminCommand::parse("players"),
+            Ok(AdminCommand::ListPlayers)
+        );
+        assert_eq!(
+            AdminCommand::parse("  KICK 3 "),
+            Ok(AdminCommand::Kick(PlayerId(3)))
+        );
+        assert_eq!(
+            AdminCommand::parse("ban 7"),
+            Ok(AdminCommand::Ban(PlayerId(7)))
+        );
+        assert_eq!(
+            AdminCommand::parse("award 3 -10"),
+            Ok(AdminCommand::Award {
+                player_id: PlayerId(3),
+                points: -10
+            })
+        );
+        assert_eq!(
+            AdminCommand::parse("level meadow"),
+            Ok(AdminCommand::SetLevel(LevelId::Meadow))
+        );
+        assert_eq!(
+            AdminCommand::parse("reset"),
+            Ok(AdminCommand::ResetPositions)
+        );
+        assert_eq!(AdminCommand::parse("help"), Ok(AdminCommand::Help));
+    }
+
+    #[test]
+    fn rejects_missing_or_invalid_arguments() {
+        assert!(AdminCommand::parse("kick").is_err());
+        assert!(AdminCommand::parse("ban someone").is_err());
+        assert!(AdminCommand::parse("award 3").is_err());
+        assert!(AdminCommand::parse("award 3 lots").is_err());
+        assert!(AdminCommand::parse("level moon").is_err());
+        assert!(AdminCommand::parse("").is_err());
+        assert!(AdminCommand::parse("dance").is_err());
+    }
+
+    #[test]
+    fn only_world_changes_become_physics_commands() {
+        assert!(matches!(
+            AdminCommand::Kick(PlayerId(3)).physics_command(),
+            Some(PhysicsCommand::Kick(PlayerId(3)))
+        ));
+        assert!(AdminCommand::ListPlayers.physics_command().is_none());
+        assert!(AdminCommand::Help.physics_command().is_none());
+    }
+}
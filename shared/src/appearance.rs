@@ -0,0 +1,131 @@
+use crate::{color::Rgb, palette::Palette};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EyeStyle {
+    Line,
+    Dot,
+    Happy,
+}
+
+/// How a player's dango looks. Clients request their preferred appearance when joining, and the
+/// world validates it against everyone else's before spawning the dango.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayerAppearance {
+    pub color: Rgb,
+    pub size: f32,
+    pub eye_style: EyeStyle,
+    pub nickname: String,
+}
+
+impl EyeStyle {
+    pub const ALL: [EyeStyle; 3] = [EyeStyle::Line, EyeStyle::Dot, EyeStyle::Happy];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EyeStyle::Line => "line",
+            EyeStyle::Dot => "dot",
+            EyeStyle::Happy => "happy",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<EyeStyle> {
+        EyeStyle::ALL
+            .iter()
+            .copied()
+            .find(|eye_style| eye_style.name().eq_ignore_ascii_case(name))
+    }
+}
+
+impl Default for EyeStyle {
+    fn default() -> Self {
+        EyeStyle::Line
+    }
+}
+
+impl Default for PlayerAppearance {
+    fn default() -> Self {
+        Self {
+            color: Palette::Standard.colors()[0],
+            size: 0.6,
+            eye_style: EyeStyle::default(),
+            nickname: String::new(),
+        }
+    }
+}
+
+impl PlayerAppearance {
+    pub const MIN_SIZE: f32 = 0.45;
+    pub const MAX_SIZE: f32 = 0.75;
+    pub const MAX_NICKNAME_CHARS: usize = 16;
+
+    /// Clamps the requested appearance to what is allowed, and resolves any conflicts with the
+    /// appearances of the players that are already in the world.
+    pub fn validated<'a, I: IntoIterator<Item = &'a PlayerAppearance>>(
+        &self,
+        palette: Palette,
+        existing_appearances: I,
+    ) -> PlayerAppearance {
+        let existing_appearances: Vec<&PlayerAppearance> =
+            existing_appearances.into_iter().collect();
+
+        let size = if self.size.is_finite() {
+            self.size.max(Self::MIN_SIZE).min(Self::MAX_SIZE)
+        } else {
+            Self::default().size
+        };
+
+        let requested_color = Rgb::new(
+            clamp_unit(self.color.r),
+            clamp_unit(self.color.g),
+            clamp_unit(self.color.b),
+        );
+        let colors_in_use: Vec<Rgb> = existing_appearances
+            .iter()
+            .map(|appearance| appearance.color)
+            .collect();
+        let color = palette.allocate(requested_color, &colors_in_use);
+
+        let base_nickname: String = self
+            .nickname
+            .chars()
+            .filter(|c| !c.is_control())
+            .collect::<String>()
+            .trim()
+            .chars()
+            .take(Self::MAX_NICKNAME_CHARS)
+            .collect();
+        let is_nickname_taken = |nickname: &str| {
+            existing_appearances
+                .iter()
+                .any(|appearance| !nickname.is_empty() && appearance.nickname == nickname)
+        };
+        let mut nickname = base_nickname.clone();
+        let mut suffix = 2;
+        while is_nickname_taken(&nickname) {
+            // Note: Make room for the suffix, so that it doesn't push the nickname over the limit.
+            let suffix_text = format!(" {}", suffix);
+            let truncated_nickname: String = base_nickname
+                .chars()
+                .take(Self::MAX_NICKNAME_CHARS.saturating_sub(suffix_text.chars().count()))
+                .collect();
+            nickname = format!("{}{}", truncated_nickname.trim_end(), suffix_text);
+            suffix += 1;
+        }
+
+        PlayerAppearance {
+            color,
+            size,
+            eye_style: self.eye_style,
+            nickname,
+        }
+    }
+}
+
+fn clamp_unit(value: f32) -> f32 {
+    if value.is_finite() {
+        value.max(0.0).min(1.0)
+    } else {
+        0.0
+    }
+}
@@ -1,3 +1,4 @@
+use crate::appearance::EyeStyle;
 use bevy::{prelude::*, render::mesh::Indices};
 use lyon::{
     math::point,
@@ -11,6 +12,7 @@ use rand::prelude::*;
 
 pub struct BlinkingEyes {
     pub eye_meshes: Vec<Handle<Mesh>>,
+    pub eye_style: EyeStyle,
     pub state: EyeState,
     pub state_remaining_seconds: f32,
 }
@@ -23,9 +25,14 @@ pub enum EyeState {
 }
 
 impl BlinkingEyes {
-    pub fn new(eye_meshes: Vec<Handle<Mesh>>, meshes: &mut Assets<Mesh>) -> BlinkingEyes {
+    pub fn new(
+        eye_meshes: Vec<Handle<Mesh>>,
+        eye_style: EyeStyle,
+        meshes: &mut Assets<Mesh>,
+    ) -> BlinkingEyes {
         let blinking_eyes = BlinkingEyes {
             eye_meshes,
+            eye_style,
             state: EyeState::BlinkClosedSecond,
             state_remaining_seconds: 0.0,
         };
@@ -37,13 +44,31 @@ impl BlinkingEyes {
 
     pub fn update_meshes(&self, meshes: &mut Assets<Mesh>) {
         let path = match self.state {
-            EyeState::Open | EyeState::BlinkOpen => {
-                let mut builder = Path::builder();
-                builder.move_to(point(0.0, -0.2));
-                builder.line_to(point(0.0, 0.2));
-                builder.close();
-                builder.build()
-            }
+            EyeState::Open | EyeState::BlinkOpen => match self.eye_style {
+                EyeStyle::Line => {
+                    let mut builder = Path::builder();
+                    builder.move_to(point(0.0, -0.2));
+                    builder.line_to(point(0.0, 0.2));
+                    builder.close();
+                    builder.build()
+                }
+                EyeStyle::Dot => {
+                    // Note: The round line caps turn this short line into a dot.
+                    let mut builder = Path::builder();
+                    builder.move_to(point(0.0, -0.03));
+                    builder.line_to(point(0.0, 0.03));
+                    builder.close();
+                    builder.build()
+                }
+                EyeStyle::Happy => {
+                    let mut builder = Path::builder();
+                    builder.move_to(point(-0.15, -0.05));
+                    builder.line_to(point(0.0, 0.1));
+                    builder.line_to(point(0.15, -0.05));
+                    builder.close();
+                    builder.build()
+                }
+            },
             EyeState::BlinkClosedFirst | EyeState::BlinkClosedSecond => {
                 let mut builder = Path::builder();
                 builder.move_to(point(-0.15, -0.1));
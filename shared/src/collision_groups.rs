@@ -0,0 +1,83 @@
+use crate::team::TeamId;
+use nphysics2d::ncollide2d::pipeline::CollisionGroups;
+use serde::{Deserialize, Serialize};
+
+const LEVEL_GROUP: usize = 0;
+const PROP_GROUP: usize = 1;
+const PLAYER_GROUP: usize = 2;
+const GHOST_GROUP: usize = 3;
+const TEAM_GROUP_OFFSET: usize = 4;
+const TEAM_WALL_GROUP_OFFSET: usize = TEAM_GROUP_OFFSET + TeamId::MAX_TEAMS;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollisionRole {
+    /// Collides with everything except team-mates.
+    Player,
+
+    /// Passes through all players and props, but still stands on the level.
+    Ghost,
+
+    /// Like a ghost, but also passes through every team-only wall.
+    Spectator,
+}
+
+/// Determines what a player's dango collides with. This is replicated as part of the player's
+/// snapshot since it directly affects the physics simulation.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlayerCollisionGroups {
+    pub role: CollisionRole,
+    pub team: Option<TeamId>,
+}
+
+impl Default for PlayerCollisionGroups {
+    fn default() -> Self {
+        Self {
+            role: CollisionRole::Player,
+            team: None,
+        }
+    }
+}
+
+impl PlayerCollisionGroups {
+    pub fn collision_groups(&self) -> CollisionGroups {
+        let mut membership = vec![];
+        let mut blacklist = vec![];
+
+        match self.role {
+            CollisionRole::Player => {
+                membership.push(PLAYER_GROUP);
+            }
+            CollisionRole::Ghost => {
+                membership.push(GHOST_GROUP);
+                blacklist.extend_from_slice(&[PROP_GROUP, PLAYER_GROUP, GHOST_GROUP]);
+            }
+            CollisionRole::Spectator => {
+                membership.push(GHOST_GROUP);
+                blacklist.extend_from_slice(&[PROP_GROUP, PLAYER_GROUP, GHOST_GROUP]);
+                blacklist.extend((0..TeamId::MAX_TEAMS).map(|i| TEAM_WALL_GROUP_OFFSET + i));
+            }
+        }
+
+        if let Some(team) = self.team {
+            // Team-mates are members of the same team group, and blacklisting our own team group
+            // lets them pass through each other.
+            membership.push(TEAM_GROUP_OFFSET + team.index());
+            blacklist.push(TEAM_GROUP_OFFSET + team.index());
+            blacklist.push(TEAM_WALL_GROUP_OFFSET + team.index());
+        }
+
+        CollisionGroups::new()
+            .with_membership(&membership)
+            .with_blacklist(&blacklist)
+    }
+}
+
+/// Collision groups for level geometry. Team-only walls can only be passed through by members of
+/// the given team.
+pub fn level_collision_groups(passable_by: Option<TeamId>) -> CollisionGroups {
+    match passable_by {
+        Some(team) => CollisionGroups::new()
+            .with_membership(&[LEVEL_GROUP, TEAM_WALL_GROUP_OFFSET + team.index()]),
+        None => CollisionGroups::new().with_membership(&[LEVEL_GROUP]),
+    }
+}
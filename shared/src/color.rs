@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+
+/// An opaque colour in sRGB space. This is what the simulation deals with, so that headless
+/// builds don't need to pull in bevy's renderer just to know what colour everyone is.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct Rgb {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+}
+
+impl Rgb {
+    pub const fn new(r: f32, g: f32, b: f32) -> Self {
+        Self { r, g, b }
+    }
+
+    /// Hue in degrees, saturation and lightness in the range 0 to 1.
+    pub fn hsl(hue: f32, saturation: f32, lightness: f32) -> Self {
+        let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+        let hue_prime = hue.rem_euclid(360.0) / 60.0;
+        let x = chroma * (1.0 - (hue_prime % 2.0 - 1.0).abs());
+        let (r, g, b) = match hue_prime as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+        let m = lightness - chroma * 0.5;
+        Self::new(r + m, g + m, b + m)
+    }
+
+    /// Parses colours written as `#rrggbb`, with the `#` being optional.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.trim_start_matches('#');
+        if hex.len() != 6 {
+            return None;
+        }
+        let channel = |i: usize| {
+            u8::from_str_radix(hex.get(i..i + 2)?, 16)
+                .ok()
+                .map(|value| value as f32 / 255.0)
+        };
+        Some(Self::new(channel(0)?, channel(2)?, channel(4)?))
+    }
+
+    pub fn to_hex(&self) -> String {
+        let channel = |value: f32| (value.max(0.0).min(1.0) * 255.0).round() as u8;
+        format!(
+            "#{:02x}{:02x}{:02x}",
+            channel(self.r),
+            channel(self.g),
+            channel(self.b)
+        )
+    }
+
+    /// Moves this colour towards the other one by the given fraction.
+    pub fn lerp(&self, other: Rgb, t: f32) -> Self {
+        Self::new(
+            self.r + (other.r - self.r) * t,
+            self.g + (other.g - self.g) * t,
+            self.b + (other.b - self.b) * t,
+        )
+    }
+
+    pub fn scaled(&self, factor: f32) -> Self {
+        Self::new(self.r * factor, self.g * factor, self.b * factor)
+    }
+}
+
+#[cfg(feature = "render")]
+impl From<Rgb> for bevy::prelude::Color {
+    fn from(color: Rgb) -> Self {
+        bevy::prelude::Color::rgb(color.r, color.g, color.b)
+    }
+}
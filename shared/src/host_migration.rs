@@ -0,0 +1,38 @@
+use crate::{physics_multiplayer::PhysicsSnapshot, player::PlayerId, session::SessionHash};
+use serde::{Deserialize, Serialize};
+
+/// Where a successor leaves its handoff for the server page it switches over to.
+pub const HANDOFF_KEY: &str = "dango-handoff";
+
+/// Where a successor leaves the secret that proves to the signalling server that it was picked.
+pub const CLAIM_KEY: &str = "dango-claim";
+
+/// The launch parameter that tells a server to take over an existing room rather than open a
+/// new one.
+pub const ROOM_PARAM: &str = "room";
+
+/// The launch parameter that marks the client embedded in the server page, which goes away along
+/// with its host and so can't be a successor.
+pub const HOSTING_PARAM: &str = "hosting";
+
+/// Who takes over hosting the room if the host goes away, and what the world looked like when
+/// they were picked.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Handoff {
+    pub successor: PlayerId,
+
+    /// The successor's session at the time, which the host passes on to the signalling server.
+    /// Only the successor knows the secret behind it, so nobody else can take over the room.
+    pub claim: Option<SessionHash>,
+    pub snapshot: PhysicsSnapshot,
+}
+
+impl Handoff {
+    pub fn to_setting(&self) -> String {
+        base64::encode(bincode::serialize(self).expect("handoff should be serializable"))
+    }
+
+    pub fn from_setting(setting: &str) -> Option<Self> {
+        bincode::deserialize(&base64::decode(setting).ok()?).ok()
+    }
+}
@@ -0,0 +1,420 @@
+use bevy::prelude::*;
+
+/// Something a player can do with their dango, whichever key or button it happens to be on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InputAction {
+    Left,
+    Right,
+    Jump,
+    Roll,
+    SwitchTeam,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AxisDirection {
+    Negative,
+    Positive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GamepadInput {
+    Button(GamepadButtonType),
+
+    /// Pushing an axis part of the way only does the action part of the way, e.g. creeping along
+    /// rather than rolling at full speed.
+    Axis(GamepadAxisType, AxisDirection),
+}
+
+/// Lets bindings be saved as settings, and typed in by hand into the settings file.
+pub trait BindingName: Sized {
+    fn name(&self) -> String;
+    fn from_name(name: &str) -> Option<Self>;
+}
+
+/// Which inputs do which action. An action can have any number of inputs, and holding any one of
+/// them is enough.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionBindings<T>(pub Vec<(InputAction, T)>);
+
+/// Every input that local players can use. The first two players split the keyboard between
+/// them, and everyone shares the same gamepad bindings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputBindings {
+    /// WASD by default.
+    pub first_keys: ActionBindings<KeyCode>,
+
+    /// The arrow keys by default.
+    pub second_keys: ActionBindings<KeyCode>,
+
+    pub gamepad: ActionBindings<GamepadInput>,
+}
+
+impl InputAction {
+    pub const ALL: [InputAction; 5] = [
+        InputAction::Left,
+        InputAction::Right,
+        InputAction::Jump,
+        InputAction::Roll,
+        InputAction::SwitchTeam,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            InputAction::Left => "left",
+            InputAction::Right => "right",
+            InputAction::Jump => "jump",
+            InputAction::Roll => "roll",
+            InputAction::SwitchTeam => "switch-team",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        InputAction::ALL
+            .iter()
+            .copied()
+            .find(|action| action.name() == name)
+    }
+}
+
+impl<T: BindingName> ActionBindings<T> {
+    pub fn bound_to(&self, action: InputAction) -> impl Iterator<Item = &T> {
+        self.0
+            .iter()
+            .filter(move |(bound_action, _)| *bound_action == action)
+            .map(|(_, input)| input)
+    }
+
+    /// Writes out the bindings as e.g. `left=A right=D jump=W jump=Space`.
+    pub fn to_setting(&self) -> String {
+        self.0
+            .iter()
+            .map(|(action, input)| format!("{}={}", action.name(), input.name()))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    /// Reads back what `to_setting` wrote. Returns `None` if any of it doesn't make sense, so
+    /// that a typo doesn't leave a player with half of their controls missing.
+    pub fn from_setting(setting: &str) -> Option<Self> {
+        setting
+            .split_whitespace()
+            .map(|binding| {
+                let index = binding.find('=')?;
+                Some((
+                    InputAction::from_name(&binding[..index])?,
+                    T::from_name(&binding[index + 1..])?,
+                ))
+            })
+            .collect::<Option<Vec<(InputAction, T)>>>()
+            .map(ActionBindings)
+    }
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        use GamepadAxisType::*;
+        use GamepadButtonType::*;
+        use InputAction::*;
+        Self {
+            first_keys: ActionBindings(vec![
+                (Left, KeyCode::A),
+                (Right, KeyCode::D),
+                (Jump, KeyCode::W),
+                (Jump, KeyCode::Space),
+                (Roll, KeyCode::LShift),
+                (SwitchTeam, KeyCode::T),
+            ]),
+            second_keys: ActionBindings(vec![
+                (Left, KeyCode::Left),
+                (Right, KeyCode::Right),
+                (Jump, KeyCode::Up),
+                (Roll, KeyCode::RShift),
+                (SwitchTeam, KeyCode::Return),
+            ]),
+            gamepad: ActionBindings(vec![
+                (
+                    Left,
+                    GamepadInput::Axis(LeftStickX, AxisDirection::Negative),
+                ),
+                (Left, GamepadInput::Axis(DPadX, AxisDirection::Negative)),
+                (Left, GamepadInput::Button(DPadLeft)),
+                (
+                    Right,
+                    GamepadInput::Axis(LeftStickX, AxisDirection::Positive),
+                ),
+                (Right, GamepadInput::Axis(DPadX, AxisDirection::Positive)),
+                (Right, GamepadInput::Button(DPadRight)),
+                (Jump, GamepadInput::Button(South)),
+                (Jump, GamepadInput::Button(North)),
+                (Roll, GamepadInput::Button(West)),
+                (Roll, GamepadInput::Button(East)),
+                (SwitchTeam, GamepadInput::Button(Select)),
+            ]),
+        }
+    }
+}
+
+/// Names enum variants after themselves, so that the settings file reads like the Bevy docs.
+macro_rules! variant_names {
+    ($type:ident { $($variant:ident),* $(,)? }) => {
+        impl BindingName for $type {
+            fn name(&self) -> String {
+                match self {
+                    $($type::$variant => stringify!($variant).to_string(),)*
+                    // Note: Anything missing from the list gets written out under a name that
+                    // doesn't read back, and whoever loads it falls back to the defaults.
+                    #[allow(unreachable_patterns)]
+                    _ => format!("{:?}", self),
+                }
+            }
+
+            fn from_name(name: &str) -> Option<Self> {
+                match name {
+                    $(stringify!($variant) => Some($type::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+variant_names!(KeyCode {
+    Key1,
+    Key2,
+    Key3,
+    Key4,
+    Key5,
+    Key6,
+    Key7,
+    Key8,
+    Key9,
+    Key0,
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+    G,
+    H,
+    I,
+    J,
+    K,
+    L,
+    M,
+    N,
+    O,
+    P,
+    Q,
+    R,
+    S,
+    T,
+    U,
+    V,
+    W,
+    X,
+    Y,
+    Z,
+    Escape,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    Insert,
+    Home,
+    Delete,
+    End,
+    PageDown,
+    PageUp,
+    Snapshot,
+    Scroll,
+    Pause,
+    Left,
+    Up,
+    Right,
+    Down,
+    Back,
+    Return,
+    Space,
+    Numlock,
+    Numpad0,
+    Numpad1,
+    Numpad2,
+    Numpad3,
+    Numpad4,
+    Numpad5,
+    Numpad6,
+    Numpad7,
+    Numpad8,
+    Numpad9,
+    NumpadAdd,
+    NumpadSubtract,
+    NumpadMultiply,
+    NumpadDivide,
+    NumpadDecimal,
+    NumpadEnter,
+    Apostrophe,
+    Apps,
+    Backslash,
+    Comma,
+    Equals,
+    Grave,
+    LBracket,
+    Minus,
+    Oem102,
+    Period,
+    RBracket,
+    Semicolon,
+    Slash,
+    Tab,
+    Capital,
+    LAlt,
+    LControl,
+    LShift,
+    LWin,
+    RAlt,
+    RControl,
+    RShift,
+    RWin,
+});
+
+variant_names!(GamepadButtonType {
+    South,
+    East,
+    North,
+    West,
+    C,
+    Z,
+    LeftTrigger,
+    LeftTrigger2,
+    RightTrigger,
+    RightTrigger2,
+    Select,
+    Start,
+    Mode,
+    LeftThumb,
+    RightThumb,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+});
+
+variant_names!(GamepadAxisType {
+    LeftStickX,
+    LeftStickY,
+    LeftZ,
+    RightStickX,
+    RightStickY,
+    RightZ,
+    DPadX,
+    DPadY,
+});
+
+/// Buttons go by their own names, and axes get a `+` or `-` on the end, e.g. `LeftStickX-` for
+/// pushing the left stick to the left.
+impl BindingName for GamepadInput {
+    fn name(&self) -> String {
+        match self {
+            GamepadInput::Button(button_type) => button_type.name(),
+            GamepadInput::Axis(axis_type, AxisDirection::Negative) => {
+                format!("{}-", axis_type.name())
+            }
+            GamepadInput::Axis(axis_type, AxisDirection::Positive) => {
+                format!("{}+", axis_type.name())
+            }
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        if let Some(axis_name) = name.strip_suffix('-') {
+            GamepadAxisType::from_name(axis_name)
+                .map(|axis_type| GamepadInput::Axis(axis_type, AxisDirection::Negative))
+        } else if let Some(axis_name) = name.strip_suffix('+') {
+            GamepadAxisType::from_name(axis_name)
+                .map(|axis_type| GamepadInput::Axis(axis_type, AxisDirection::Positive))
+        } else {
+            GamepadButtonType::from_name(name).map(GamepadInput::Button)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_bindings_round_trip_through_settings() {
+        let bindings = InputBindings::default();
+        assert_eq!(
+            ActionBindings::<KeyCode>::from_setting(&bindings.first_keys.to_setting()),
+            Some(bindings.first_keys.clone())
+        );
+        assert_eq!(
+            ActionBindings::<KeyCode>::from_setting(&bindings.second_keys.to_setting()),
+            Some(bindings.second_keys.clone())
+        );
+        assert_eq!(
+            ActionBindings::<GamepadInput>::from_setting(&bindings.gamepad.to_setting()),
+            Some(bindings.gamepad.clone())
+        );
+    }
+
+    #[test]
+    fn reads_hand_written_settings() {
+        assert_eq!(
+            ActionBindings::<KeyCode>::from_setting("left=J  right=L\njump=Space"),
+            Some(ActionBindings(vec![
+                (InputAction::Left, KeyCode::J),
+                (InputAction::Right, KeyCode::L),
+                (InputAction::Jump, KeyCode::Space),
+            ]))
+        );
+        assert_eq!(
+            ActionBindings::<GamepadInput>::from_setting("left=LeftStickX- roll=West"),
+            Some(ActionBindings(vec![
+                (
+                    InputAction::Left,
+                    GamepadInput::Axis(GamepadAxisType::LeftStickX, AxisDirection::Negative)
+                ),
+                (
+                    InputAction::Roll,
+                    GamepadInput::Button(GamepadButtonType::West)
+                ),
+            ]))
+        );
+    }
+
+    #[test]
+    fn reads_back_keys_the_settings_page_can_pick() {
+        for name in &["Oem102", "Numlock", "Scroll", "Apps", "Snapshot", "Pause"] {
+            let key_code = KeyCode::from_name(name).expect(name);
+            assert_eq!(&key_code.name(), name);
+        }
+    }
+
+    #[test]
+    fn rejects_settings_with_any_mistake() {
+        assert_eq!(
+            ActionBindings::<KeyCode>::from_setting("left=A right=Dee"),
+            None
+        );
+        assert_eq!(
+            ActionBindings::<KeyCode>::from_setting("left=A fly=Space"),
+            None
+        );
+        assert_eq!(ActionBindings::<KeyCode>::from_setting("left"), None);
+        assert_eq!(
+            ActionBindings::<GamepadInput>::from_setting("left=LeftStickW-"),
+            None
+        );
+    }
+}
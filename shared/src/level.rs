@@ -0,0 +1,122 @@
+use crate::{
+    collision_groups::level_collision_groups, settings::RealField, surface::SurfaceMaterial,
+    team::TeamId,
+};
+use nphysics2d::{
+    nalgebra::Vector2,
+    ncollide2d::shape::{Cuboid, ShapeHandle},
+    object::{
+        BodyPartHandle, ColliderDesc, DefaultBodyHandle, DefaultColliderHandle, DefaultColliderSet,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LevelId {
+    Meadow,
+    Playground,
+}
+
+/// A static axis-aligned box of level geometry.
+#[derive(Debug, Clone)]
+pub struct LevelSurface {
+    pub half_extents: Vector2<RealField>,
+    pub translation: Vector2<RealField>,
+    pub material: SurfaceMaterial,
+
+    /// Makes this a team-only wall that members of the given team can pass through.
+    pub passable_by: Option<TeamId>,
+}
+
+impl Default for LevelId {
+    fn default() -> Self {
+        LevelId::Meadow
+    }
+}
+
+impl LevelId {
+    pub const ALL: [LevelId; 2] = [LevelId::Meadow, LevelId::Playground];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LevelId::Meadow => "meadow",
+            LevelId::Playground => "playground",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<LevelId> {
+        LevelId::ALL
+            .iter()
+            .copied()
+            .find(|level| level.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn surfaces(&self) -> Vec<LevelSurface> {
+        match self {
+            LevelId::Meadow => vec![LevelSurface::ground(
+                -500.0,
+                500.0,
+                SurfaceMaterial::DEFAULT,
+            )],
+            LevelId::Playground => vec![
+                LevelSurface::ground(-500.0, -15.0, SurfaceMaterial::DEFAULT),
+                LevelSurface::ground(-15.0, -5.0, SurfaceMaterial::STICKY),
+                LevelSurface::ground(-5.0, 5.0, SurfaceMaterial::DEFAULT),
+                LevelSurface::ground(5.0, 15.0, SurfaceMaterial::ICE),
+                LevelSurface::ground(15.0, 500.0, SurfaceMaterial::DEFAULT),
+                // Trampoline.
+                LevelSurface {
+                    half_extents: Vector2::new(1.5, 0.25),
+                    translation: Vector2::new(22.0, 0.25),
+                    material: SurfaceMaterial::BOUNCY,
+                    passable_by: None,
+                },
+                LevelSurface::team_wall(-30.0, TeamId(0)),
+                LevelSurface::team_wall(35.0, TeamId(1)),
+            ],
+        }
+    }
+
+    pub fn build(
+        &self,
+        ground: DefaultBodyHandle,
+        colliders: &mut DefaultColliderSet<RealField>,
+    ) -> Vec<DefaultColliderHandle> {
+        self.surfaces()
+            .iter()
+            .map(|surface| {
+                colliders.insert(
+                    ColliderDesc::<RealField>::new(ShapeHandle::new(Cuboid::new(
+                        surface.half_extents,
+                    )))
+                    .translation(surface.translation)
+                    .material(surface.material.material_handle())
+                    .collision_groups(level_collision_groups(surface.passable_by))
+                    .user_data(surface.material)
+                    .build(BodyPartHandle(ground, 0)),
+                )
+            })
+            .collect()
+    }
+}
+
+impl LevelSurface {
+    /// A 10 unit thick slab of ground spanning the given x range, with its top surface at y = 0.
+    fn ground(left: RealField, right: RealField, material: SurfaceMaterial) -> Self {
+        Self {
+            half_extents: Vector2::new((right - left) * 0.5, 5.0),
+            translation: Vector2::new((right + left) * 0.5, -5.0),
+            material,
+            passable_by: None,
+        }
+    }
+
+    fn team_wall(x: RealField, team: TeamId) -> Self {
+        Self {
+            half_extents: Vector2::new(0.25, 4.0),
+            translation: Vector2::new(x, 4.0),
+            material: SurfaceMaterial::DEFAULT,
+            passable_by: Some(team),
+        }
+    }
+}
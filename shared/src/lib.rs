@@ -1,10 +1,33 @@
 #![feature(generic_associated_types)]
 
+pub mod admin;
+pub mod appearance;
+#[cfg(feature = "render")]
 pub mod blinking_eyes;
+#[cfg(feature = "render")]
 pub mod camera_2point5d;
+pub mod collision_groups;
+pub mod color;
+pub mod host_migration;
+pub mod input_bindings;
+pub mod level;
+pub mod npc;
+pub mod palette;
 pub mod physics_multiplayer;
+pub mod physics_multiplayer_server;
+#[cfg(feature = "render")]
 pub mod physics_multiplayer_systems;
+pub mod physics_offline;
+pub mod platform;
+#[cfg(not(feature = "web"))]
+pub mod platform_native;
+#[cfg(feature = "web")]
+pub mod platform_web;
 pub mod player;
 pub mod player_input;
+pub mod session;
 pub mod settings;
+pub mod signalling;
+pub mod surface;
+pub mod team;
 pub mod wasm_print_diagnostics_plugin;
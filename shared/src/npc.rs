@@ -0,0 +1,339 @@
+use crate::{
+    appearance::PlayerAppearance,
+    physics_multiplayer::{PhysicsCommand, PhysicsWorld},
+    player::{PlayerDisplayState, PlayerId, PlayerInputCommand, PlayerInputState},
+    settings::RealField,
+};
+use bevy::prelude::*;
+use crystalorb_bevy_networking_turbulence::{
+    bevy_networking_turbulence::NetworkResource, crystalorb::server::Server, WrappedNetworkResource,
+};
+use rand::prelude::*;
+use std::{cmp::Ordering, collections::HashMap};
+
+const NPC_NICKNAMES: [&str; 8] = [
+    "mochi",
+    "kinako",
+    "anko",
+    "yomogi",
+    "mitarashi",
+    "goma",
+    "sakura",
+    "matcha",
+];
+
+/// How long to wait before asking to join again, e.g. while the room is full.
+const NPC_JOIN_RETRY_INTERVAL: f64 = 3.0;
+
+/// How long a wanderer sticks with a direction, in seconds.
+const MIN_WANDER_DURATION: f64 = 0.5;
+const MAX_WANDER_DURATION: f64 = 2.5;
+
+const JUMP_DURATION: f64 = 0.3;
+
+/// How close a follower tries to stay to the player it follows.
+const FOLLOW_DISTANCE: RealField = 1.5;
+
+/// A dango that has been pushing in one direction for this long, yet barely moves, is taken to
+/// be up against an obstacle.
+const BLOCKED_DELAY: f64 = 0.5;
+const BLOCKED_SPEED: RealField = 0.2;
+
+/// A node in an NPC's behaviour tree. The tree is run from the root every tick, and the actions
+/// it reaches decide which inputs the NPC presses.
+#[derive(Debug, Clone)]
+pub enum BehaviourNode {
+    /// Runs its children in turn until one succeeds.
+    Selector(Vec<BehaviourNode>),
+
+    /// Runs its children in turn until one fails.
+    Sequence(Vec<BehaviourNode>),
+
+    /// Runs its child, and succeeds whether or not the child did.
+    Optional(Box<BehaviourNode>),
+
+    Condition(NpcCondition),
+    Action(NpcAction),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum NpcCondition {
+    /// Another team's human player is within this distance. They become the target.
+    OpponentWithin(RealField),
+
+    /// A human player is within this distance. They become the target.
+    PlayerWithin(RealField),
+
+    /// The NPC has been walking into something.
+    Blocked,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum NpcAction {
+    Wander,
+    Follow,
+    Flee,
+    Jump,
+}
+
+/// What an NPC keeps track of from one tick to the next.
+#[derive(Default)]
+struct NpcMemory {
+    target: Option<PlayerId>,
+    direction: i8,
+    direction_changed_at: f64,
+    next_wander_change: f64,
+    jump_until: f64,
+}
+
+/// What an NPC can see of the world while its behaviour tree runs.
+struct NpcSenses<'a> {
+    now: f64,
+    player_id: PlayerId,
+    me: &'a PlayerDisplayState,
+    players: &'a HashMap<PlayerId, PlayerDisplayState>,
+}
+
+pub struct Npc {
+    pub player_id: PlayerId,
+    appearance: PlayerAppearance,
+    behaviour: BehaviourNode,
+    memory: NpcMemory,
+    inputs: PlayerInputState,
+    last_join_request: Option<f64>,
+}
+
+/// The dangos that the server plays itself. Each one joins and moves through the same commands
+/// as a human player, so clients can't tell them apart.
+#[derive(Default)]
+pub struct Npcs(pub Vec<Npc>);
+
+impl BehaviourNode {
+    /// Flees from nearby opponents, tags along with nearby players, and otherwise wanders about,
+    /// hopping over whatever gets in the way.
+    pub fn default_tree() -> Self {
+        use BehaviourNode::*;
+        Sequence(vec![
+            Selector(vec![
+                Sequence(vec![
+                    Condition(NpcCondition::OpponentWithin(4.0)),
+                    Action(NpcAction::Flee),
+                ]),
+                Sequence(vec![
+                    Condition(NpcCondition::PlayerWithin(12.0)),
+                    Action(NpcAction::Follow),
+                ]),
+                Action(NpcAction::Wander),
+            ]),
+            Optional(Box::new(Sequence(vec![
+                Condition(NpcCondition::Blocked),
+                Action(NpcAction::Jump),
+            ]))),
+        ])
+    }
+
+    /// Returns whether the node succeeded.
+    fn run(&self, senses: &NpcSenses, memory: &mut NpcMemory, direction: &mut i8) -> bool {
+        match self {
+            BehaviourNode::Selector(children) => children
+                .iter()
+                .any(|child| child.run(senses, memory, direction)),
+            BehaviourNode::Sequence(children) => children
+                .iter()
+                .all(|child| child.run(senses, memory, direction)),
+            BehaviourNode::Optional(child) => {
+                child.run(senses, memory, direction);
+                true
+            }
+            BehaviourNode::Condition(condition) => condition.check(senses, memory),
+            BehaviourNode::Action(action) => {
+                action.perform(senses, memory, direction);
+                true
+            }
+        }
+    }
+}
+
+impl NpcCondition {
+    fn check(&self, senses: &NpcSenses, memory: &mut NpcMemory) -> bool {
+        match self {
+            NpcCondition::OpponentWithin(distance) => {
+                memory.target = senses.nearest_human(*distance, |player| {
+                    player.team.is_some() && player.team != senses.me.team
+                });
+                memory.target.is_some()
+            }
+            NpcCondition::PlayerWithin(distance) => {
+                memory.target = senses.nearest_human(*distance, |_| true);
+                memory.target.is_some()
+            }
+            NpcCondition::Blocked => {
+                memory.direction != 0
+                    && senses.now - memory.direction_changed_at > BLOCKED_DELAY
+                    && senses.me.measurements.velocity.x.abs() < BLOCKED_SPEED
+            }
+        }
+    }
+}
+
+impl NpcAction {
+    fn perform(&self, senses: &NpcSenses, memory: &mut NpcMemory, direction: &mut i8) {
+        match self {
+            NpcAction::Wander => {
+                if senses.now >= memory.next_wander_change {
+                    let mut rng = rand::thread_rng();
+                    memory.next_wander_change =
+                        senses.now + rng.gen_range(MIN_WANDER_DURATION..MAX_WANDER_DURATION);
+                    *direction = rng.gen_range(-1..=1);
+                } else {
+                    *direction = memory.direction;
+                }
+            }
+            NpcAction::Follow | NpcAction::Flee => {
+                let offset = memory
+                    .target
+                    .and_then(|target| senses.players.get(&target))
+                    .map_or(0.0, |target| {
+                        target.measurements.center_of_mass.x
+                            - senses.me.measurements.center_of_mass.x
+                    });
+                *direction = match self {
+                    NpcAction::Follow if offset > FOLLOW_DISTANCE => 1,
+                    NpcAction::Follow if offset < -FOLLOW_DISTANCE => -1,
+                    NpcAction::Follow => 0,
+                    _ if offset > 0.0 => -1,
+                    _ => 1,
+                };
+                // Note: Pick a fresh direction once there is nobody left to follow or flee from.
+                memory.next_wander_change = senses.now;
+            }
+            NpcAction::Jump => memory.jump_until = senses.now + JUMP_DURATION,
+        }
+    }
+}
+
+impl<'a> NpcSenses<'a> {
+    fn nearest_human(
+        &self,
+        max_distance: RealField,
+        filter: impl Fn(&PlayerDisplayState) -> bool,
+    ) -> Option<PlayerId> {
+        let position = self.me.measurements.center_of_mass;
+        self.players
+            .iter()
+            .filter(|(player_id, player)| {
+                **player_id != self.player_id && !player_id.is_npc() && filter(player)
+            })
+            .map(|(player_id, player)| {
+                (
+                    *player_id,
+                    (player.measurements.center_of_mass - position).norm(),
+                )
+            })
+            // Note: A dango that blew up into NaNs is nowhere, so it can't be the nearest.
+            .filter(|(_, distance)| distance.is_finite() && *distance <= max_distance)
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+            .map(|(player_id, _)| player_id)
+    }
+}
+
+impl Npc {
+    pub fn new(index: usize, behaviour: BehaviourNode) -> Self {
+        Self {
+            player_id: PlayerId::npc(index),
+            appearance: PlayerAppearance {
+                nickname: NPC_NICKNAMES[index % NPC_NICKNAMES.len()].to_string(),
+                size: rand::thread_rng()
+                    .gen_range(PlayerAppearance::MIN_SIZE..PlayerAppearance::MAX_SIZE),
+                ..Default::default()
+            },
+            behaviour,
+            memory: NpcMemory::default(),
+            inputs: PlayerInputState::default(),
+            last_join_request: None,
+        }
+    }
+
+    fn decide(&mut self, senses: &NpcSenses) -> PlayerInputState {
+        let mut direction = 0;
+        self.behaviour.run(senses, &mut self.memory, &mut direction);
+        if direction != self.memory.direction {
+            self.memory.direction = direction;
+            self.memory.direction_changed_at = senses.now;
+        }
+        PlayerInputState {
+            horizontal: direction * PlayerInputState::MAX_HORIZONTAL,
+            jump: senses.now < self.memory.jump_until,
+            roll: false,
+        }
+    }
+}
+
+impl Npcs {
+    pub fn new(count: usize) -> Self {
+        Self(
+            (0..count)
+                .map(|index| Npc::new(index, BehaviourNode::default_tree()))
+                .collect(),
+        )
+    }
+}
+
+pub fn npc_system(
+    mut npcs: ResMut<Npcs>,
+    time: Res<Time>,
+    mut server: ResMut<Server<PhysicsWorld>>,
+    mut net: ResMut<NetworkResource>,
+) {
+    if npcs.0.is_empty() {
+        return;
+    }
+    let now = time.seconds_since_startup();
+    // Note: Look at the display state in place, and only issue commands once done with it.
+    let mut commands = Vec::new();
+    let display_state = server.display_state();
+    let players = display_state.players();
+    for npc in npcs.0.iter_mut() {
+        let me = match players.get(&npc.player_id) {
+            Some(me) => me,
+            None => {
+                let should_request = npc.last_join_request.map_or(true, |last_request| {
+                    now - last_request > NPC_JOIN_RETRY_INTERVAL
+                });
+                if should_request {
+                    npc.last_join_request = Some(now);
+                    npc.inputs = PlayerInputState::default();
+                    commands.push(PhysicsCommand::Join {
+                        player_id: npc.player_id,
+                        requested_appearance: npc.appearance.clone(),
+                        reclaimed_session: None,
+                        next_session: None,
+                        can_host: false,
+                    });
+                }
+                continue;
+            }
+        };
+
+        let senses = NpcSenses {
+            now,
+            player_id: npc.player_id,
+            me,
+            players,
+        };
+        let next_inputs = npc.decide(&senses);
+        // Note: The server's own commands can't get lost on the way to itself, and clients catch
+        // up from its snapshots anyway, so NPCs only send their inputs when they change.
+        if next_inputs != npc.inputs {
+            commands.push(PhysicsCommand::PlayerInput {
+                player_id: npc.player_id,
+                command: PlayerInputCommand::new(&next_inputs),
+            });
+            npc.inputs = next_inputs;
+        }
+    }
+
+    for command in commands {
+        server.issue_command(command, &mut WrappedNetworkResource(&mut *net));
+    }
+}
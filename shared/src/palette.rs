@@ -0,0 +1,196 @@
+use crate::color::Rgb;
+use serde::{Deserialize, Serialize};
+
+/// Which set of colours dangos get allocated from.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Palette {
+    /// Any requested colour is allowed, as long as it is distinct from everyone else's.
+    Standard,
+
+    /// Colours are restricted to ones that stay distinguishable with the common forms of colour
+    /// blindness.
+    ColorBlindSafe,
+}
+
+/// The original four dango colours, followed by Kelly's colours of maximum contrast.
+const STANDARD_COLORS: [[f32; 3]; 22] = [
+    [1.0, 0.3, 0.3],
+    [0.3, 0.8, 0.4],
+    [1.0, 0.8, 0.3],
+    [0.3, 0.6, 1.0],
+    [0.53, 0.34, 0.57],
+    [0.95, 0.52, 0.0],
+    [0.63, 0.79, 0.95],
+    [0.75, 0.0, 0.2],
+    [0.76, 0.7, 0.5],
+    [0.52, 0.52, 0.51],
+    [0.0, 0.53, 0.34],
+    [0.9, 0.56, 0.67],
+    [0.0, 0.4, 0.65],
+    [0.98, 0.58, 0.47],
+    [0.38, 0.31, 0.59],
+    [0.96, 0.65, 0.0],
+    [0.7, 0.27, 0.42],
+    [0.86, 0.83, 0.0],
+    [0.53, 0.18, 0.09],
+    [0.55, 0.71, 0.0],
+    [0.4, 0.27, 0.13],
+    [0.89, 0.35, 0.13],
+];
+
+/// The Okabe-Ito palette, without black since that is used for the eyes.
+const COLOR_BLIND_SAFE_COLORS: [[f32; 3]; 7] = [
+    [0.9, 0.62, 0.0],
+    [0.34, 0.71, 0.91],
+    [0.0, 0.62, 0.45],
+    [0.94, 0.89, 0.26],
+    [0.0, 0.45, 0.7],
+    [0.84, 0.37, 0.0],
+    [0.8, 0.47, 0.65],
+];
+
+/// Colours closer than this (CIE76 delta E) are considered duplicates.
+pub const MIN_COLOR_DISTANCE: f32 = 20.0;
+
+/// How many extra hues the standard palette generates once its fixed colours run out.
+const GENERATED_HUES: usize = 36;
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::Standard
+    }
+}
+
+impl Palette {
+    pub const ALL: [Palette; 2] = [Palette::Standard, Palette::ColorBlindSafe];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Palette::Standard => "standard",
+            Palette::ColorBlindSafe => "colorblind",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Palette> {
+        Palette::ALL
+            .iter()
+            .copied()
+            .find(|palette| palette.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn colors(&self) -> Vec<Rgb> {
+        let colors: &[[f32; 3]] = match self {
+            Palette::Standard => &STANDARD_COLORS,
+            Palette::ColorBlindSafe => &COLOR_BLIND_SAFE_COLORS,
+        };
+        colors
+            .iter()
+            .map(|[r, g, b]| Rgb::new(*r, *g, *b))
+            .collect()
+    }
+
+    /// Resolves the colour a player asked for against the colours already in use. The requested
+    /// colour is kept when the palette allows it and nobody else looks similar, otherwise the
+    /// most distinct colour from the palette is handed out instead. Since this only looks at who
+    /// is currently playing, colours are reclaimed as soon as their owner leaves.
+    pub fn allocate(&self, requested_color: Rgb, colors_in_use: &[Rgb]) -> Rgb {
+        match self {
+            Palette::Standard => {
+                if is_color_available(requested_color, colors_in_use) {
+                    return requested_color;
+                }
+            }
+            Palette::ColorBlindSafe => {
+                let nearest = self.colors().into_iter().min_by(|a, b| {
+                    color_distance(*a, requested_color)
+                        .partial_cmp(&color_distance(*b, requested_color))
+                        .unwrap()
+                });
+                if let Some(nearest) = nearest {
+                    if is_color_available(nearest, colors_in_use) {
+                        return nearest;
+                    }
+                }
+            }
+        }
+
+        let mut candidates = self.colors();
+        if let Some(free_color) = candidates
+            .iter()
+            .copied()
+            .find(|color| is_color_available(*color, colors_in_use))
+        {
+            return free_color;
+        }
+
+        // Everything in the palette is taken, so settle for whatever stands out the most.
+        if *self == Palette::Standard {
+            candidates.extend(generated_colors());
+        }
+        candidates
+            .into_iter()
+            .map(|color| (color, min_distance(color, colors_in_use)))
+            .fold(
+                None,
+                |best: Option<(Rgb, f32)>, (color, distance)| match best {
+                    Some((_, best_distance)) if best_distance >= distance => best,
+                    _ => Some((color, distance)),
+                },
+            )
+            .map_or(requested_color, |(color, _)| color)
+    }
+}
+
+/// Evenly spaced hues at a couple of lightness levels.
+fn generated_colors() -> impl Iterator<Item = Rgb> {
+    [0.65, 0.45].iter().flat_map(|lightness| {
+        (0..GENERATED_HUES)
+            .map(move |i| Rgb::hsl(360.0 * i as f32 / GENERATED_HUES as f32, 0.75, *lightness))
+    })
+}
+
+fn is_color_available(color: Rgb, colors_in_use: &[Rgb]) -> bool {
+    min_distance(color, colors_in_use) >= MIN_COLOR_DISTANCE
+}
+
+fn min_distance(color: Rgb, colors_in_use: &[Rgb]) -> f32 {
+    colors_in_use
+        .iter()
+        .map(|other| color_distance(color, *other))
+        .fold(f32::INFINITY, f32::min)
+}
+
+/// Perceptual distance between two colours, measured in CIELAB space.
+pub fn color_distance(a: Rgb, b: Rgb) -> f32 {
+    let [l1, a1, b1] = to_lab(a);
+    let [l2, a2, b2] = to_lab(b);
+    ((l1 - l2).powi(2) + (a1 - a2).powi(2) + (b1 - b2).powi(2)).sqrt()
+}
+
+fn to_lab(color: Rgb) -> [f32; 3] {
+    let linearize = |c: f32| {
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    let r = linearize(color.r);
+    let g = linearize(color.g);
+    let b = linearize(color.b);
+
+    // Note: Relative to the D65 white point.
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.9505;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.089;
+
+    let f = |t: f32| {
+        if t > 0.008856 {
+            t.cbrt()
+        } else {
+            7.787 * t + 16.0 / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
@@ -1,7 +1,14 @@
 use crate::{
+    appearance::PlayerAppearance,
+    collision_groups::{CollisionRole, PlayerCollisionGroups},
+    host_migration::Handoff,
+    level::LevelId,
+    palette::Palette,
     player::{Player, PlayerDisplayState, PlayerId, PlayerInputCommand, PlayerSnapshot},
+    session::{SessionHash, SessionSecret},
     settings,
     settings::RealField,
+    team::TeamId,
 };
 use bevy::prelude::*;
 use crystalorb_bevy_networking_turbulence::crystalorb::{
@@ -13,12 +20,17 @@ use nphysics2d::{
     force_generator::DefaultForceGeneratorSet,
     joint::DefaultJointConstraintSet,
     nalgebra::Vector2,
-    ncollide2d::shape::{Cuboid, ShapeHandle},
-    object::{BodyPartHandle, ColliderDesc, DefaultBodySet, DefaultColliderSet, Ground},
+    object::{
+        DefaultBodyHandle, DefaultBodySet, DefaultColliderHandle, DefaultColliderSet, Ground,
+    },
     world::{DefaultGeometricalWorld, DefaultMechanicalWorld},
 };
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fmt::Debug};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    sync::Arc,
+};
 
 pub struct PhysicsWorld {
     mechanical_world: DefaultMechanicalWorld<RealField>,
@@ -27,7 +39,17 @@ pub struct PhysicsWorld {
     colliders: DefaultColliderSet<RealField>,
     joint_constraints: DefaultJointConstraintSet<RealField>,
     force_generators: DefaultForceGeneratorSet<RealField>,
+    ground: DefaultBodyHandle,
+    level: LevelId,
+    level_colliders: Vec<DefaultColliderHandle>,
+    palette: Palette,
+    max_players: usize,
     players: HashMap<PlayerId, Player>,
+    handoff: Option<Arc<Handoff>>,
+
+    /// Connections that the host kicked out, whose join requests get ignored from then on.
+    turned_away_clients: HashSet<usize>,
+    banned_sessions: HashSet<SessionHash>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -38,56 +60,265 @@ pub enum PhysicsCommand {
     },
     SpawnPlayer {
         player_id: PlayerId,
-        color: Color,
-        size: f32,
+        appearance: PlayerAppearance,
         x: RealField,
         y: RealField,
+        collision_groups: PlayerCollisionGroups,
     },
     DespawnPlayer(PlayerId),
+
+    /// The player's client dropped out. Their dango waits for a while in case they come back.
+    Leave(PlayerId),
+    Join {
+        player_id: PlayerId,
+        requested_appearance: PlayerAppearance,
+
+        /// Takes over the dango left behind by whoever joined with this secret's hash.
+        reclaimed_session: Option<SessionSecret>,
+
+        /// Lets this client reclaim the dango if it drops out.
+        next_session: Option<SessionHash>,
+
+        /// Whether this client could take over hosting the room.
+        can_host: bool,
+    },
+    SetCollisionGroups {
+        player_id: PlayerId,
+        collision_groups: PlayerCollisionGroups,
+    },
+    RequestTeamSwitch {
+        player_id: PlayerId,
+        team: TeamId,
+    },
+    AwardPoints {
+        player_id: PlayerId,
+        points: i32,
+    },
+    SetPalette(Palette),
+    SetLevel(LevelId),
+    SetMaxPlayers(usize),
+
+    /// Picks who takes over if the host goes away. Everyone snapshots their own copy of the world
+    /// at the same step, so the snapshot never needs to go over the wire.
+    DesignateSuccessor(PlayerId),
+
+    /// Brings back everyone from a previous host's world, waiting for their clients to reclaim
+    /// them.
+    Restore(PhysicsSnapshot),
+
+    /// Removes every dango of the player's client, and ignores its join requests from then on. The
+    /// client itself stays connected, since there is no way to hang up on it.
+    Kick(PlayerId),
+
+    /// Kicks the player, and turns away anyone trying to come back with their session.
+    Ban(PlayerId),
+
+    /// Puts everyone back at the spawn point, keeping their colours, teams and scores.
+    ResetPositions,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct PhysicsSnapshot {
+    level: LevelId,
+    palette: Palette,
+    max_players: usize,
     players: HashMap<PlayerId, PlayerSnapshot>,
+    turned_away_clients: HashSet<usize>,
+    banned_sessions: HashSet<SessionHash>,
 }
 
 //#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 #[derive(Clone, Default, Debug)]
 pub struct PhysicsDisplayState {
     players: HashMap<PlayerId, PlayerDisplayState>,
+    max_players: usize,
+    handoff: Option<Arc<Handoff>>,
+    turned_away_clients: HashSet<usize>,
 }
 
 impl Default for PhysicsWorld {
     fn default() -> Self {
+        let mut bodies = DefaultBodySet::<RealField>::new();
+        let ground = bodies.insert(Ground::new());
         let mut physics_world = Self {
             mechanical_world: DefaultMechanicalWorld::<RealField>::new(Vector2::new(
                 0.0,
                 settings::GRAVITY,
             )),
             geometrical_world: DefaultGeometricalWorld::<RealField>::new(),
-            bodies: DefaultBodySet::<RealField>::new(),
+            bodies,
             colliders: DefaultColliderSet::<RealField>::new(),
             joint_constraints: DefaultJointConstraintSet::<RealField>::new(),
             force_generators: DefaultForceGeneratorSet::<RealField>::new(),
+            ground,
+            level: LevelId::default(),
+            level_colliders: Vec::new(),
+            palette: Palette::default(),
+            max_players: settings::MAX_PLAYERS,
             players: HashMap::new(),
+            handoff: None,
+            turned_away_clients: HashSet::new(),
+            banned_sessions: HashSet::new(),
         };
 
         physics_world
             .mechanical_world
             .set_timestep(settings::TIMESTEP as f32);
 
-        // TODO: Source from a scene.
-        let ground = physics_world.bodies.insert(Ground::new());
-        physics_world.colliders.insert(
-            ColliderDesc::<RealField>::new(ShapeHandle::new(Cuboid::new(Vector2::new(500.0, 5.0))))
-                .translation(Vector2::new(0.0, -5.0))
-                .build(BodyPartHandle(ground, 0)),
-        );
+        physics_world.load_level(LevelId::default());
 
         physics_world
     }
 }
 
+impl PhysicsWorld {
+    const SPAWN_X: RealField = 0.0;
+    const SPAWN_Y: RealField = 5.0;
+
+    pub fn level(&self) -> LevelId {
+        self.level
+    }
+
+    pub fn load_level(&mut self, level: LevelId) {
+        for collider in self.level_colliders.drain(..) {
+            self.colliders.remove(collider);
+        }
+        self.level = level;
+        self.level_colliders = level.build(self.ground, &mut self.colliders);
+    }
+
+    pub fn max_players(&self) -> usize {
+        self.max_players
+    }
+
+    pub fn palette(&self) -> Palette {
+        self.palette
+    }
+
+    /// Switches to a different palette, handing out new colours to everyone already playing.
+    pub fn set_palette(&mut self, palette: Palette) {
+        if palette == self.palette {
+            return;
+        }
+        self.palette = palette;
+
+        // Note: Go through the players in a fixed order so that every client ends up with the
+        // same colours.
+        let mut player_ids: Vec<PlayerId> = self.players.keys().copied().collect();
+        player_ids.sort();
+        let mut colors_in_use = Vec::new();
+        for player_id in player_ids {
+            let player = self.players.get_mut(&player_id).unwrap();
+            let color = palette.allocate(player.appearance().color, &colors_in_use);
+            player.set_color(color);
+            colors_in_use.push(color);
+        }
+    }
+
+    fn spawn_player(
+        &mut self,
+        player_id: PlayerId,
+        appearance: PlayerAppearance,
+        position: Vector2<RealField>,
+        collision_groups: PlayerCollisionGroups,
+    ) {
+        if let Some(existing_player) = self.players.get(&player_id) {
+            existing_player.deregister(&mut self.bodies, &mut self.colliders);
+        }
+        self.players.insert(
+            player_id,
+            Player::new(
+                appearance,
+                position,
+                collision_groups,
+                &mut self.bodies,
+                &mut self.colliders,
+            ),
+        );
+    }
+
+    /// Takes on a previous host's world. Only players with a session can ever be reclaimed, so
+    /// everyone else is left behind, and NPCs get spawned afresh by the new host.
+    fn restore(&mut self, snapshot: &PhysicsSnapshot) {
+        if snapshot.level != self.level {
+            self.load_level(snapshot.level);
+        }
+        self.palette = snapshot.palette;
+        self.max_players = snapshot.max_players;
+        // Note: Connections are numbered afresh by the new host, but sessions stay the same.
+        self.banned_sessions
+            .extend(snapshot.banned_sessions.iter().copied());
+
+        // Note: The old ids belong to the old host's connections, which new connections would
+        // clash with.
+        let mut old_player_ids: Vec<PlayerId> = snapshot
+            .players
+            .iter()
+            .filter(|(_, player_snapshot)| player_snapshot.session.is_some())
+            .map(|(player_id, _)| *player_id)
+            .collect();
+        old_player_ids.sort();
+        info!("Restoring {} players", old_player_ids.len());
+        for (index, old_player_id) in old_player_ids.into_iter().enumerate() {
+            let player_snapshot = &snapshot.players[&old_player_id];
+            let player_id = PlayerId::restored(index);
+            self.spawn_player(
+                player_id,
+                player_snapshot.appearance.clone(),
+                Vector2::new(0.0, 0.0),
+                player_snapshot.collision_groups,
+            );
+            let player = self.players.get_mut(&player_id).unwrap();
+            player.apply_snapshot(player_snapshot, &mut self.bodies, &mut self.colliders);
+            player.go_away();
+        }
+    }
+
+    fn kick(&mut self, player_id: PlayerId) {
+        // Note: Every local player shares the same connection, so they all have to go.
+        let kicked_player_ids: Vec<PlayerId> = match player_id.client_id() {
+            Some(client_id) => {
+                self.turned_away_clients.insert(client_id);
+                self.players
+                    .keys()
+                    .filter(|other_player_id| other_player_id.client_id() == Some(client_id))
+                    .copied()
+                    .collect()
+            }
+            None => vec![player_id],
+        };
+        for kicked_player_id in kicked_player_ids {
+            if let Some(player) = self.players.remove(&kicked_player_id) {
+                info!("Kicked player {:?}", kicked_player_id);
+                player.deregister(&mut self.bodies, &mut self.colliders);
+            }
+        }
+    }
+
+    fn reset_positions(&mut self) {
+        let mut player_ids: Vec<PlayerId> = self.players.keys().copied().collect();
+        player_ids.sort();
+        for player_id in player_ids {
+            let mut player_snapshot = self.players[&player_id].snapshot(&self.bodies);
+            self.spawn_player(
+                player_id,
+                player_snapshot.appearance.clone(),
+                Vector2::new(Self::SPAWN_X, Self::SPAWN_Y),
+                player_snapshot.collision_groups,
+            );
+            // Note: Keep everything about the player but where they are and how they move.
+            let player = self.players.get_mut(&player_id).unwrap();
+            let fresh_snapshot = player.snapshot(&self.bodies);
+            player_snapshot.positions = fresh_snapshot.positions;
+            player_snapshot.velocities = fresh_snapshot.velocities;
+            player_snapshot.forces = fresh_snapshot.forces;
+            player_snapshot.semiderived_collision_state =
+                fresh_snapshot.semiderived_collision_state;
+            player.apply_snapshot(&player_snapshot, &mut self.bodies, &mut self.colliders);
+        }
+    }
+}
+
 impl World for PhysicsWorld {
     type CommandType = PhysicsCommand;
     type SnapshotType = PhysicsSnapshot;
@@ -96,10 +327,28 @@ impl World for PhysicsWorld {
     fn command_is_valid(command: &PhysicsCommand, client_id: usize) -> bool {
         let player_id = match command {
             PhysicsCommand::PlayerInput { player_id, .. } => player_id,
-            PhysicsCommand::SpawnPlayer { player_id, .. } => player_id,
             PhysicsCommand::DespawnPlayer(player_id) => player_id,
+            PhysicsCommand::Join { player_id, .. } => player_id,
+            PhysicsCommand::RequestTeamSwitch { player_id, .. } => player_id,
+
+            // Clients need to join through the Join command so that their appearance gets
+            // validated. Only the server decides who collides with what, who gets points and
+            // which colours are available.
+            PhysicsCommand::SpawnPlayer { .. }
+            | PhysicsCommand::Leave(_)
+            | PhysicsCommand::SetCollisionGroups { .. }
+            | PhysicsCommand::AwardPoints { .. }
+            | PhysicsCommand::SetPalette(_)
+            | PhysicsCommand::SetLevel(_)
+            | PhysicsCommand::SetMaxPlayers(_)
+            | PhysicsCommand::DesignateSuccessor(_)
+            | PhysicsCommand::Restore(_)
+            | PhysicsCommand::Kick(_)
+            | PhysicsCommand::Ban(_)
+            | PhysicsCommand::ResetPositions => return false,
         };
-        *player_id == PlayerId(client_id)
+        player_id.client_id() == Some(client_id)
+            && player_id.local_slot() < PlayerId::MAX_LOCAL_PLAYERS
     }
 
     fn apply_command(&mut self, command: &PhysicsCommand) {
@@ -111,38 +360,196 @@ impl World for PhysicsWorld {
             }
             PhysicsCommand::SpawnPlayer {
                 player_id,
-                color,
-                size,
+                appearance,
                 x,
                 y,
+                collision_groups,
             } => {
-                if let Some(existing_player) = self.players.get(player_id) {
-                    existing_player.deregister(&mut self.bodies, &mut self.colliders);
+                self.spawn_player(
+                    *player_id,
+                    appearance.clone(),
+                    Vector2::new(*x, *y),
+                    *collision_groups,
+                );
+            }
+            PhysicsCommand::Join {
+                player_id,
+                requested_appearance,
+                reclaimed_session,
+                next_session,
+                can_host,
+            } => {
+                // Clients keep asking to join until they see themselves in the world, so ignore
+                // any repeated requests.
+                if self.players.contains_key(player_id) {
+                    return;
                 }
-                self.players.insert(
+                let client_id = player_id.client_id();
+                if client_id.map_or(false, |client_id| {
+                    self.turned_away_clients.contains(&client_id)
+                }) {
+                    return;
+                }
+                let is_banned = next_session
+                    .iter()
+                    .chain(reclaimed_session.map(|secret| secret.hash()).iter())
+                    .any(|session| self.banned_sessions.contains(session));
+                if is_banned {
+                    info!("Turning away banned player {:?}", player_id);
+                    self.turned_away_clients.extend(client_id);
+                    // Note: Ban the session they would have come back with too, since they
+                    // could otherwise reload and get in with it.
+                    self.banned_sessions.extend(*next_session);
+                    return;
+                }
+                // Note: The dango moves over to the new id, since clients can only control ids
+                // that come from their own connection. This holds even if the old connection
+                // hasn't been noticed to drop yet, e.g. after a quick reload, so that nobody ends
+                // up with two dangos.
+                let reclaimed_session = reclaimed_session.map(|secret| secret.hash());
+                let reclaimed_player_id = self
+                    .players
+                    .iter()
+                    .find(|(_, player)| {
+                        reclaimed_session.is_some() && player.session() == reclaimed_session
+                    })
+                    .map(|(reclaimed_player_id, _)| *reclaimed_player_id);
+                if let Some(reclaimed_player_id) = reclaimed_player_id {
+                    let mut player = self.players.remove(&reclaimed_player_id).unwrap();
+                    info!(
+                        "Player {:?} reclaimed {:?} as {:?}",
+                        player_id,
+                        reclaimed_player_id,
+                        player.appearance().nickname
+                    );
+                    player.start_session(*next_session, *can_host);
+                    self.players.insert(*player_id, player);
+                    return;
+                }
+                // Note: Anyone turned away keeps asking, so they get in once someone leaves.
+                if self.players.len() >= self.max_players {
+                    return;
+                }
+                let appearance = requested_appearance
+                    .validated(self.palette, self.players.values().map(Player::appearance));
+                let team = TeamId::balanced(
+                    settings::TEAM_COUNT,
+                    self.players.values().map(Player::team),
+                );
+                info!(
+                    "Player {:?} joined as {:?} on team {:?}",
+                    player_id, appearance.nickname, team
+                );
+                self.spawn_player(
                     *player_id,
-                    Player::new(
-                        *color,
-                        *size,
-                        Vector2::new(*x, *y),
-                        &mut self.bodies,
-                        &mut self.colliders,
-                    ),
+                    appearance,
+                    Vector2::new(Self::SPAWN_X, Self::SPAWN_Y),
+                    PlayerCollisionGroups {
+                        role: CollisionRole::Player,
+                        team,
+                    },
                 );
+                self.players
+                    .get_mut(player_id)
+                    .unwrap()
+                    .start_session(*next_session, *can_host);
             }
             PhysicsCommand::DespawnPlayer(player_id) => {
                 if let Some(player) = self.players.remove(player_id) {
                     player.deregister(&mut self.bodies, &mut self.colliders);
                 }
             }
+            PhysicsCommand::Leave(player_id) => {
+                // Note: Without a session, there is no way to come back, e.g. for bots.
+                match self
+                    .players
+                    .get(player_id)
+                    .map(|player| player.session().is_some())
+                {
+                    Some(true) => self.players.get_mut(player_id).unwrap().go_away(),
+                    Some(false) => {
+                        let player = self.players.remove(player_id).unwrap();
+                        player.deregister(&mut self.bodies, &mut self.colliders);
+                    }
+                    None => {}
+                }
+            }
+            PhysicsCommand::SetCollisionGroups {
+                player_id,
+                collision_groups,
+            } => {
+                if let Some(player) = self.players.get_mut(player_id) {
+                    player.set_collision_groups(*collision_groups, &mut self.colliders);
+                }
+            }
+            PhysicsCommand::RequestTeamSwitch { player_id, team } => {
+                if let Some(current_team) = self.players.get(player_id).map(Player::team) {
+                    // Note: This is validated here rather than in command_is_valid since it
+                    // depends on everyone else's team.
+                    if TeamId::can_switch(
+                        settings::TEAM_COUNT,
+                        self.players.values().map(Player::team),
+                        current_team,
+                        *team,
+                    ) {
+                        self.players
+                            .get_mut(player_id)
+                            .unwrap()
+                            .set_team(Some(*team), &mut self.colliders);
+                    }
+                }
+            }
+            PhysicsCommand::AwardPoints { player_id, points } => {
+                if let Some(player) = self.players.get_mut(player_id) {
+                    player.award_points(*points);
+                }
+            }
+            PhysicsCommand::SetPalette(palette) => self.set_palette(*palette),
+            PhysicsCommand::SetLevel(level) => {
+                if *level != self.level {
+                    self.load_level(*level);
+                }
+            }
+            PhysicsCommand::SetMaxPlayers(max_players) => self.max_players = *max_players,
+            PhysicsCommand::DesignateSuccessor(successor) => {
+                self.handoff = Some(Arc::new(Handoff {
+                    successor: *successor,
+                    claim: self.players.get(successor).and_then(Player::session),
+                    snapshot: self.snapshot(),
+                }));
+            }
+            PhysicsCommand::Restore(snapshot) => self.restore(snapshot),
+            PhysicsCommand::Kick(player_id) => self.kick(*player_id),
+            PhysicsCommand::Ban(player_id) => {
+                if let Some(session) = self.players.get(player_id).and_then(Player::session) {
+                    self.banned_sessions.insert(session);
+                }
+                self.kick(*player_id);
+            }
+            PhysicsCommand::ResetPositions => self.reset_positions(),
         }
     }
 
     fn apply_snapshot(&mut self, snapshot: PhysicsSnapshot) {
+        if snapshot.level != self.level {
+            self.load_level(snapshot.level);
+        }
+        self.palette = snapshot.palette;
+        self.max_players = snapshot.max_players;
+        self.turned_away_clients = snapshot.turned_away_clients;
+        self.banned_sessions = snapshot.banned_sessions;
         let bodies = &mut self.bodies;
         let colliders = &mut self.colliders;
         self.players.retain(|player_id, player| {
-            if !snapshot.players.contains_key(player_id) {
+            // Note: A change in size means the body needs to be rebuilt from scratch. Any other
+            // change in appearance is applied along with the rest of the snapshot.
+            let is_outdated = snapshot
+                .players
+                .get(player_id)
+                .map_or(true, |player_snapshot| {
+                    player_snapshot.appearance.size != player.appearance().size
+                });
+            if is_outdated {
                 player.deregister(bodies, colliders);
                 false
             } else {
@@ -156,16 +563,16 @@ impl World for PhysicsWorld {
                 self.players.insert(
                     *player_id,
                     Player::new(
-                        player_snapshot.color,
-                        player_snapshot.size,
+                        player_snapshot.appearance.clone(),
                         Vector2::new(0.0, 0.0),
+                        player_snapshot.collision_groups,
                         bodies,
                         colliders,
                     ),
                 );
                 self.players.get_mut(player_id).unwrap()
             };
-            player.apply_snapshot(player_snapshot, bodies);
+            player.apply_snapshot(player_snapshot, bodies, colliders);
         }
     }
 
@@ -174,7 +581,14 @@ impl World for PhysicsWorld {
         for (player_id, player) in &self.players {
             players.insert(*player_id, player.snapshot(&self.bodies));
         }
-        PhysicsSnapshot { players }
+        PhysicsSnapshot {
+            level: self.level,
+            palette: self.palette,
+            max_players: self.max_players,
+            players,
+            turned_away_clients: self.turned_away_clients.clone(),
+            banned_sessions: self.banned_sessions.clone(),
+        }
     }
 
     fn display_state(&self) -> PhysicsDisplayState {
@@ -182,7 +596,12 @@ impl World for PhysicsWorld {
         for (player_id, player) in &self.players {
             players.insert(*player_id, player.display_state(&self.bodies));
         }
-        PhysicsDisplayState { players }
+        PhysicsDisplayState {
+            players,
+            max_players: self.max_players,
+            handoff: self.handoff.clone(),
+            turned_away_clients: self.turned_away_clients.clone(),
+        }
     }
 }
 
@@ -201,6 +620,18 @@ impl Stepper for PhysicsWorld {
         for player in &mut self.players.values_mut() {
             player.post_step(&self.colliders, &self.geometrical_world);
         }
+
+        let bodies = &mut self.bodies;
+        let colliders = &mut self.colliders;
+        self.players.retain(|player_id, player| {
+            if player.step_away() {
+                info!("Player {:?} did not come back", player_id);
+                player.deregister(bodies, colliders);
+                false
+            } else {
+                true
+            }
+        });
     }
 }
 
@@ -210,6 +641,46 @@ impl PhysicsDisplayState {
     pub fn players(&self) -> &HashMap<PlayerId, PlayerDisplayState> {
         &self.players
     }
+
+    pub fn max_players(&self) -> usize {
+        self.max_players
+    }
+
+    /// Whether anyone else asking to join gets turned away. Dangos waiting to be reclaimed still
+    /// take up a spot.
+    pub fn is_full(&self) -> bool {
+        self.players.len() >= self.max_players
+    }
+
+    /// Whether every spot is taken by someone who is actually here. Anyone coming back for a dango
+    /// that is waiting for them still fits, since they take over its spot.
+    pub fn is_full_without_away_players(&self) -> bool {
+        self.players
+            .values()
+            .filter(|player| !player.is_away)
+            .count()
+            >= self.max_players
+    }
+
+    /// Whether the host kicked the given client out of the room.
+    pub fn is_turned_away(&self, client_id: usize) -> bool {
+        self.turned_away_clients.contains(&client_id)
+    }
+
+    pub fn team_scores(&self) -> HashMap<TeamId, i32> {
+        let mut team_scores = HashMap::new();
+        for player in self.players.values() {
+            if let Some(team) = player.team {
+                *team_scores.entry(team).or_insert(0) += player.score;
+            }
+        }
+        team_scores
+    }
+
+    /// The latest successor picked by the host, along with the world as it was at the time.
+    pub fn handoff(&self) -> Option<Arc<Handoff>> {
+        self.handoff.clone()
+    }
 }
 
 impl DisplayState for PhysicsDisplayState {
@@ -228,6 +699,124 @@ impl DisplayState for PhysicsDisplayState {
             }
         }
 
-        Self { players }
+        Self {
+            players,
+            max_players: new_state.max_players,
+            handoff: new_state.handoff.clone(),
+            turned_away_clients: new_state.turned_away_clients.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::{color_distance, MIN_COLOR_DISTANCE};
+
+    fn join(
+        world: &mut PhysicsWorld,
+        player_id: PlayerId,
+        reclaimed_session: Option<SessionSecret>,
+        next_session: SessionSecret,
+    ) {
+        world.apply_command(&PhysicsCommand::Join {
+            player_id,
+            requested_appearance: PlayerAppearance::default(),
+            reclaimed_session,
+            next_session: Some(next_session.hash()),
+            can_host: false,
+        });
+    }
+
+    #[test]
+    fn reclaiming_moves_the_dango_over_even_before_the_old_client_leaves() {
+        let mut world = PhysicsWorld::default();
+        let first_session = SessionSecret(1);
+        join(&mut world, PlayerId(1), None, first_session);
+        world.apply_command(&PhysicsCommand::AwardPoints {
+            player_id: PlayerId(1),
+            points: 5,
+        });
+
+        join(
+            &mut world,
+            PlayerId(2),
+            Some(first_session),
+            SessionSecret(2),
+        );
+        let players = world.display_state().players().clone();
+        assert_eq!(players.len(), 1);
+        assert_eq!(players[&PlayerId(2)].score, 5);
+    }
+
+    #[test]
+    fn team_mates_keep_colours_that_tell_them_apart() {
+        let mut world = PhysicsWorld::default();
+        for client_id in 1..=4 {
+            join(
+                &mut world,
+                PlayerId(client_id),
+                None,
+                SessionSecret(client_id as u64),
+            );
+        }
+        let players = world.display_state().players().clone();
+        for (player_id, player) in &players {
+            for (other_id, other) in &players {
+                if player_id != other_id && player.team == other.team {
+                    assert!(color_distance(player.color, other.color) >= MIN_COLOR_DISTANCE);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn kicked_clients_cannot_join_again() {
+        let mut world = PhysicsWorld::default();
+        join(&mut world, PlayerId(1), None, SessionSecret(1));
+        join(&mut world, PlayerId::local(1, 1), None, SessionSecret(2));
+        world.apply_command(&PhysicsCommand::Kick(PlayerId(1)));
+        assert!(world.display_state().players().is_empty());
+        assert!(world.display_state().is_turned_away(1));
+
+        join(&mut world, PlayerId(1), None, SessionSecret(3));
+        assert!(world.display_state().players().is_empty());
+
+        // Note: A kick doesn't follow the player to a new connection.
+        join(
+            &mut world,
+            PlayerId(2),
+            Some(SessionSecret(1)),
+            SessionSecret(4),
+        );
+        assert!(world.display_state().players().contains_key(&PlayerId(2)));
+    }
+
+    #[test]
+    fn banned_players_stay_out_however_often_they_reload() {
+        let mut world = PhysicsWorld::default();
+        join(&mut world, PlayerId(1), None, SessionSecret(1));
+        world.apply_command(&PhysicsCommand::Ban(PlayerId(1)));
+        assert!(world.display_state().players().is_empty());
+
+        join(
+            &mut world,
+            PlayerId(2),
+            Some(SessionSecret(1)),
+            SessionSecret(2),
+        );
+        assert!(world.display_state().players().is_empty());
+        assert!(world.display_state().is_turned_away(2));
+
+        join(
+            &mut world,
+            PlayerId(3),
+            Some(SessionSecret(2)),
+            SessionSecret(3),
+        );
+        assert!(world.display_state().players().is_empty());
+
+        join(&mut world, PlayerId(4), None, SessionSecret(4));
+        assert!(world.display_state().players().contains_key(&PlayerId(4)));
     }
 }
@@ -0,0 +1,69 @@
+use crate::{
+    physics_multiplayer::{PhysicsCommand, PhysicsWorld},
+    player::PlayerId,
+    settings,
+};
+use bevy::prelude::*;
+use crystalorb_bevy_networking_turbulence::{
+    bevy_networking_turbulence::{NetworkEvent, NetworkResource},
+    crystalorb::server::Server,
+    WrappedNetworkResource,
+};
+use std::convert::TryInto;
+
+pub fn physics_multiplayer_server_despawn_system(
+    mut server: ResMut<Server<PhysicsWorld>>,
+    mut network_events: EventReader<NetworkEvent>,
+    mut net: ResMut<NetworkResource>,
+) {
+    // Note: Players are spawned when their client issues a Join command with the appearance
+    // they would like to have. Leaving keeps their dango around for a while in case they come
+    // back.
+    for network_event in network_events.iter() {
+        if let NetworkEvent::Disconnected(client_id) = network_event {
+            let client_id: usize = (*client_id).try_into().unwrap();
+            for slot in 0..PlayerId::MAX_LOCAL_PLAYERS {
+                server.issue_command(
+                    PhysicsCommand::Leave(PlayerId::local(client_id, slot)),
+                    &mut WrappedNetworkResource(&mut net),
+                );
+            }
+        }
+    }
+}
+
+/// Every so often, picks a client to take over if the host goes away. Whoever has been connected
+/// the longest gets picked, since they are the most likely to stick around.
+pub fn physics_multiplayer_server_handoff_system(
+    mut last_handoff_time: Local<Option<f64>>,
+    time: Res<Time>,
+    mut server: ResMut<Server<PhysicsWorld>>,
+    mut net: ResMut<NetworkResource>,
+) {
+    let now = time.seconds_since_startup();
+    if last_handoff_time.map_or(false, |last_handoff_time| {
+        now - last_handoff_time < settings::HANDOFF_INTERVAL
+    }) {
+        return;
+    }
+    *last_handoff_time = Some(now);
+
+    let successor = server
+        .display_state()
+        .players()
+        .iter()
+        .filter(|(player_id, player)| {
+            player_id.client_id().is_some()
+                && player_id.local_slot() == 0
+                && player.can_host
+                && !player.is_away
+        })
+        .map(|(player_id, _)| *player_id)
+        .min();
+    if let Some(successor) = successor {
+        server.issue_command(
+            PhysicsCommand::DesignateSuccessor(successor),
+            &mut WrappedNetworkResource(&mut net),
+        );
+    }
+}
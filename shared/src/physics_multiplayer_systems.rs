@@ -1,7 +1,10 @@
 use crate::{
     blinking_eyes::BlinkingEyes,
-    physics_multiplayer::{PhysicsCommand, PhysicsDisplayState, PhysicsWorld},
+    color::Rgb,
+    physics_multiplayer::{PhysicsDisplayState, PhysicsWorld},
+    physics_offline::{OfflinePhysics, OFFLINE_CLIENT_ID},
     player::{PlayerDisplayState, PlayerId},
+    player_input::{LocalPlayers, Spectator},
 };
 use bevy::{
     prelude::*,
@@ -12,13 +15,9 @@ use bevy::{
     },
     sprite::SPRITE_PIPELINE_HANDLE,
 };
-use crystalorb_bevy_networking_turbulence::{
-    bevy_networking_turbulence::{NetworkEvent, NetworkResource},
-    crystalorb::{
-        client::{stage::Stage as ClientStage, Client},
-        server::Server,
-    },
-    WrappedNetworkResource,
+use crystalorb_bevy_networking_turbulence::crystalorb::{
+    client::{stage::Stage as ClientStage, Client},
+    server::Server,
 };
 
 use bevy_prototype_transform_tracker::TransformTrackingTarget;
@@ -31,48 +30,7 @@ use lyon::{
     },
 };
 use splines::{Interpolation, Key, Spline};
-use std::{
-    collections::{HashMap, HashSet},
-    convert::TryInto,
-};
-
-//#[cfg(not(target_arch = "wasm32"))]
-pub fn physics_multiplayer_server_spawn_despawn_system(
-    mut server: ResMut<Server<PhysicsWorld>>,
-    mut network_events: EventReader<NetworkEvent>,
-    mut net: ResMut<NetworkResource>,
-) {
-    for network_event in network_events.iter() {
-        match network_event {
-            NetworkEvent::Connected(client_id) => {
-                server.issue_command(
-                    PhysicsCommand::SpawnPlayer {
-                        player_id: PlayerId((*client_id).try_into().unwrap()),
-                        // TODO: Dynamically chosen...
-                        size: 0.6,
-                        x: 0.0,
-                        y: 5.0,
-                        color: match *client_id % 4 {
-                            0 => Color::rgb(1.0, 0.3, 0.3),
-                            1 => Color::rgb(0.3, 0.8, 0.4),
-                            2 => Color::rgb(1.0, 0.8, 0.3),
-                            3 => Color::rgb(0.3, 0.6, 1.0),
-                            _ => unreachable!(),
-                        },
-                    },
-                    &mut WrappedNetworkResource(&mut net),
-                );
-            }
-            NetworkEvent::Disconnected(client_id) => {
-                server.issue_command(
-                    PhysicsCommand::DespawnPlayer(PlayerId((*client_id).try_into().unwrap())),
-                    &mut WrappedNetworkResource(&mut net),
-                );
-            }
-            _ => {}
-        }
-    }
-}
+use std::collections::{HashMap, HashSet};
 
 #[derive(Clone)]
 pub struct OutlineMesh(Handle<Mesh>);
@@ -96,8 +54,21 @@ pub struct PlayerBundle {
 
 pub struct PlayerComponent;
 
+/// Keeps track of the materials used by each player so they can be recoloured, e.g. when
+/// switching teams.
+pub struct PlayerMaterials {
+    fill: Handle<ColorMaterial>,
+    outline: Handle<ColorMaterial>,
+    shadow: Handle<ColorMaterial>,
+}
+
 #[derive(Default)]
-pub struct PlayerMap(HashMap<PlayerId, Entity>);
+pub struct PlayerMap {
+    entities: HashMap<PlayerId, Entity>,
+
+    /// Which players the camera was told to follow last time.
+    tracked: Vec<PlayerId>,
+}
 
 pub struct Shadow(Entity);
 
@@ -108,22 +79,30 @@ enum DrawMode {
 }
 
 const PLAYER_Z_SPACE: f32 = 0.1;
-const MAX_PLAYERS: f32 = 100.0;
 const SHADOW_Z_OFFSET: f32 = 1.0;
 
 pub fn physics_multiplayer_client_sync_system(
     mut player_map: Local<PlayerMap>,
     commands: Commands,
     client: Res<Client<PhysicsWorld>>,
+    local_players: Res<LocalPlayers>,
+    spectator: Res<Spectator>,
     mut materials: ResMut<Assets<ColorMaterial>>,
     meshes: ResMut<Assets<Mesh>>,
-    query: Query<(&PlayerComponent, &Handle<Mesh>, &OutlineMesh, &Shadow)>,
+    query: Query<(
+        &PlayerComponent,
+        &Handle<Mesh>,
+        &OutlineMesh,
+        &Shadow,
+        &PlayerMaterials,
+        &mut BlinkingEyes,
+    )>,
     transform_query: Query<&mut Transform>,
 ) {
     if let ClientStage::Ready(ready_client) = client.stage() {
         sync_from_state(
             ready_client.display_state(),
-            PlayerId(ready_client.client_id()),
+            &spectator.players_to_track(&local_players, ready_client.client_id()),
             &mut player_map,
             commands,
             &mut materials,
@@ -135,6 +114,36 @@ pub fn physics_multiplayer_client_sync_system(
     }
 }
 
+pub fn physics_offline_sync_system(
+    mut player_map: Local<PlayerMap>,
+    commands: Commands,
+    physics: Res<OfflinePhysics>,
+    local_players: Res<LocalPlayers>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    meshes: ResMut<Assets<Mesh>>,
+    query: Query<(
+        &PlayerComponent,
+        &Handle<Mesh>,
+        &OutlineMesh,
+        &Shadow,
+        &PlayerMaterials,
+        &mut BlinkingEyes,
+    )>,
+    transform_query: Query<&mut Transform>,
+) {
+    sync_from_state(
+        physics.display_state(),
+        &local_players.player_ids(OFFLINE_CLIENT_ID),
+        &mut player_map,
+        commands,
+        &mut materials,
+        meshes,
+        query,
+        transform_query,
+        DrawMode::Spline,
+    );
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 pub fn physics_multiplayer_server_diagnostic_sync_system(
     mut player_map: Local<PlayerMap>,
@@ -142,12 +151,19 @@ pub fn physics_multiplayer_server_diagnostic_sync_system(
     server: Res<Server<PhysicsWorld>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
     meshes: ResMut<Assets<Mesh>>,
-    query: Query<(&PlayerComponent, &Handle<Mesh>, &OutlineMesh, &Shadow)>,
+    query: Query<(
+        &PlayerComponent,
+        &Handle<Mesh>,
+        &OutlineMesh,
+        &Shadow,
+        &PlayerMaterials,
+        &mut BlinkingEyes,
+    )>,
     transform_query: Query<&mut Transform>,
 ) {
     sync_from_state(
         &server.display_state(),
-        PlayerId(0),
+        &[PlayerId(0)],
         &mut player_map,
         commands,
         &mut materials,
@@ -160,19 +176,40 @@ pub fn physics_multiplayer_server_diagnostic_sync_system(
 
 fn sync_from_state(
     world_state: &PhysicsDisplayState,
-    player_to_track: PlayerId,
+    players_to_track: &[PlayerId],
     player_map: &mut PlayerMap,
     mut commands: Commands,
     materials: &mut Assets<ColorMaterial>,
     mut meshes: ResMut<Assets<Mesh>>,
-    mut query: Query<(&PlayerComponent, &Handle<Mesh>, &OutlineMesh, &Shadow)>,
+    mut query: Query<(
+        &PlayerComponent,
+        &Handle<Mesh>,
+        &OutlineMesh,
+        &Shadow,
+        &PlayerMaterials,
+        &mut BlinkingEyes,
+    )>,
     transform_query: Query<&mut Transform>,
     draw_mode: DrawMode,
 ) {
     let new_player_states = world_state.players();
 
+    // Note: Share the z-space out between however many players the room has room for, going by
+    // their order rather than their ids, since ids can be far apart.
+    let mut sorted_player_ids: Vec<PlayerId> = new_player_states.keys().copied().collect();
+    sorted_player_ids.sort();
+    let depth_slots: HashMap<PlayerId, usize> = sorted_player_ids
+        .into_iter()
+        .enumerate()
+        .map(|(depth_slot, player_id)| (player_id, depth_slot))
+        .collect();
+    let depth_slot_count = world_state
+        .max_players()
+        .max(new_player_states.len())
+        .max(1);
+
     let new_player_ids: HashSet<PlayerId> = new_player_states.keys().copied().collect();
-    let old_player_ids: HashSet<PlayerId> = player_map.0.keys().copied().collect();
+    let old_player_ids: HashSet<PlayerId> = player_map.entities.keys().copied().collect();
     trace!("new_player_ids: {:?}", new_player_ids);
 
     let to_spawn = new_player_ids.difference(&old_player_ids);
@@ -186,7 +223,8 @@ fn sync_from_state(
         update_transform(
             &mut transform,
             &mut shadow_transform,
-            player_id,
+            depth_slots[player_id],
+            depth_slot_count,
             player_state,
         );
 
@@ -233,6 +271,11 @@ fn sync_from_state(
             player_state,
             draw_mode,
         );
+        let player_materials = PlayerMaterials {
+            fill: materials.add(Color::from(player_state.color).into()),
+            outline: materials.add(outline_color(player_state).into()),
+            shadow: materials.add(shadow_color(player_state.color).into()),
+        };
         let entity = commands
             .spawn()
             .insert_bundle(PlayerBundle {
@@ -242,7 +285,7 @@ fn sync_from_state(
                 },
                 mesh: mesh_handle,
                 outline_mesh: outline_mesh_handle.clone(),
-                material: materials.add(player_state.color.into()),
+                material: player_materials.fill.clone(),
                 main_pass: MainPass,
                 draw: Default::default(),
                 visible: Visible {
@@ -257,6 +300,7 @@ fn sync_from_state(
                 player: PlayerComponent,
                 blinking_eyes: BlinkingEyes::new(
                     vec![left_eye_mesh_handle.clone(), right_eye_mesh_handle.clone()],
+                    player_state.eye_style,
                     &mut meshes,
                 ),
             })
@@ -295,14 +339,7 @@ fn sync_from_state(
                     ..Default::default()
                 },
                 mesh: outline_mesh_handle.0,
-                material: materials.add(
-                    Color::rgb(
-                        player_state.color.r() * 0.5,
-                        player_state.color.g() * 0.5,
-                        player_state.color.b() * 0.5,
-                    )
-                    .into(),
-                ),
+                material: player_materials.outline.clone(),
                 main_pass: MainPass,
                 draw: Default::default(),
                 visible: Visible {
@@ -324,14 +361,7 @@ fn sync_from_state(
                     ..Default::default()
                 },
                 mesh: shadow_mesh_handle.clone(),
-                material: materials.add(
-                    Color::rgb(
-                        player_state.color.r() * 0.1,
-                        player_state.color.g() * 0.1,
-                        player_state.color.b() * 0.1,
-                    )
-                    .into(),
-                ),
+                material: player_materials.shadow.clone(),
                 main_pass: MainPass,
                 draw: Default::default(),
                 visible: Visible {
@@ -345,28 +375,59 @@ fn sync_from_state(
                 global_transform: GlobalTransform::default(),
             })
             .id();
-        commands.entity(entity).insert(Shadow(shadow_entity));
+        commands
+            .entity(entity)
+            .insert(Shadow(shadow_entity))
+            .insert(player_materials);
 
-        if *player_id == player_to_track {
+        if players_to_track.contains(player_id) {
             commands.entity(entity).insert(TransformTrackingTarget);
         }
 
-        player_map.0.insert(*player_id, entity);
+        player_map.entities.insert(*player_id, entity);
     }
 
     for player_id in to_despawn {
         info!("Despawning player {:?}", player_id);
-        let entity = player_map.0.remove(player_id).unwrap();
+        let entity = player_map.entities.remove(player_id).unwrap();
         if let Ok(shadow_entity) = query.get_component::<Shadow>(entity) {
             commands.entity(shadow_entity.0).despawn_recursive();
         }
         commands.entity(entity).despawn_recursive();
     }
 
+    // Note: Newly spawned players already know whether to be tracked, but spectators can switch
+    // who they follow, or join and start following their own dango instead.
+    if player_map.tracked != players_to_track {
+        for (player_id, entity) in player_map.entities.iter() {
+            if players_to_track.contains(player_id) {
+                commands.entity(*entity).insert(TransformTrackingTarget);
+            } else {
+                commands.entity(*entity).remove::<TransformTrackingTarget>();
+            }
+        }
+        player_map.tracked = players_to_track.to_vec();
+    }
+
     for (player_id, player_state) in new_player_states {
-        let entity = player_map.0.get(player_id).unwrap();
+        let entity = player_map.entities.get(player_id).unwrap();
         // SAFE: Shadow and it's shadow caster are different entities, so no aliasing occurs.
-        if let Ok((_, mesh_handle, outline_mesh_handle, shadow)) = query.get_mut(*entity) {
+        if let Ok((
+            _,
+            mesh_handle,
+            outline_mesh_handle,
+            shadow,
+            player_materials,
+            mut blinking_eyes,
+        )) = query.get_mut(*entity)
+        {
+            update_materials(materials, player_materials, player_state);
+            // Note: Eyes only get drawn from scratch when they blink, so redraw them right away
+            // when their style changes.
+            if blinking_eyes.eye_style != player_state.eye_style {
+                blinking_eyes.eye_style = player_state.eye_style;
+                blinking_eyes.update_meshes(&mut meshes);
+            }
             unsafe {
                 if let Ok(mut transform) = transform_query.get_unchecked(*entity) {
                     if let Ok(mut shadow_transform) = transform_query.get_unchecked(shadow.0) {
@@ -374,7 +435,8 @@ fn sync_from_state(
                         update_transform(
                             &mut transform,
                             &mut shadow_transform,
-                            player_id,
+                            depth_slots[player_id],
+                            depth_slot_count,
                             player_state,
                         );
                         update_mesh(
@@ -391,17 +453,54 @@ fn sync_from_state(
     }
 }
 
+/// Team mates share an outline in their team's colour.
+fn outline_color(player_state: &PlayerDisplayState) -> Color {
+    player_state
+        .team
+        .map_or_else(|| player_state.color.scaled(0.5), |team| team.color())
+        .into()
+}
+
+fn shadow_color(color: Rgb) -> Color {
+    color.scaled(0.1).into()
+}
+
+fn update_materials(
+    materials: &mut Assets<ColorMaterial>,
+    player_materials: &PlayerMaterials,
+    player_state: &PlayerDisplayState,
+) {
+    let needs_update = materials
+        .get(&player_materials.fill)
+        .map_or(false, |fill| fill.color != Color::from(player_state.color))
+        || materials
+            .get(&player_materials.outline)
+            .map_or(false, |outline| {
+                outline.color != outline_color(player_state)
+            });
+    if !needs_update {
+        return;
+    }
+    if let Some(fill) = materials.get_mut(&player_materials.fill) {
+        fill.color = player_state.color.into();
+    }
+    if let Some(outline) = materials.get_mut(&player_materials.outline) {
+        outline.color = outline_color(player_state);
+    }
+    if let Some(shadow) = materials.get_mut(&player_materials.shadow) {
+        shadow.color = shadow_color(player_state.color);
+    }
+}
+
 fn update_transform(
     transform: &mut Transform,
     shadow_transform: &mut Transform,
-    player_id: &PlayerId,
+    depth_slot: usize,
+    depth_slot_count: usize,
     player_state: &PlayerDisplayState,
 ) {
-    transform.scale = Vec3::new(
-        player_state.size,
-        player_state.size,
-        PLAYER_Z_SPACE / MAX_PLAYERS,
-    );
+    let depth_per_player = PLAYER_Z_SPACE / depth_slot_count as f32;
+    transform.scale = Vec3::new(player_state.size, player_state.size, depth_per_player);
     transform.translation.x = player_state.measurements.center_of_mass.x;
 
     // HACK: Compensating outline's 0.1 thickness so it touches the ground at the right visual
@@ -410,7 +509,7 @@ fn update_transform(
 
     // Ensure each player gets their own z-space for drawing, since we don't want
     // one players outline and fill to sandwich another player's.
-    transform.translation.z = -(player_id.0 as f32) * PLAYER_Z_SPACE / MAX_PLAYERS;
+    transform.translation.z = -(depth_slot as f32) * depth_per_player;
     transform.rotation = Quat::from_rotation_z(player_state.measurements.mean_angle);
 
     shadow_transform.scale = Vec3::ONE * player_state.size;
@@ -0,0 +1,78 @@
+use crate::{
+    level::LevelId,
+    physics_multiplayer::{PhysicsCommand, PhysicsDisplayState, PhysicsWorld},
+    settings,
+};
+use bevy::prelude::*;
+use crystalorb_bevy_networking_turbulence::crystalorb::{
+    fixed_timestepper::Stepper,
+    world::{DisplayState, World},
+};
+
+/// Stands in for a connection handle when working out the local players' ids.
+pub const OFFLINE_CLIENT_ID: usize = 0;
+
+/// Give up on catching up after this many steps in one frame, e.g. when the browser tab comes back
+/// from the background, rather than freezing while fast-forwarding.
+const MAX_STEPS_PER_FRAME: usize = 10;
+
+/// A world that runs entirely inside the client without any server, for practising on your own,
+/// trying out levels, or when the signalling server is down. Commands are applied straight away
+/// instead of going through CrystalOrb.
+pub struct OfflinePhysics {
+    world: PhysicsWorld,
+    unstepped_seconds: f64,
+    previous_display_state: PhysicsDisplayState,
+    current_display_state: PhysicsDisplayState,
+    display_state: PhysicsDisplayState,
+}
+
+impl OfflinePhysics {
+    pub fn new(level: LevelId) -> Self {
+        let mut world = PhysicsWorld::default();
+        world.apply_command(&PhysicsCommand::SetLevel(level));
+        let display_state = world.display_state();
+        Self {
+            world,
+            unstepped_seconds: 0.0,
+            previous_display_state: display_state.clone(),
+            current_display_state: display_state.clone(),
+            display_state,
+        }
+    }
+
+    pub fn issue_command(&mut self, command: PhysicsCommand) {
+        self.world.apply_command(&command);
+    }
+
+    /// What to render, interpolated between the last two steps.
+    pub fn display_state(&self) -> &PhysicsDisplayState {
+        &self.display_state
+    }
+
+    /// Steps the world at `settings::TIMESTEP` for however long has passed since last time.
+    pub fn advance(&mut self, delta_seconds: f64) {
+        self.unstepped_seconds += delta_seconds;
+        let mut step_count = 0;
+        while self.unstepped_seconds >= settings::TIMESTEP {
+            if step_count == MAX_STEPS_PER_FRAME {
+                self.unstepped_seconds %= settings::TIMESTEP;
+                break;
+            }
+            self.world.step();
+            self.unstepped_seconds -= settings::TIMESTEP;
+            step_count += 1;
+            self.previous_display_state =
+                std::mem::replace(&mut self.current_display_state, self.world.display_state());
+        }
+        self.display_state = PhysicsDisplayState::from_interpolation(
+            &self.previous_display_state,
+            &self.current_display_state,
+            self.unstepped_seconds / settings::TIMESTEP,
+        );
+    }
+}
+
+pub fn offline_physics_step_system(mut physics: ResMut<OfflinePhysics>, time: Res<Time>) {
+    physics.advance(time.delta_seconds_f64());
+}
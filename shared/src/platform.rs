@@ -0,0 +1,329 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    sync::{Arc, Mutex},
+};
+
+#[cfg(feature = "web")]
+pub use crate::platform_web::WebPlatform;
+
+#[cfg(not(feature = "web"))]
+pub use crate::platform_native::NativePlatform;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    Connecting,
+    Connected,
+
+    /// Connected, but every spot in the room is taken, so watching until one frees up.
+    Full,
+
+    /// The signalling server turned this client away since every spot in the room is taken, so
+    /// waiting to try again once one frees up.
+    RoomFull,
+
+    /// Still connected, but the host kicked or banned this client out of the room.
+    Removed,
+    Disconnected,
+
+    /// Playing on a world that only exists inside this client.
+    Offline,
+}
+
+#[derive(Debug)]
+pub enum PlatformError {
+    Unsupported,
+    Failed(String),
+}
+
+/// The answer to a [`Platform::start_fetch`], which turns up some frames later. Systems keep it
+/// around and check on it every frame, rather than stalling the game while waiting.
+#[derive(Debug, Clone, Default)]
+pub struct PendingFetch(Arc<Mutex<Option<Result<String, PlatformError>>>>);
+
+/// Everything the game needs from the environment it runs in, so that systems don't need to know
+/// whether they are inside a browser tab, a desktop window, or a headless test. Systems access
+/// it as a `Res<Box<dyn Platform>>`.
+pub trait Platform: Send + Sync + 'static {
+    /// Looks up a named launch parameter, e.g. `join`. On the web, these come from the page's
+    /// URL. Natively, they come from `--name value` command line arguments.
+    fn launch_param(&self, name: &str) -> Option<String>;
+
+    /// Lets the player know that a part of the game has finished loading.
+    fn report_load_complete(&self, component_name: &str);
+
+    fn report_connection_status(&self, status: ConnectionStatus);
+
+    /// Whether the player is likely to be playing with their fingers rather than a keyboard.
+    fn has_touch_screen(&self) -> bool;
+
+    /// Where each finger on the screen is, by touch id, in logical pixels from the bottom left of
+    /// the window. Only platforms whose windowing can't pass on more than one touch at a time
+    /// answer this. Elsewhere, bevy's `Touches` already has them.
+    fn touches(&self) -> Option<Vec<(u64, [f32; 2])>>;
+
+    /// Turns the id or address that the server is reachable at into something that can be handed
+    /// to other players.
+    fn shareable_link(&self, endpoint_id: &str) -> String;
+
+    fn show_shareable_link(&self, link: &str);
+
+    fn copy_to_clipboard(&self, text: &str) -> Result<(), PlatformError>;
+
+    /// Reads a setting that was remembered from a previous session.
+    fn load_setting(&self, key: &str) -> Option<String>;
+
+    fn save_setting(&self, key: &str, value: &str) -> Result<(), PlatformError>;
+
+    /// Reads a setting that only this tab remembers, e.g. across a reload, and that other tabs
+    /// running the game can't see.
+    fn load_tab_setting(&self, key: &str) -> Option<String>;
+
+    fn save_tab_setting(&self, key: &str, value: &str) -> Result<(), PlatformError>;
+
+    /// Whether this client could take over hosting the room it is in if the host went away.
+    fn can_host(&self) -> bool;
+
+    /// Leaves the game to host the given room in its place, e.g. after the host went away.
+    fn host_room(&self, room_id: &str) -> Result<(), PlatformError>;
+
+    /// Starts the game over with the same launch parameters, e.g. to reconnect to a room.
+    fn restart(&self) -> Result<(), PlatformError>;
+
+    /// Sends off an HTTP request without waiting to hear back.
+    fn send_request(&self, method: &str, url: &str, body: &str) -> Result<(), PlatformError>;
+
+    /// Asks for a URL without waiting for the answer, which fails with its body unless the
+    /// request succeeded.
+    fn start_fetch(&self, url: &str) -> PendingFetch;
+
+    /// Picks up any admin commands the host typed in since last time.
+    fn read_admin_commands(&self) -> Vec<String>;
+
+    /// Answers the host's admin commands.
+    fn show_admin_output(&self, text: &str);
+}
+
+/// The platform that matches the features this build was compiled with.
+pub fn default_platform() -> Box<dyn Platform> {
+    #[cfg(feature = "web")]
+    let platform = WebPlatform;
+
+    #[cfg(not(feature = "web"))]
+    let platform = NativePlatform::default();
+
+    Box::new(platform)
+}
+
+impl PendingFetch {
+    pub fn ready(result: Result<String, PlatformError>) -> Self {
+        let pending = Self::default();
+        pending.resolve(result);
+        pending
+    }
+
+    pub fn resolve(&self, result: Result<String, PlatformError>) {
+        *self.0.lock().unwrap() = Some(result);
+    }
+
+    /// Hands over the answer once it has arrived.
+    pub fn take(&self) -> Option<Result<String, PlatformError>> {
+        self.0.lock().unwrap().take()
+    }
+}
+
+impl Display for PlatformError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlatformError::Unsupported => write!(f, "not supported on this platform"),
+            PlatformError::Failed(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+/// Everything a [`TestPlatform`] has been asked to do so far.
+#[derive(Debug, Default, Clone)]
+pub struct TestPlatformRecord {
+    pub launch_params: HashMap<String, String>,
+    pub loaded_components: Vec<String>,
+    pub connection_statuses: Vec<ConnectionStatus>,
+    pub shareable_links: Vec<String>,
+    pub clipboard: Option<String>,
+    pub settings: HashMap<String, String>,
+    pub hosted_rooms: Vec<String>,
+    pub restarts: usize,
+    pub requests: Vec<(String, String, String)>,
+
+    /// What to answer [`Platform::start_fetch`] with, by URL. Anything else fails.
+    pub responses: HashMap<String, String>,
+
+    /// Admin commands waiting to be read, as if the host had typed them in.
+    pub admin_commands: Vec<String>,
+    pub admin_output: Vec<String>,
+}
+
+/// A platform for headless tests that records everything instead of touching the outside world.
+/// Clones share the same record, so a test can keep one around to inspect after handing another
+/// one over to the app.
+#[derive(Debug, Default, Clone)]
+pub struct TestPlatform {
+    record: Arc<Mutex<TestPlatformRecord>>,
+    tab_settings: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl TestPlatform {
+    pub fn with_launch_params<'a, I: IntoIterator<Item = (&'a str, &'a str)>>(params: I) -> Self {
+        let platform = Self::default();
+        platform.record.lock().unwrap().launch_params = params
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        platform
+    }
+
+    /// Another tab of the same browser, which shares the record but not the tab settings.
+    pub fn new_tab(&self) -> Self {
+        Self {
+            record: self.record.clone(),
+            tab_settings: Default::default(),
+        }
+    }
+
+    pub fn record(&self) -> TestPlatformRecord {
+        self.record.lock().unwrap().clone()
+    }
+
+    pub fn type_admin_command(&self, line: &str) {
+        self.record
+            .lock()
+            .unwrap()
+            .admin_commands
+            .push(line.to_string());
+    }
+}
+
+impl Platform for TestPlatform {
+    fn launch_param(&self, name: &str) -> Option<String> {
+        self.record.lock().unwrap().launch_params.get(name).cloned()
+    }
+
+    fn report_load_complete(&self, component_name: &str) {
+        self.record
+            .lock()
+            .unwrap()
+            .loaded_components
+            .push(component_name.to_string());
+    }
+
+    fn report_connection_status(&self, status: ConnectionStatus) {
+        self.record.lock().unwrap().connection_statuses.push(status);
+    }
+
+    fn has_touch_screen(&self) -> bool {
+        false
+    }
+
+    fn touches(&self) -> Option<Vec<(u64, [f32; 2])>> {
+        None
+    }
+
+    fn shareable_link(&self, endpoint_id: &str) -> String {
+        format!("test://{}", endpoint_id)
+    }
+
+    fn show_shareable_link(&self, link: &str) {
+        self.record
+            .lock()
+            .unwrap()
+            .shareable_links
+            .push(link.to_string());
+    }
+
+    fn copy_to_clipboard(&self, text: &str) -> Result<(), PlatformError> {
+        self.record.lock().unwrap().clipboard = Some(text.to_string());
+        Ok(())
+    }
+
+    fn load_setting(&self, key: &str) -> Option<String> {
+        self.record.lock().unwrap().settings.get(key).cloned()
+    }
+
+    fn save_setting(&self, key: &str, value: &str) -> Result<(), PlatformError> {
+        self.record
+            .lock()
+            .unwrap()
+            .settings
+            .insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    fn load_tab_setting(&self, key: &str) -> Option<String> {
+        self.tab_settings.lock().unwrap().get(key).cloned()
+    }
+
+    fn save_tab_setting(&self, key: &str, value: &str) -> Result<(), PlatformError> {
+        self.tab_settings
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    fn can_host(&self) -> bool {
+        false
+    }
+
+    fn host_room(&self, room_id: &str) -> Result<(), PlatformError> {
+        self.record
+            .lock()
+            .unwrap()
+            .hosted_rooms
+            .push(room_id.to_string());
+        Ok(())
+    }
+
+    fn restart(&self) -> Result<(), PlatformError> {
+        self.record.lock().unwrap().restarts += 1;
+        Ok(())
+    }
+
+    fn send_request(&self, method: &str, url: &str, body: &str) -> Result<(), PlatformError> {
+        self.record.lock().unwrap().requests.push((
+            method.to_string(),
+            url.to_string(),
+            body.to_string(),
+        ));
+        Ok(())
+    }
+
+    fn start_fetch(&self, url: &str) -> PendingFetch {
+        let mut record = self.record.lock().unwrap();
+        record
+            .requests
+            .push(("GET".to_string(), url.to_string(), String::new()));
+        PendingFetch::ready(
+            record
+                .responses
+                .get(url)
+                .cloned()
+                .ok_or_else(|| PlatformError::Failed(format!("nothing at {}", url))),
+        )
+    }
+
+    fn read_admin_commands(&self) -> Vec<String> {
+        self.record
+            .lock()
+            .unwrap()
+            .admin_commands
+            .drain(..)
+            .collect()
+    }
+
+    fn show_admin_output(&self, text: &str) {
+        self.record
+            .lock()
+            .unwrap()
+            .admin_output
+            .push(text.to_string());
+    }
+}
@@ -0,0 +1,260 @@
+use crate::platform::{ConnectionStatus, PendingFetch, Platform, PlatformError};
+use bevy::prelude::*;
+use crystalorb_bevy_networking_turbulence::bevy_networking_turbulence::find_my_ip_address;
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{self, BufRead, Write},
+    net::SocketAddr,
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::{
+        mpsc::{self, Receiver},
+        Mutex,
+    },
+    thread,
+};
+
+/// Reads launch parameters from the command line and the environment, reports progress through
+/// the log, and keeps settings in a `key=value` file in the user's config directory.
+pub struct NativePlatform {
+    args: Vec<String>,
+    settings_path: PathBuf,
+
+    /// Note: A window is its own tab, and there's no telling a restart apart from opening a second
+    /// window, so these only last as long as the process.
+    tab_settings: Mutex<HashMap<String, String>>,
+
+    /// Lines typed into the terminal, once anyone asks for admin commands.
+    admin_commands: Mutex<Option<Receiver<String>>>,
+}
+
+impl Default for NativePlatform {
+    fn default() -> Self {
+        let config_dir = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+            .unwrap_or_default();
+        Self {
+            args: env::args().skip(1).collect(),
+            settings_path: config_dir.join("dango").join("settings.txt"),
+            tab_settings: Mutex::new(HashMap::new()),
+            admin_commands: Mutex::new(None),
+        }
+    }
+}
+
+impl NativePlatform {
+    fn load_settings(&self) -> Vec<(String, String)> {
+        fs::read_to_string(&self.settings_path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| {
+                let index = line.find('=')?;
+                Some((
+                    line[..index].trim().to_string(),
+                    line[index + 1..].trim().to_string(),
+                ))
+            })
+            .collect()
+    }
+}
+
+impl Platform for NativePlatform {
+    /// Looks for `--name value` or `--name=value` on the command line, falling back to the
+    /// `DANGO_NAME` environment variable. The first bare argument is treated as the value of
+    /// `join`.
+    fn launch_param(&self, name: &str) -> Option<String> {
+        let flag = format!("--{}", name);
+        let flag_with_value = format!("--{}=", name);
+        let mut args = self.args.iter();
+        let mut is_first_bare_arg = true;
+        while let Some(arg) = args.next() {
+            if *arg == flag {
+                return args.next().cloned();
+            } else if let Some(value) = arg.strip_prefix(&flag_with_value) {
+                return Some(value.to_string());
+            } else if arg.starts_with("--") {
+                if !arg.contains('=') {
+                    // Skip over the other flag's value.
+                    args.next();
+                }
+            } else if is_first_bare_arg {
+                if name == "join" {
+                    return Some(arg.clone());
+                }
+                is_first_bare_arg = false;
+            }
+        }
+        env::var(format!("DANGO_{}", name.to_uppercase().replace('-', "_"))).ok()
+    }
+
+    fn report_load_complete(&self, component_name: &str) {
+        info!("Finished loading {}", component_name);
+    }
+
+    fn report_connection_status(&self, status: ConnectionStatus) {
+        match status {
+            ConnectionStatus::Connecting => info!("Connecting..."),
+            ConnectionStatus::Connected => info!("Hooray! You are connected."),
+            ConnectionStatus::Full => {
+                warn!("The room is full. Watching until someone leaves...")
+            }
+            ConnectionStatus::RoomFull => {
+                warn!("The room is full. Trying again once someone leaves...")
+            }
+            ConnectionStatus::Removed => warn!("The host removed you from the room."),
+            ConnectionStatus::Disconnected => warn!("Oh no! You got disconnected."),
+            ConnectionStatus::Offline => info!("Playing offline on your own."),
+        }
+    }
+
+    /// Natively, touch controls only show up once the screen actually gets touched.
+    fn has_touch_screen(&self) -> bool {
+        false
+    }
+
+    fn touches(&self) -> Option<Vec<(u64, [f32; 2])>> {
+        None
+    }
+
+    fn shareable_link(&self, endpoint_id: &str) -> String {
+        match endpoint_id.parse::<SocketAddr>() {
+            // Note: Nobody can connect to 0.0.0.0, so point them to this machine instead.
+            Ok(address) if address.ip().is_unspecified() => find_my_ip_address()
+                .map(|ip| SocketAddr::new(ip, address.port()).to_string())
+                .unwrap_or_else(|| endpoint_id.to_string()),
+            _ => endpoint_id.to_string(),
+        }
+    }
+
+    fn show_shareable_link(&self, link: &str) {
+        println!("Join address: {}", link);
+    }
+
+    fn copy_to_clipboard(&self, text: &str) -> Result<(), PlatformError> {
+        // Note: Rather than pulling in a clipboard crate, hand it over to whichever clipboard
+        // tool the system has.
+        let candidates: &[(&str, &[&str])] = &[
+            ("pbcopy", &[]),
+            ("wl-copy", &[]),
+            ("xclip", &["-selection", "clipboard"]),
+            ("xsel", &["--clipboard", "--input"]),
+            ("clip", &[]),
+        ];
+        for (program, args) in candidates {
+            let child = Command::new(program)
+                .args(*args)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn();
+            if let Ok(mut child) = child {
+                let written = child
+                    .stdin
+                    .take()
+                    .map_or(false, |mut stdin| stdin.write_all(text.as_bytes()).is_ok());
+                if written && child.wait().map_or(false, |status| status.success()) {
+                    return Ok(());
+                }
+            }
+        }
+        Err(PlatformError::Unsupported)
+    }
+
+    fn load_setting(&self, key: &str) -> Option<String> {
+        self.load_settings()
+            .into_iter()
+            .find(|(setting_key, _)| setting_key == key)
+            .map(|(_, value)| value)
+    }
+
+    fn save_setting(&self, key: &str, value: &str) -> Result<(), PlatformError> {
+        let mut settings = self.load_settings();
+        match settings
+            .iter_mut()
+            .find(|(setting_key, _)| setting_key == key)
+        {
+            Some((_, existing_value)) => *existing_value = value.to_string(),
+            None => settings.push((key.to_string(), value.to_string())),
+        }
+        let contents: String = settings
+            .iter()
+            .map(|(key, value)| format!("{}={}\n", key, value.replace('\n', " ")))
+            .collect();
+        self.settings_path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&self.settings_path, contents))
+            .map_err(|err| {
+                PlatformError::Failed(format!(
+                    "could not write to {}: {}",
+                    self.settings_path.display(),
+                    err
+                ))
+            })
+    }
+
+    fn load_tab_setting(&self, key: &str) -> Option<String> {
+        self.tab_settings.lock().unwrap().get(key).cloned()
+    }
+
+    fn save_tab_setting(&self, key: &str, value: &str) -> Result<(), PlatformError> {
+        self.tab_settings
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    /// Native clients talk straight to dedicated servers, which don't go away with a player.
+    fn can_host(&self) -> bool {
+        false
+    }
+
+    fn host_room(&self, _room_id: &str) -> Result<(), PlatformError> {
+        Err(PlatformError::Unsupported)
+    }
+
+    fn restart(&self) -> Result<(), PlatformError> {
+        Err(PlatformError::Unsupported)
+    }
+
+    /// Only web builds need to talk to the signalling server.
+    fn send_request(&self, _method: &str, _url: &str, _body: &str) -> Result<(), PlatformError> {
+        Err(PlatformError::Unsupported)
+    }
+
+    fn start_fetch(&self, _url: &str) -> PendingFetch {
+        PendingFetch::ready(Err(PlatformError::Unsupported))
+    }
+
+    /// Reads admin commands from the terminal, one per line.
+    fn read_admin_commands(&self) -> Vec<String> {
+        let mut admin_commands = self.admin_commands.lock().unwrap();
+        let receiver = admin_commands.get_or_insert_with(|| {
+            // Note: Reading stdin blocks, so leave that to a thread of its own.
+            let (sender, receiver) = mpsc::channel();
+            thread::spawn(move || {
+                for line in io::stdin().lock().lines() {
+                    match line {
+                        Ok(line) if line.trim().is_empty() => {}
+                        Ok(line) => {
+                            if sender.send(line).is_err() {
+                                break;
+                            }
+                        }
+                        Err(_) => break,
+                    }
+                }
+            });
+            receiver
+        });
+        receiver.try_iter().collect()
+    }
+
+    fn show_admin_output(&self, text: &str) {
+        println!("{}", text);
+    }
+}
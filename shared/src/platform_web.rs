@@ -0,0 +1,320 @@
+use crate::{
+    host_migration::{HOSTING_PARAM, ROOM_PARAM},
+    platform::{ConnectionStatus, PendingFetch, Platform, PlatformError},
+    signalling::SIGNALLING_URL_PARAM,
+};
+use bevy::prelude::*;
+use std::cell::RefCell;
+use wasm_bindgen::{closure::Closure, JsCast};
+use web_sys::{
+    Document, HtmlDocument, HtmlTextAreaElement, Storage, TouchEvent, Url, UrlSearchParams,
+    XmlHttpRequest,
+};
+
+thread_local! {
+    /// Every finger on the page, kept up to date by touch event listeners once anyone asks.
+    static TOUCHES: RefCell<Option<Vec<(u64, [f32; 2])>>> = RefCell::new(None);
+}
+
+/// Talks to the page the game is embedded in.
+pub struct WebPlatform;
+
+impl WebPlatform {
+    /// Resolves a url relative to the current page, keeping track of which signalling server to
+    /// use.
+    fn relative_url(&self, relative_url: &str) -> Url {
+        let document_location: String = document()
+            .location()
+            .expect("document should have a location")
+            .to_string()
+            .into();
+        let url = Url::new_with_base(relative_url, &document_location)
+            .expect("resulting url should be valid");
+        // Note: Whoever opens the url needs to find its way to the same signalling server.
+        if let Some(signalling_url) = self.launch_param(SIGNALLING_URL_PARAM) {
+            url.search_params()
+                .set(SIGNALLING_URL_PARAM, &signalling_url);
+        }
+        url
+    }
+}
+
+fn document() -> Document {
+    web_sys::window()
+        .expect("should have global window")
+        .document()
+        .expect("window should have document")
+}
+
+/// Listens for touches on the page, since winit only passes the first one on, and only as a mouse.
+fn listen_for_touches() {
+    let on_touch = Closure::wrap(Box::new(|event: TouchEvent| {
+        // Note: The game's canvas fills the window, but measure it anyway so that positions match
+        // what bevy sees.
+        let canvas_rect = document()
+            .query_selector("canvas")
+            .ok()
+            .flatten()
+            .map(|canvas| canvas.get_bounding_client_rect());
+        let (left, bottom) = canvas_rect.map_or_else(
+            || {
+                let window = web_sys::window().expect("should have global window");
+                let height = window
+                    .inner_height()
+                    .ok()
+                    .and_then(|height| height.as_f64())
+                    .unwrap_or(0.0);
+                (0.0, height)
+            },
+            |rect| (rect.left(), rect.bottom()),
+        );
+        let event_touches = event.touches();
+        let touches = (0..event_touches.length())
+            .filter_map(|index| event_touches.get(index))
+            .map(|touch| {
+                (
+                    touch.identifier() as u32 as u64,
+                    [
+                        (touch.client_x() as f64 - left) as f32,
+                        (bottom - touch.client_y() as f64) as f32,
+                    ],
+                )
+            })
+            .collect();
+        TOUCHES.with(|cell| *cell.borrow_mut() = Some(touches));
+    }) as Box<dyn FnMut(TouchEvent)>);
+    let window = web_sys::window().expect("should have global window");
+    for event_type in ["touchstart", "touchmove", "touchend", "touchcancel"].iter() {
+        if let Err(err) =
+            window.add_event_listener_with_callback(event_type, on_touch.as_ref().unchecked_ref())
+        {
+            warn!("Could not listen for {} events: {:?}", event_type, err);
+        }
+    }
+    // Note: The listeners stay for as long as the page does.
+    on_touch.forget();
+}
+
+fn local_storage() -> Option<Storage> {
+    web_sys::window()
+        .expect("should have global window")
+        .local_storage()
+        .ok()
+        .flatten()
+}
+
+fn session_storage() -> Option<Storage> {
+    web_sys::window()
+        .expect("should have global window")
+        .session_storage()
+        .ok()
+        .flatten()
+}
+
+impl Platform for WebPlatform {
+    fn launch_param(&self, name: &str) -> Option<String> {
+        UrlSearchParams::new_with_str(
+            &web_sys::window()
+                .expect("should have global window")
+                .location()
+                .search()
+                .expect("should have search string"),
+        )
+        .expect("should parse valid search params")
+        .get(name)
+    }
+
+    fn report_load_complete(&self, component_name: &str) {
+        if let Some(loading_screen) = document().get_element_by_id("loading-screen") {
+            loading_screen
+                .class_list()
+                .add_1(&format!("load-complete-{}", component_name))
+                .expect("should be able to add a class to span");
+        }
+    }
+
+    fn report_connection_status(&self, status: ConnectionStatus) {
+        let class_name = match status {
+            ConnectionStatus::Connecting => "status-connecting",
+            ConnectionStatus::Connected => "status-connected",
+            ConnectionStatus::Full => "status-full",
+            ConnectionStatus::RoomFull => "status-room-full",
+            ConnectionStatus::Removed => "status-removed",
+            ConnectionStatus::Disconnected => "status-disconnected",
+            ConnectionStatus::Offline => "status-offline",
+        };
+        if let Some(status_element) = document().get_element_by_id("status") {
+            status_element.set_class_name(class_name);
+        }
+    }
+
+    fn has_touch_screen(&self) -> bool {
+        web_sys::window()
+            .expect("should have global window")
+            .navigator()
+            .max_touch_points()
+            > 0
+    }
+
+    fn touches(&self) -> Option<Vec<(u64, [f32; 2])>> {
+        TOUCHES.with(|cell| {
+            let mut touches = cell.borrow_mut();
+            if touches.is_none() {
+                listen_for_touches();
+                *touches = Some(Vec::new());
+            }
+            touches.clone()
+        })
+    }
+
+    fn shareable_link(&self, endpoint_id: &str) -> String {
+        self.relative_url(&format!("../client/?join={}", endpoint_id))
+            .href()
+    }
+
+    fn show_shareable_link(&self, link: &str) {
+        let document = document();
+        if let Some(join_url) = document.get_element_by_id("join-url") {
+            join_url
+                .set_attribute("value", link)
+                .expect("setting value attribute should succeed");
+        }
+        if let Some(client_iframe) = document.get_element_by_id("client-iframe") {
+            // Note: The host's own client goes away along with the host, so it can't take over.
+            let url = Url::new(link).expect("shareable link should be a valid url");
+            url.search_params().set(HOSTING_PARAM, "1");
+            client_iframe
+                .set_attribute("src", &url.href())
+                .expect("setting src attribute should succeed");
+        }
+    }
+
+    fn copy_to_clipboard(&self, text: &str) -> Result<(), PlatformError> {
+        // Note: The async clipboard API is still unstable in web-sys, so go through a temporary
+        // text area and the copy command instead.
+        let document = document();
+        let body = document
+            .body()
+            .ok_or_else(|| PlatformError::Failed("document has no body".into()))?;
+        let text_area: HtmlTextAreaElement = document
+            .create_element("textarea")
+            .map_err(|err| PlatformError::Failed(format!("{:?}", err)))?
+            .unchecked_into();
+        text_area.set_value(text);
+        body.append_child(&text_area)
+            .map_err(|err| PlatformError::Failed(format!("{:?}", err)))?;
+        text_area.select();
+        let copied = document
+            .unchecked_ref::<HtmlDocument>()
+            .exec_command("copy")
+            .unwrap_or(false);
+        text_area.remove();
+        if copied {
+            Ok(())
+        } else {
+            Err(PlatformError::Failed("the browser refused to copy".into()))
+        }
+    }
+
+    fn load_setting(&self, key: &str) -> Option<String> {
+        local_storage()?.get_item(key).ok().flatten()
+    }
+
+    fn save_setting(&self, key: &str, value: &str) -> Result<(), PlatformError> {
+        let storage = local_storage().ok_or(PlatformError::Unsupported)?;
+        storage.set_item(key, value).map_err(|err| {
+            warn!("Could not save {} to local storage: {:?}", key, err);
+            PlatformError::Failed(format!("{:?}", err))
+        })
+    }
+
+    /// Session storage survives reloads and navigating within the tab, but isn't shared with
+    /// other tabs.
+    fn load_tab_setting(&self, key: &str) -> Option<String> {
+        session_storage()?.get_item(key).ok().flatten()
+    }
+
+    fn save_tab_setting(&self, key: &str, value: &str) -> Result<(), PlatformError> {
+        let storage = session_storage().ok_or(PlatformError::Unsupported)?;
+        storage.set_item(key, value).map_err(|err| {
+            warn!("Could not save {} to session storage: {:?}", key, err);
+            PlatformError::Failed(format!("{:?}", err))
+        })
+    }
+
+    fn can_host(&self) -> bool {
+        self.launch_param(HOSTING_PARAM).is_none()
+    }
+
+    fn host_room(&self, room_id: &str) -> Result<(), PlatformError> {
+        let url = self.relative_url("../server/");
+        url.search_params().set(ROOM_PARAM, room_id);
+        web_sys::window()
+            .expect("should have global window")
+            .location()
+            .assign(&url.href())
+            .map_err(|err| PlatformError::Failed(format!("{:?}", err)))
+    }
+
+    fn restart(&self) -> Result<(), PlatformError> {
+        web_sys::window()
+            .expect("should have global window")
+            .location()
+            .reload()
+            .map_err(|err| PlatformError::Failed(format!("{:?}", err)))
+    }
+
+    fn send_request(&self, method: &str, url: &str, body: &str) -> Result<(), PlatformError> {
+        let request =
+            XmlHttpRequest::new().map_err(|err| PlatformError::Failed(format!("{:?}", err)))?;
+        request
+            .open(method, url)
+            .and_then(|_| request.send_with_opt_str(Some(body)))
+            .map_err(|err| PlatformError::Failed(format!("{:?}", err)))
+    }
+
+    fn start_fetch(&self, url: &str) -> PendingFetch {
+        let pending = PendingFetch::default();
+        let sent = XmlHttpRequest::new().and_then(|request| {
+            let on_load_end = {
+                let pending = pending.clone();
+                let request = request.clone();
+                // Note: This frees itself once called, which it always is, even if the request
+                // fails.
+                Closure::once_into_js(move || {
+                    let body = request.response_text().ok().flatten().unwrap_or_default();
+                    pending.resolve(match request.status() {
+                        Ok(status) if (200..300).contains(&status) => Ok(body),
+                        _ => Err(PlatformError::Failed(body)),
+                    });
+                })
+            };
+            request.set_onloadend(Some(on_load_end.unchecked_ref()));
+            request.open("GET", url)?;
+            request.send()
+        });
+        if let Err(err) = sent {
+            pending.resolve(Err(PlatformError::Failed(format!("{:?}", err))));
+        }
+        pending
+    }
+
+    /// The admin panel on the server page queues commands up as `data-command` attributes on
+    /// the children of `#admin-queue`.
+    fn read_admin_commands(&self) -> Vec<String> {
+        let mut admin_commands = Vec::new();
+        if let Some(admin_queue) = document().get_element_by_id("admin-queue") {
+            while let Some(queued_command) = admin_queue.first_element_child() {
+                admin_commands.extend(queued_command.get_attribute("data-command"));
+                queued_command.remove();
+            }
+        }
+        admin_commands
+    }
+
+    fn show_admin_output(&self, text: &str) {
+        if let Some(admin_output) = document().get_element_by_id("admin-output") {
+            admin_output.set_text_content(Some(text));
+        }
+    }
+}
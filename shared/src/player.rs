@@ -1,4 +1,12 @@
-use crate::settings::RealField;
+use crate::{
+    appearance::{EyeStyle, PlayerAppearance},
+    collision_groups::PlayerCollisionGroups,
+    color::Rgb,
+    session::SessionHash,
+    settings::{self, RealField},
+    surface::SurfaceMaterial,
+    team::TeamId,
+};
 use bevy::prelude::*;
 use crystalorb_bevy_networking_turbulence::crystalorb::world::DisplayState;
 use nphysics2d::{
@@ -42,16 +50,71 @@ const PHYSICS_CONFIG: ControlledDangoConfig = ControlledDangoConfig {
     crawl_side_amplitude: 8.0,
 };
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct PlayerId(pub usize);
 
+impl PlayerId {
+    /// Ids from here on belong to dangos that the server plays itself. They stay well clear of
+    /// connection handles, and still fit in a 32 bit usize on the web.
+    const NPC_BASE: usize = 1 << 30;
+
+    /// Ids from here on belong to dangos brought over from a previous host, until their clients
+    /// reconnect and reclaim them.
+    const RESTORED_BASE: usize = 1 << 29;
+
+    /// A client's extra local players keep its connection handle in the low bits, and their slot
+    /// in the bits above. The first local player's id is just the connection handle.
+    const LOCAL_SLOT_SHIFT: usize = 24;
+    const CLIENT_ID_MASK: usize = (1 << Self::LOCAL_SLOT_SHIFT) - 1;
+
+    pub const MAX_LOCAL_PLAYERS: usize = 4;
+
+    pub fn npc(index: usize) -> Self {
+        PlayerId(Self::NPC_BASE | index)
+    }
+
+    pub fn is_npc(&self) -> bool {
+        self.0 & Self::NPC_BASE != 0
+    }
+
+    pub fn restored(index: usize) -> Self {
+        PlayerId(Self::RESTORED_BASE | index)
+    }
+
+    pub fn is_restored(&self) -> bool {
+        !self.is_npc() && self.0 & Self::RESTORED_BASE != 0
+    }
+
+    pub fn local(client_id: usize, slot: usize) -> Self {
+        PlayerId(slot << Self::LOCAL_SLOT_SHIFT | client_id & Self::CLIENT_ID_MASK)
+    }
+
+    /// The client that controls this player, unless the server does.
+    pub fn client_id(&self) -> Option<usize> {
+        if self.is_npc() || self.is_restored() {
+            None
+        } else {
+            Some(self.0 & Self::CLIENT_ID_MASK)
+        }
+    }
+
+    /// Which of its client's local players this is.
+    pub fn local_slot(&self) -> usize {
+        (self.0 & !Self::NPC_BASE & !Self::RESTORED_BASE) >> Self::LOCAL_SLOT_SHIFT
+    }
+}
+
 pub struct Player {
-    size: f32,
-    color: Color,
+    appearance: PlayerAppearance,
     body: DefaultBodyHandle,
     collider: DefaultColliderHandle,
+    collision_groups: PlayerCollisionGroups,
+    score: i32,
     inputs: PlayerInputState,
     forces: PlayerForcesState,
+    session: Option<SessionHash>,
+    away_steps: Option<u32>,
+    can_host: bool,
 
     semiderived_collision_state: PlayerCollisionState,
 
@@ -62,12 +125,20 @@ pub struct Player {
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct PlayerSnapshot {
-    pub color: Color,
-    pub size: f32,
+    pub appearance: PlayerAppearance,
     pub positions: Vec<RealField>,
     pub velocities: Vec<RealField>,
+    pub collision_groups: PlayerCollisionGroups,
+    pub score: i32,
     pub inputs: PlayerInputState,
     pub forces: PlayerForcesState,
+    pub session: Option<SessionHash>,
+
+    /// How long the player has been gone for, if their client dropped out.
+    pub away_steps: Option<u32>,
+
+    /// Whether the player's client could take over hosting the room if the host left.
+    pub can_host: bool,
 
     // Note: While this information can be derived from the colliders,
     // we don't sync collider information with the server, so we need
@@ -79,8 +150,14 @@ pub struct PlayerSnapshot {
 
 #[derive(Default, Clone, Debug)]
 pub struct PlayerDisplayState {
-    pub color: Color,
+    pub color: Rgb,
     pub size: f32,
+    pub eye_style: EyeStyle,
+    pub nickname: String,
+    pub team: Option<TeamId>,
+    pub score: i32,
+    pub is_away: bool,
+    pub can_host: bool,
     pub measurements: PhysicsBodyMeasurements,
     pub local_positions: Vec<Vector2<RealField>>,
     pub local_velocities: Vec<Vector2<RealField>>,
@@ -88,20 +165,61 @@ pub struct PlayerDisplayState {
     pub boundary_indices: Vec<usize>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
 pub struct PlayerInputState {
-    pub left: bool,
-    pub right: bool,
+    /// How hard to move sideways, from all the way left at `-MAX_HORIZONTAL` to all the way
+    /// right at `MAX_HORIZONTAL`. It is quantised so that it stays small on the wire and every
+    /// client steps with exactly the same value.
+    pub horizontal: i8,
+
     pub jump: bool,
     pub roll: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub enum PlayerInputCommand {
-    Left(bool),
-    Right(bool),
-    Jump(bool),
-    Roll(bool),
+impl PlayerInputState {
+    pub const MAX_HORIZONTAL: i8 = i8::MAX;
+
+    /// Quantises an axis that goes from -1.0 for all the way left to 1.0 for all the way right.
+    pub fn quantise_horizontal(axis: f32) -> i8 {
+        (axis.max(-1.0).min(1.0) * Self::MAX_HORIZONTAL as f32).round() as i8
+    }
+
+    pub fn horizontal_axis(&self) -> RealField {
+        self.horizontal as RealField / Self::MAX_HORIZONTAL as RealField
+    }
+}
+
+/// A player's whole input state, packed into two bytes with the horizontal axis in the low byte
+/// and a bit each for jump and roll above it. Every command carries everything, so a lost one
+/// gets made up for by whichever command arrives next.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlayerInputCommand(u16);
+
+impl PlayerInputCommand {
+    const HORIZONTAL_BITS: u16 = 0xff;
+    const JUMP_BIT: u16 = 1 << 8;
+    const ROLL_BIT: u16 = 1 << 9;
+
+    pub fn new(state: &PlayerInputState) -> Self {
+        let mut bits = state.horizontal as u8 as u16;
+        if state.jump {
+            bits |= Self::JUMP_BIT;
+        }
+        if state.roll {
+            bits |= Self::ROLL_BIT;
+        }
+        Self(bits)
+    }
+
+    pub fn state(&self) -> PlayerInputState {
+        PlayerInputState {
+            // Note: -128 doesn't have a matching right hand side, so it gets clamped.
+            horizontal: ((self.0 & Self::HORIZONTAL_BITS) as u8 as i8)
+                .max(-PlayerInputState::MAX_HORIZONTAL),
+            jump: self.0 & Self::JUMP_BIT != 0,
+            roll: self.0 & Self::ROLL_BIT != 0,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
@@ -114,6 +232,7 @@ pub struct PlayerForcesState {
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct PlayerCollisionState {
     has_feet_contact: bool,
+    feet_surface: SurfaceMaterial,
 }
 
 const PLAYER_FEM_MESH_VERTICES: [[RealField; 2]; 9] = [
@@ -156,9 +275,9 @@ impl Player {
     const COLLISION_MARGIN: RealField = 0.07;
 
     pub fn new(
-        color: Color,
-        size: f32,
+        appearance: PlayerAppearance,
         position: Vector2<RealField>,
+        collision_groups: PlayerCollisionGroups,
         bodies: &mut DefaultBodySet<RealField>,
         colliders: &mut DefaultColliderSet<RealField>,
     ) -> Self {
@@ -173,13 +292,14 @@ impl Player {
                 .collect::<Vec<Point3<usize>>>(),
         )
         .translation(position)
-        .scale(Vector2::repeat(size))
+        .scale(Vector2::repeat(appearance.size))
         .young_modulus(1.0e2)
         .mass_damping(0.2)
         .build();
         let collider_desc = fem_surface
             .boundary_collider_desc()
-            .margin(Self::COLLISION_MARGIN);
+            .margin(Self::COLLISION_MARGIN)
+            .collision_groups(collision_groups.collision_groups());
 
         let derived_mesh_indices = fem_surface
             .deformed_indices()
@@ -207,12 +327,16 @@ impl Player {
 
         let collider_handle = colliders.insert(collider);
         Self {
-            color,
-            size,
+            appearance,
             body: body_handle,
             collider: collider_handle,
+            collision_groups,
+            score: 0,
             inputs: Default::default(),
             forces: Default::default(),
+            session: None,
+            away_steps: None,
+            can_host: false,
             derived_measurements: Default::default(),
             derived_mesh_indices,
             derived_boundary_indices,
@@ -233,7 +357,14 @@ impl Player {
         &mut self,
         snapshot: &PlayerSnapshot,
         bodies: &mut DefaultBodySet<RealField>,
+        colliders: &mut DefaultColliderSet<RealField>,
     ) {
+        if snapshot.collision_groups != self.collision_groups {
+            self.set_collision_groups(snapshot.collision_groups, colliders);
+        }
+        if snapshot.appearance != self.appearance {
+            self.appearance = snapshot.appearance.clone();
+        }
         let body = bodies.get_mut(self.body).unwrap();
         for (i, body_position) in body
             .deformed_positions_mut()
@@ -255,20 +386,91 @@ impl Player {
                 warn!("Not enough velocity values from snapshot to fill body");
             }
         }
+        self.score = snapshot.score;
         self.inputs = snapshot.inputs.clone();
         self.forces = snapshot.forces.clone();
+        self.session = snapshot.session;
+        self.away_steps = snapshot.away_steps;
+        self.can_host = snapshot.can_host;
         self.semiderived_collision_state = snapshot.semiderived_collision_state.clone();
     }
 
-    pub fn apply_command(&mut self, command: &PlayerInputCommand) {
-        match command {
-            PlayerInputCommand::Left(state) => self.inputs.left = *state,
-            PlayerInputCommand::Right(state) => self.inputs.right = *state,
-            PlayerInputCommand::Jump(state) => self.inputs.jump = *state,
-            PlayerInputCommand::Roll(state) => self.inputs.roll = *state,
+    pub fn set_collision_groups(
+        &mut self,
+        collision_groups: PlayerCollisionGroups,
+        colliders: &mut DefaultColliderSet<RealField>,
+    ) {
+        self.collision_groups = collision_groups;
+        colliders
+            .get_mut(self.collider)
+            .unwrap()
+            .set_collision_groups(collision_groups.collision_groups());
+    }
+
+    pub fn appearance(&self) -> &PlayerAppearance {
+        &self.appearance
+    }
+
+    pub fn set_color(&mut self, color: Rgb) {
+        self.appearance.color = color;
+    }
+
+    pub fn team(&self) -> Option<TeamId> {
+        self.collision_groups.team
+    }
+
+    pub fn set_team(
+        &mut self,
+        team: Option<TeamId>,
+        colliders: &mut DefaultColliderSet<RealField>,
+    ) {
+        let collision_groups = PlayerCollisionGroups {
+            team,
+            ..self.collision_groups
+        };
+        self.set_collision_groups(collision_groups, colliders);
+    }
+
+    pub fn award_points(&mut self, points: i32) {
+        self.score += points;
+    }
+
+    pub fn session(&self) -> Option<SessionHash> {
+        self.session
+    }
+
+    pub fn is_away(&self) -> bool {
+        self.away_steps.is_some()
+    }
+
+    /// Hands the dango over to whoever reclaimed it, ready for them to reclaim it again next time.
+    pub fn start_session(&mut self, session: Option<SessionHash>, can_host: bool) {
+        self.session = session;
+        self.away_steps = None;
+        self.can_host = can_host;
+    }
+
+    /// Leaves the dango sitting still while waiting for its client to come back.
+    pub fn go_away(&mut self) {
+        self.inputs = PlayerInputState::default();
+        self.away_steps = Some(0);
+    }
+
+    /// Returns whether the dango has been waiting for longer than its client gets to reclaim it.
+    pub fn step_away(&mut self) -> bool {
+        match &mut self.away_steps {
+            Some(away_steps) => {
+                *away_steps += 1;
+                *away_steps as f64 * settings::TIMESTEP > settings::SESSION_GRACE_PERIOD
+            }
+            None => false,
         }
     }
 
+    pub fn apply_command(&mut self, command: &PlayerInputCommand) {
+        self.inputs = command.state();
+    }
+
     fn update_measurements(&mut self, body: &dyn Body<RealField>) {
         let mut center_of_mass = Vector2::<RealField>::new(0.0, 0.0);
         let mut sum_of_sine_angle = 0.0;
@@ -320,25 +522,38 @@ impl Player {
         };
     }
 
-    fn has_feet_contact(
+    fn feet_contact(
         &self,
         colliders: &DefaultColliderSet<RealField>,
         geometrical_world: &DefaultGeometricalWorld<RealField>,
-    ) -> bool {
+    ) -> Option<SurfaceMaterial> {
         let collider = colliders.get(self.collider).unwrap();
         if collider.graph_index().is_some() {
-            for (_, _, _, _, _, manifold) in geometrical_world
+            for (handle1, collider1, _, collider2, _, manifold) in geometrical_world
                 .contacts_with(colliders, self.collider, true)
                 .unwrap()
             {
                 for contact in manifold.contacts() {
                     if contact.contact.world1.y < self.derived_measurements.center_of_mass.y {
-                        return true;
+                        let other_collider = if handle1 == self.collider {
+                            collider2
+                        } else {
+                            collider1
+                        };
+
+                        // Other dangos don't carry a surface material.
+                        return Some(
+                            other_collider
+                                .user_data()
+                                .and_then(|data| data.downcast_ref::<SurfaceMaterial>())
+                                .copied()
+                                .unwrap_or_default(),
+                        );
                     }
                 }
             }
         }
-        return false;
+        return None;
     }
 
     fn step_variable_jump_force(&mut self, dt: RealField) {
@@ -354,14 +569,14 @@ impl Player {
     }
 
     fn step_horizontal_force(&mut self) {
-        self.forces.horizontal_force = ((self.inputs.right as i32 as RealField)
-            - (self.inputs.left as i32 as RealField))
+        self.forces.horizontal_force = self.inputs.horizontal_axis()
             * if !self.semiderived_collision_state.has_feet_contact {
                 PHYSICS_CONFIG.horizontal_air_movement_force
             } else if self.inputs.roll {
                 PHYSICS_CONFIG.horizontal_rolling_movement_force
             } else {
                 PHYSICS_CONFIG.horizontal_crawling_movement_force
+                    * self.semiderived_collision_state.feet_surface.traction()
             };
     }
 
@@ -484,7 +699,12 @@ impl Player {
         let drag = if should_apply_drag {
             // Don't apply ground drag to y axis to prevent artificial bounciness due to the
             // impact incidence velocity.
-            -PHYSICS_CONFIG.ground_drag * self.derived_measurements.velocity.x
+            -PHYSICS_CONFIG.ground_drag
+                * self
+                    .semiderived_collision_state
+                    .feet_surface
+                    .drag_multiplier
+                * self.derived_measurements.velocity.x
                 / body.num_parts() as RealField
         } else {
             0.0
@@ -502,19 +722,24 @@ impl Player {
         colliders: &DefaultColliderSet<RealField>,
         geometrical_world: &DefaultGeometricalWorld<RealField>,
     ) {
-        self.semiderived_collision_state.has_feet_contact =
-            self.has_feet_contact(colliders, geometrical_world);
+        let feet_contact = self.feet_contact(colliders, geometrical_world);
+        self.semiderived_collision_state.has_feet_contact = feet_contact.is_some();
+        self.semiderived_collision_state.feet_surface = feet_contact.unwrap_or_default();
     }
 
     pub fn snapshot(&self, bodies: &DefaultBodySet<RealField>) -> PlayerSnapshot {
         let body = bodies.get(self.body).unwrap();
         PlayerSnapshot {
-            color: self.color,
-            size: self.size,
+            appearance: self.appearance.clone(),
             positions: body.deformed_positions().unwrap().1.into(),
             velocities: body.generalized_velocity().iter().copied().collect(),
+            collision_groups: self.collision_groups,
+            score: self.score,
             inputs: self.inputs.clone(),
             forces: self.forces.clone(),
+            session: self.session,
+            away_steps: self.away_steps,
+            can_host: self.can_host,
             semiderived_collision_state: self.semiderived_collision_state.clone(),
         }
     }
@@ -524,12 +749,18 @@ impl Player {
         let to_local_coords = Similarity2::new(
             self.derived_measurements.center_of_mass,
             self.derived_measurements.mean_angle,
-            self.size,
+            self.appearance.size,
         )
         .inverse();
         PlayerDisplayState {
-            color: self.color,
-            size: self.size,
+            color: self.appearance.color,
+            size: self.appearance.size,
+            eye_style: self.appearance.eye_style,
+            nickname: self.appearance.nickname.clone(),
+            team: self.team(),
+            score: self.score,
+            is_away: self.is_away(),
+            can_host: self.can_host,
             measurements: self.derived_measurements.clone(),
             local_positions: body
                 .deformed_positions()
@@ -617,3 +848,83 @@ impl DisplayState for PlayerDisplayState {
         state
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn input_commands_round_trip() {
+        for &horizontal in [0, 1, -1, 64, -64, PlayerInputState::MAX_HORIZONTAL].iter() {
+            for &jump in [false, true].iter() {
+                for &roll in [false, true].iter() {
+                    let state = PlayerInputState {
+                        horizontal,
+                        jump,
+                        roll,
+                    };
+                    assert_eq!(PlayerInputCommand::new(&state).state(), state);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn input_commands_clamp_the_unmatched_minimum() {
+        let state = PlayerInputState {
+            horizontal: i8::MIN,
+            jump: true,
+            roll: false,
+        };
+        assert_eq!(
+            PlayerInputCommand::new(&state).state(),
+            PlayerInputState {
+                horizontal: -PlayerInputState::MAX_HORIZONTAL,
+                ..state
+            }
+        );
+    }
+
+    #[test]
+    fn quantises_the_horizontal_axis() {
+        assert_eq!(PlayerInputState::quantise_horizontal(0.0), 0);
+        assert_eq!(
+            PlayerInputState::quantise_horizontal(1.0),
+            PlayerInputState::MAX_HORIZONTAL
+        );
+        assert_eq!(
+            PlayerInputState::quantise_horizontal(-2.0),
+            -PlayerInputState::MAX_HORIZONTAL
+        );
+    }
+
+    #[test]
+    fn local_player_ids_keep_their_client_and_slot() {
+        for &client_id in [0, 1, 12345, PlayerId::CLIENT_ID_MASK].iter() {
+            for slot in 0..PlayerId::MAX_LOCAL_PLAYERS {
+                let player_id = PlayerId::local(client_id, slot);
+                assert_eq!(player_id.client_id(), Some(client_id));
+                assert_eq!(player_id.local_slot(), slot);
+                assert!(!player_id.is_npc());
+                assert!(!player_id.is_restored());
+            }
+        }
+        assert_eq!(PlayerId::local(7, 0), PlayerId(7));
+    }
+
+    #[test]
+    fn npc_and_restored_ids_have_no_client() {
+        for &index in [0, 1, 99].iter() {
+            let npc_id = PlayerId::npc(index);
+            assert!(npc_id.is_npc());
+            assert!(!npc_id.is_restored());
+            assert_eq!(npc_id.client_id(), None);
+
+            let restored_id = PlayerId::restored(index);
+            assert!(restored_id.is_restored());
+            assert!(!restored_id.is_npc());
+            assert_eq!(restored_id.client_id(), None);
+        }
+        assert_ne!(PlayerId::npc(3), PlayerId::restored(3));
+    }
+}
@@ -1,83 +1,527 @@
 use super::{
+    appearance::PlayerAppearance,
+    input_bindings::{ActionBindings, AxisDirection, GamepadInput, InputAction, InputBindings},
     physics_multiplayer::{PhysicsCommand, PhysicsWorld},
+    physics_offline::{OfflinePhysics, OFFLINE_CLIENT_ID},
+    platform::Platform,
     player::{PlayerId, PlayerInputCommand, PlayerInputState},
+    session::LocalSessions,
+    settings,
+    team::TeamId,
 };
-use bevy::prelude::*;
+use bevy::{ecs::system::SystemParam, prelude::*};
 use crystalorb_bevy_networking_turbulence::{
     bevy_networking_turbulence::NetworkResource,
     crystalorb::client::{stage::StageMut as ClientStageMut, Client},
     WrappedNetworkResource,
 };
+use std::collections::HashMap;
 
-pub const INPUT_RESYNC_INTERVAL: f64 = 3.0;
+/// How long to wait before asking to join again, in case the previous request got lost or the
+/// server hasn't seen it yet.
+pub const JOIN_RETRY_INTERVAL: f64 = 3.0;
 
+/// Sends a player's whole input state as soon as it changes, and then again every tick, so that
+/// a lost command only holds up the dango until the next one gets through.
+#[derive(Default, Clone)]
+pub struct InputSender {
+    sent: Option<(PlayerInputState, f64)>,
+}
+
+/// When each local player last asked to join, by slot.
 #[derive(Default)]
-pub struct LastResetTime(f64);
+pub struct LastJoinRequestTimes(HashMap<usize, f64>);
+
+/// The appearance this client would like its dango to have. The world may still adjust it, e.g.
+/// when someone else is already using the same colour.
+#[derive(Default, Clone)]
+pub struct RequestedAppearance(pub PlayerAppearance);
+
+/// How far a stick needs to be pushed before it counts, so that worn sticks that don't quite
+/// centre don't make dangos creep along by themselves.
+pub const GAMEPAD_STICK_DEADZONE: f32 = 0.3;
+
+/// Sticks are rounded to this many steps in each direction, so that a wobbly thumb doesn't issue
+/// a new command every frame.
+const GAMEPAD_STICK_STEPS: f32 = 16.0;
+
+/// Which keys a local player uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyboardLayout {
+    /// Both sets of keys below, for when there is only one player at the keyboard.
+    Any,
+
+    /// `InputBindings::first_keys`, WASD by default.
+    First,
+
+    /// `InputBindings::second_keys`, the arrow keys by default.
+    Second,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LocalPlayer {
+    pub slot: usize,
+
+    /// Players beyond the keyboard layouts only play with a gamepad.
+    pub keyboard: Option<KeyboardLayout>,
+
+    /// Handed out by `gamepad_connection_system` as gamepads come and go.
+    pub gamepad: Option<Gamepad>,
+}
+
+/// Whether this client is only watching. Spectators get the world like everyone else, but don't
+/// get a dango of their own until they ask to join.
+#[derive(Debug, Clone, Default)]
+pub struct Spectator {
+    pub is_spectating: bool,
+
+    /// Whose dango the camera follows, if anyone's. Otherwise the camera gets moved around freely.
+    pub following: Option<PlayerId>,
+}
+
+/// Everyone playing on this client. Each local player gets a dango of their own.
+#[derive(Debug, Clone)]
+pub struct LocalPlayers(pub Vec<LocalPlayer>);
+
+/// What the on-screen touch controls are pressing, on clients that have them. They drive the
+/// first local player.
+#[derive(Debug, Clone, Default)]
+pub struct TouchInputState(pub PlayerInputState);
+
+/// Everything that local players can control their dangos with.
+#[derive(SystemParam)]
+pub struct PlayerInputDevices<'a> {
+    bindings: Res<'a, InputBindings>,
+    keys: Res<'a, Input<KeyCode>>,
+    gamepad_buttons: Res<'a, Input<GamepadButton>>,
+    gamepad_axes: Res<'a, Axis<GamepadAxis>>,
+    touch: Option<Res<'a, TouchInputState>>,
+}
+
+impl KeyboardLayout {
+    fn key_bindings<'a>(&self, bindings: &'a InputBindings) -> Vec<&'a ActionBindings<KeyCode>> {
+        match self {
+            KeyboardLayout::Any => vec![&bindings.first_keys, &bindings.second_keys],
+            KeyboardLayout::First => vec![&bindings.first_keys],
+            KeyboardLayout::Second => vec![&bindings.second_keys],
+        }
+    }
+
+    pub fn read(&self, bindings: &InputBindings, input: &Input<KeyCode>) -> PlayerInputState {
+        let key_bindings = self.key_bindings(bindings);
+        read_actions(|action| {
+            let is_pressed = key_bindings
+                .iter()
+                .any(|key_bindings| key_bindings.bound_to(action).any(|key| input.pressed(*key)));
+            if is_pressed {
+                1.0
+            } else {
+                0.0
+            }
+        })
+    }
+
+    pub fn switch_team_just_pressed(
+        &self,
+        bindings: &InputBindings,
+        input: &Input<KeyCode>,
+    ) -> bool {
+        self.key_bindings(bindings).iter().any(|key_bindings| {
+            key_bindings
+                .bound_to(InputAction::SwitchTeam)
+                .any(|key| input.just_pressed(*key))
+        })
+    }
+}
+
+/// Pushing a stick part of the way creeps along.
+fn read_gamepad(
+    gamepad: Gamepad,
+    bindings: &ActionBindings<GamepadInput>,
+    buttons: &Input<GamepadButton>,
+    axes: &Axis<GamepadAxis>,
+) -> PlayerInputState {
+    read_actions(|action| {
+        bindings
+            .bound_to(action)
+            .map(|input| match input {
+                GamepadInput::Button(button_type) => {
+                    if buttons.pressed(GamepadButton(gamepad, *button_type)) {
+                        1.0
+                    } else {
+                        0.0
+                    }
+                }
+                GamepadInput::Axis(axis_type, direction) => {
+                    let value = axes.get(GamepadAxis(gamepad, *axis_type)).unwrap_or(0.0);
+                    match direction {
+                        AxisDirection::Negative => stick_amount(-value),
+                        AxisDirection::Positive => stick_amount(value),
+                    }
+                }
+            })
+            .fold(0.0, f32::max)
+    })
+}
+
+fn stick_amount(value: f32) -> f32 {
+    if value > GAMEPAD_STICK_DEADZONE {
+        let beyond_deadzone = (value - GAMEPAD_STICK_DEADZONE) / (1.0 - GAMEPAD_STICK_DEADZONE);
+        (beyond_deadzone * GAMEPAD_STICK_STEPS).round() / GAMEPAD_STICK_STEPS
+    } else {
+        0.0
+    }
+}
+
+/// Turns how far each action is held, from nothing at 0 to all the way at 1, into inputs for a
+/// dango. Holding left and right at once cancels out.
+fn read_actions(amount: impl Fn(InputAction) -> f32) -> PlayerInputState {
+    PlayerInputState {
+        horizontal: PlayerInputState::quantise_horizontal(
+            amount(InputAction::Right) - amount(InputAction::Left),
+        ),
+        jump: amount(InputAction::Jump) > 0.0,
+        roll: amount(InputAction::Roll) > 0.0,
+    }
+}
+
+/// Holding an input down on any device holds it down for the dango. Pushing both ways at once
+/// cancels out, just like holding both arrow keys.
+fn merge(a: &PlayerInputState, b: &PlayerInputState) -> PlayerInputState {
+    PlayerInputState {
+        horizontal: merge_horizontal(a.horizontal, b.horizontal),
+        jump: a.jump || b.jump,
+        roll: a.roll || b.roll,
+    }
+}
+
+fn merge_horizontal(a: i8, b: i8) -> i8 {
+    let max = PlayerInputState::MAX_HORIZONTAL as i16;
+    (a as i16 + b as i16).max(-max).min(max) as i8
+}
+
+impl LocalPlayer {
+    pub fn read(&self, devices: &PlayerInputDevices) -> PlayerInputState {
+        let mut state = self
+            .keyboard
+            .map(|layout| layout.read(&devices.bindings, &devices.keys))
+            .unwrap_or_default();
+        if let Some(gamepad) = self.gamepad {
+            state = merge(
+                &state,
+                &read_gamepad(
+                    gamepad,
+                    &devices.bindings.gamepad,
+                    &devices.gamepad_buttons,
+                    &devices.gamepad_axes,
+                ),
+            );
+        }
+        if let (0, Some(touch)) = (self.slot, &devices.touch) {
+            state = merge(&state, &touch.0);
+        }
+        state
+    }
+
+    pub fn switch_team_just_pressed(&self, devices: &PlayerInputDevices) -> bool {
+        self.keyboard.map_or(false, |layout| {
+            layout.switch_team_just_pressed(&devices.bindings, &devices.keys)
+        }) || self.gamepad.map_or(false, |gamepad| {
+            devices
+                .bindings
+                .gamepad
+                .bound_to(InputAction::SwitchTeam)
+                .any(|input| match input {
+                    GamepadInput::Button(button_type) => devices
+                        .gamepad_buttons
+                        .just_pressed(GamepadButton(gamepad, *button_type)),
+                    GamepadInput::Axis(..) => false,
+                })
+        })
+    }
+}
+
+impl LocalPlayers {
+    /// Splits the keyboard between the first two players. Anyone after that needs a gamepad.
+    pub fn new(count: usize) -> Self {
+        let count = count.max(1).min(PlayerId::MAX_LOCAL_PLAYERS);
+        Self(
+            (0..count)
+                .map(|slot| LocalPlayer {
+                    slot,
+                    keyboard: match (count, slot) {
+                        (1, _) => Some(KeyboardLayout::Any),
+                        (_, 0) => Some(KeyboardLayout::First),
+                        (_, 1) => Some(KeyboardLayout::Second),
+                        _ => None,
+                    },
+                    gamepad: None,
+                })
+                .collect(),
+        )
+    }
+
+    pub fn player_ids(&self, client_id: usize) -> Vec<PlayerId> {
+        self.0
+            .iter()
+            .map(|local_player| PlayerId::local(client_id, local_player.slot))
+            .collect()
+    }
+}
+
+impl Default for LocalPlayers {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+impl Spectator {
+    pub fn players_to_track(
+        &self,
+        local_players: &LocalPlayers,
+        client_id: usize,
+    ) -> Vec<PlayerId> {
+        if self.is_spectating {
+            self.following.into_iter().collect()
+        } else {
+            local_players.player_ids(client_id)
+        }
+    }
+}
+
+/// Hands out gamepads to local players in the order they were plugged in. A gamepad that gets
+/// unplugged leaves its player free for the next one, and any spare gamepads wait their turn.
+pub fn gamepad_connection_system(
+    mut connected_gamepads: Local<Vec<Gamepad>>,
+    mut gamepad_events: EventReader<GamepadEvent>,
+    mut local_players: ResMut<LocalPlayers>,
+) {
+    let mut has_changed = false;
+    for GamepadEvent(gamepad, event_type) in gamepad_events.iter() {
+        match event_type {
+            GamepadEventType::Connected => {
+                info!("Gamepad {:?} connected", gamepad);
+                connected_gamepads.push(*gamepad);
+                has_changed = true;
+            }
+            GamepadEventType::Disconnected => {
+                info!("Gamepad {:?} disconnected", gamepad);
+                connected_gamepads.retain(|connected_gamepad| connected_gamepad != gamepad);
+                has_changed = true;
+            }
+            _ => {}
+        }
+    }
+    if !has_changed {
+        return;
+    }
+
+    for local_player in local_players.0.iter_mut() {
+        if let Some(gamepad) = local_player.gamepad {
+            if !connected_gamepads.contains(&gamepad) {
+                local_player.gamepad = None;
+            }
+        }
+    }
+    let assigned_gamepads: Vec<Gamepad> = local_players
+        .0
+        .iter()
+        .filter_map(|local_player| local_player.gamepad)
+        .collect();
+    let mut spare_gamepads = connected_gamepads
+        .iter()
+        .filter(|gamepad| !assigned_gamepads.contains(gamepad));
+    for local_player in local_players.0.iter_mut() {
+        if local_player.gamepad.is_none() {
+            local_player.gamepad = spare_gamepads.next().copied();
+        }
+    }
+}
+
+/// Extra local players get numbered nicknames so that everyone can tell them apart. The world
+/// hands them their own colours anyway.
+pub fn local_appearance(requested_appearance: &PlayerAppearance, slot: usize) -> PlayerAppearance {
+    let mut appearance = requested_appearance.clone();
+    if slot > 0 && !appearance.nickname.is_empty() {
+        appearance.nickname = format!("{} {}", appearance.nickname, slot + 1);
+    }
+    appearance
+}
+
+pub fn player_join_system(
+    mut last_join_requests: Local<LastJoinRequestTimes>,
+    requested_appearance: Res<RequestedAppearance>,
+    local_players: Res<LocalPlayers>,
+    spectator: Res<Spectator>,
+    mut sessions: ResMut<LocalSessions>,
+    platform: Option<Res<Box<dyn Platform>>>,
+    time: Res<Time>,
+    mut client: ResMut<Client<PhysicsWorld>>,
+    mut net: ResMut<NetworkResource>,
+) {
+    if spectator.is_spectating {
+        return;
+    }
+    // Note: Bots run without a platform, and never take over hosting.
+    let can_host = platform.map_or(false, |platform| platform.can_host());
+    if let ClientStageMut::Ready(mut ready_client) = client.stage_mut() {
+        let now = time.seconds_since_startup();
+        for local_player in &local_players.0 {
+            let player_id = PlayerId::local(ready_client.client_id(), local_player.slot);
+            if ready_client
+                .display_state()
+                .players()
+                .contains_key(&player_id)
+            {
+                continue;
+            }
+
+            let should_request = last_join_requests
+                .0
+                .get(&local_player.slot)
+                .map_or(true, |last_request| {
+                    now - last_request > JOIN_RETRY_INTERVAL
+                });
+            if should_request {
+                last_join_requests.0.insert(local_player.slot, now);
+                let session = sessions.get(local_player.slot);
+                ready_client.issue_command(
+                    PhysicsCommand::Join {
+                        player_id,
+                        requested_appearance: local_appearance(
+                            &requested_appearance.0,
+                            local_player.slot,
+                        ),
+                        reclaimed_session: session.reclaim,
+                        next_session: Some(session.next.hash()),
+                        can_host,
+                    },
+                    &mut WrappedNetworkResource(&mut *net),
+                );
+            }
+        }
+    }
+}
 
 pub fn player_input_system(
-    mut state: Local<PlayerInputState>,
-    mut last_resync: Local<LastResetTime>,
-    input: Res<Input<KeyCode>>,
+    mut senders: Local<Vec<InputSender>>,
+    local_players: Res<LocalPlayers>,
+    spectator: Res<Spectator>,
+    devices: PlayerInputDevices,
     time: Res<Time>,
     mut client: ResMut<Client<PhysicsWorld>>,
     mut net: ResMut<NetworkResource>,
 ) {
-    let left = input.pressed(KeyCode::A) || input.pressed(KeyCode::Left);
-    let right = input.pressed(KeyCode::D) || input.pressed(KeyCode::Right);
-    let jump =
-        input.pressed(KeyCode::W) || input.pressed(KeyCode::Space) || input.pressed(KeyCode::Up);
-    let roll = input.pressed(KeyCode::LShift) || input.pressed(KeyCode::RShift);
+    if spectator.is_spectating {
+        return;
+    }
+    senders.resize(local_players.0.len(), InputSender::default());
 
-    if let ClientStageMut::Ready(mut ready_client) = client.stage_mut() {
-        let player_id = PlayerId(ready_client.client_id());
+    for (local_player, sender) in local_players.0.iter().zip(senders.iter_mut()) {
+        let next_state = local_player.read(&devices);
+        let switch_team = local_player.switch_team_just_pressed(&devices);
 
-        let resync = if time.seconds_since_startup() - last_resync.0 > INPUT_RESYNC_INTERVAL {
-            last_resync.0 = time.seconds_since_startup();
-            true
-        } else {
-            false
-        };
-
-        if resync || left != state.left {
-            ready_client.issue_command(
-                PhysicsCommand::PlayerInput {
-                    player_id,
-                    command: PlayerInputCommand::Left(left),
-                },
-                &mut WrappedNetworkResource(&mut *net),
-            );
+        if let ClientStageMut::Ready(mut ready_client) = client.stage_mut() {
+            let player_id = PlayerId::local(ready_client.client_id(), local_player.slot);
+
+            sender.send(time.seconds_since_startup(), &next_state, |command| {
+                ready_client.issue_command(
+                    PhysicsCommand::PlayerInput { player_id, command },
+                    &mut WrappedNetworkResource(&mut *net),
+                );
+            });
+            if switch_team && settings::TEAM_COUNT > 0 {
+                let current_team = ready_client
+                    .display_state()
+                    .players()
+                    .get(&player_id)
+                    .and_then(|player| player.team);
+                ready_client.issue_command(
+                    PhysicsCommand::RequestTeamSwitch {
+                        player_id,
+                        team: next_team(current_team),
+                    },
+                    &mut WrappedNetworkResource(&mut *net),
+                );
+            }
         }
-        if resync || right != state.right {
-            ready_client.issue_command(
-                PhysicsCommand::PlayerInput {
-                    player_id,
-                    command: PlayerInputCommand::Right(right),
-                },
-                &mut WrappedNetworkResource(&mut *net),
-            );
+    }
+}
+
+pub fn offline_player_join_system(
+    requested_appearance: Res<RequestedAppearance>,
+    local_players: Res<LocalPlayers>,
+    mut physics: ResMut<OfflinePhysics>,
+) {
+    for local_player in &local_players.0 {
+        let player_id = PlayerId::local(OFFLINE_CLIENT_ID, local_player.slot);
+        // Note: The world ignores repeated requests until it has been stepped and the new dango
+        // shows up.
+        if !physics.display_state().players().contains_key(&player_id) {
+            physics.issue_command(PhysicsCommand::Join {
+                player_id,
+                requested_appearance: local_appearance(&requested_appearance.0, local_player.slot),
+                reclaimed_session: None,
+                next_session: None,
+                can_host: false,
+            });
         }
-        if resync || jump != state.jump {
-            ready_client.issue_command(
-                PhysicsCommand::PlayerInput {
-                    player_id,
-                    command: PlayerInputCommand::Jump(jump),
-                },
-                &mut WrappedNetworkResource(&mut *net),
-            );
+    }
+}
+
+/// Feeds the keyboard straight into an offline world. Nothing can get lost on the way, so inputs
+/// are only sent when they change.
+pub fn offline_player_input_system(
+    mut states: Local<Vec<PlayerInputState>>,
+    local_players: Res<LocalPlayers>,
+    devices: PlayerInputDevices,
+    mut physics: ResMut<OfflinePhysics>,
+) {
+    states.resize(local_players.0.len(), PlayerInputState::default());
+
+    for (local_player, state) in local_players.0.iter().zip(states.iter_mut()) {
+        let player_id = PlayerId::local(OFFLINE_CLIENT_ID, local_player.slot);
+        let next_state = local_player.read(&devices);
+        if next_state != *state {
+            physics.issue_command(PhysicsCommand::PlayerInput {
+                player_id,
+                command: PlayerInputCommand::new(&next_state),
+            });
         }
-        if resync || roll != state.roll {
-            ready_client.issue_command(
-                PhysicsCommand::PlayerInput {
-                    player_id,
-                    command: PlayerInputCommand::Roll(roll),
-                },
-                &mut WrappedNetworkResource(&mut *net),
-            );
+        if local_player.switch_team_just_pressed(&devices) && settings::TEAM_COUNT > 0 {
+            let current_team = physics
+                .display_state()
+                .players()
+                .get(&player_id)
+                .and_then(|player| player.team);
+            physics.issue_command(PhysicsCommand::RequestTeamSwitch {
+                player_id,
+                team: next_team(current_team),
+            });
         }
+
+        *state = next_state;
     }
+}
+
+/// Cycles through the teams in order.
+fn next_team(current_team: Option<TeamId>) -> TeamId {
+    TeamId(current_team.map_or(0, |team| team.0 + 1) % settings::TEAM_COUNT)
+}
 
-    state.left = left;
-    state.right = right;
-    state.jump = jump;
-    state.roll = roll;
+impl InputSender {
+    pub fn send(
+        &mut self,
+        now: f64,
+        next: &PlayerInputState,
+        issue: impl FnOnce(PlayerInputCommand),
+    ) {
+        let is_due = self.sent.as_ref().map_or(true, |(sent, sent_at)| {
+            sent != next || now - sent_at >= settings::TIMESTEP
+        });
+        if is_due {
+            issue(PlayerInputCommand::new(next));
+            self.sent = Some((next.clone(), now));
+        }
+    }
 }
@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+
+/// Lets a client that dropped out reclaim its dango, along with its colour and score. Only the
+/// hash goes out when joining, since every client gets to see every command, and the secret
+/// itself only gets revealed when it is used up.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionSecret(pub u64);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SessionHash([u8; 20]);
+
+/// What a client's local player brings along when asking to join.
+#[derive(Debug, Clone, Copy)]
+pub struct LocalSession {
+    /// The secret from last time, to reclaim the dango that was left behind.
+    pub reclaim: Option<SessionSecret>,
+
+    /// The secret for next time.
+    pub next: SessionSecret,
+}
+
+/// Sessions for each of this client's local players, by slot.
+#[derive(Debug, Clone, Default)]
+pub struct LocalSessions(pub Vec<LocalSession>);
+
+impl SessionSecret {
+    pub fn random() -> Self {
+        Self(rand::random())
+    }
+
+    pub fn hash(&self) -> SessionHash {
+        let mut hasher = Sha1::new();
+        hasher.update(&self.0.to_le_bytes());
+        SessionHash(hasher.digest().bytes())
+    }
+}
+
+impl SessionHash {
+    /// Spells the hash out the same way as `sha1`'s digests, e.g. for the signalling server.
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+impl Default for LocalSession {
+    fn default() -> Self {
+        Self {
+            reclaim: None,
+            next: SessionSecret::random(),
+        }
+    }
+}
+
+impl LocalSessions {
+    /// Starts new sessions for any slots that don't have one yet.
+    pub fn get(&mut self, slot: usize) -> LocalSession {
+        if self.0.len() <= slot {
+            self.0.resize_with(slot + 1, LocalSession::default);
+        }
+        self.0[slot]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_matches_how_the_signalling_server_hashes_claims() {
+        let secret = SessionSecret(0x0123_4567_89ab_cdef);
+        let mut hasher = Sha1::new();
+        hasher.update(&secret.0.to_le_bytes());
+        assert_eq!(secret.hash().to_hex(), hasher.digest().to_string());
+    }
+}
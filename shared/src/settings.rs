@@ -3,6 +3,12 @@ use crystalorb_bevy_networking_turbulence::crystalorb::Config as NetworkedPhysic
 pub const TIMESTEP: f64 = 1.0 / 60.0;
 pub const GRAVITY: f32 = -9.81 * 1.5;
 pub type RealField = f32;
+pub const TEAM_COUNT: u8 = 2;
+pub const MAX_PLAYERS: usize = 100;
+/// How long, in seconds, a dropped out player's dango waits for them to come back.
+pub const SESSION_GRACE_PERIOD: f64 = 30.0;
+/// How often, in seconds, the host picks a successor and hands over a fresh snapshot.
+pub const HANDOFF_INTERVAL: f64 = 5.0;
 pub const NETWORKED_PHYSICS_CONFIG: NetworkedPhysicsConfig = NetworkedPhysicsConfig {
     timestep_seconds: TIMESTEP,
     ..NetworkedPhysicsConfig::new()
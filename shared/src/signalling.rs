@@ -0,0 +1,111 @@
+use crate::{platform::Platform, session::SessionSecret};
+
+/// The launch parameter that overrides where the signalling server lives, e.g.
+/// `?signalling=https://signalling.example.com`.
+pub const SIGNALLING_URL_PARAM: &str = "signalling";
+
+/// Used when neither the launch parameter nor the `DANGO_SIGNALLING_URL` environment variable at
+/// build time say otherwise.
+pub const DEFAULT_SIGNALLING_URL: &str = "https://dango-tribute.up.railway.app";
+
+/// Proves to the signalling server that whoever is asking about a room is its host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostKey(String);
+
+impl HostKey {
+    pub fn random() -> Self {
+        Self(format!("{:016x}", rand::random::<u64>()))
+    }
+}
+
+/// Where web builds go to find each other. Hosts keep a websocket open at `/host`, and clients
+/// post their offers to `/join/{id}`. A successor takes over a room that lost its host at
+/// `/host/{id}`, after checking on it at `/room/{id}`. Hosts say whether their room is full at
+/// `/host/{id}/full`, and who may take it over at `/host/{id}/successor`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignallingEndpoint {
+    /// The `http://` or `https://` url that the signalling server's routes hang off, without a
+    /// trailing slash.
+    base_url: String,
+}
+
+impl SignallingEndpoint {
+    /// Accepts `http`, `https`, `ws` and `wss` urls, since it isn't always obvious which one to
+    /// give. A url without a scheme is assumed to be `https`.
+    pub fn new(base_url: &str) -> Self {
+        let base_url = base_url.trim().trim_end_matches('/');
+        let base_url = if let Some(rest) = base_url.strip_prefix("wss://") {
+            format!("https://{}", rest)
+        } else if let Some(rest) = base_url.strip_prefix("ws://") {
+            format!("http://{}", rest)
+        } else if base_url.starts_with("https://") || base_url.starts_with("http://") {
+            base_url.to_string()
+        } else {
+            format!("https://{}", base_url)
+        };
+        Self { base_url }
+    }
+
+    /// Picks the launch parameter if there is one, then whatever `DANGO_SIGNALLING_URL` was set
+    /// to at build time, and then the public signalling server.
+    pub fn from_platform(platform: &dyn Platform) -> Self {
+        let base_url = platform
+            .launch_param(SIGNALLING_URL_PARAM)
+            .unwrap_or_else(|| {
+                option_env!("DANGO_SIGNALLING_URL")
+                    .unwrap_or(DEFAULT_SIGNALLING_URL)
+                    .to_string()
+            });
+        Self::new(&base_url)
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    fn websocket_base_url(&self) -> String {
+        if let Some(rest) = self.base_url.strip_prefix("https://") {
+            format!("wss://{}", rest)
+        } else if let Some(rest) = self.base_url.strip_prefix("http://") {
+            format!("ws://{}", rest)
+        } else {
+            unreachable!("base url should always be http or https")
+        }
+    }
+
+    pub fn host_url(&self, key: &HostKey) -> String {
+        format!("{}/host?key={}", self.websocket_base_url(), key.0)
+    }
+
+    /// Only the successor that the previous host picked has the `claim` that gets it in.
+    pub fn rehost_url(&self, room_id: &str, key: &HostKey, claim: Option<SessionSecret>) -> String {
+        let mut url = format!(
+            "{}/host/{}?key={}",
+            self.websocket_base_url(),
+            room_id,
+            key.0
+        );
+        if let Some(claim) = claim {
+            url.push_str(&format!("&claim={}", claim.0));
+        }
+        url
+    }
+
+    /// Answers `hosted`, `full` or `vacated`, or a `404` if there is no such room.
+    pub fn room_status_url(&self, room_id: &str) -> String {
+        format!("{}/room/{}", self.base_url, room_id)
+    }
+
+    pub fn room_full_url(&self, room_id: &str, key: &HostKey) -> String {
+        format!("{}/host/{}/full?key={}", self.base_url, room_id, key.0)
+    }
+
+    /// Takes the hex of the successor's [`SessionHash`](crate::session::SessionHash) from the host.
+    pub fn successor_url(&self, room_id: &str, key: &HostKey) -> String {
+        format!("{}/host/{}/successor?key={}", self.base_url, room_id, key.0)
+    }
+
+    pub fn join_url(&self, host_id: &str) -> String {
+        format!("{}/join/{}", self.base_url, host_id)
+    }
+}
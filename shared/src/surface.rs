@@ -0,0 +1,63 @@
+use crate::settings::RealField;
+use nphysics2d::material::{BasicMaterial, MaterialCombineMode, MaterialHandle};
+use serde::{Deserialize, Serialize};
+
+/// Physical properties of a level surface. This is attached to each level collider as its user
+/// data so that dangos can find out what they are standing on.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct SurfaceMaterial {
+    /// Multiplied with the dango's own friction coefficient.
+    pub friction: RealField,
+
+    /// Takes precedence over the dango's own restitution coefficient when it is higher.
+    pub restitution: RealField,
+
+    /// Scales the fictitious ground drag of dangos standing on this surface. Values below one
+    /// also reduce how much traction the dango gets when crawling.
+    pub drag_multiplier: RealField,
+}
+
+impl SurfaceMaterial {
+    // Note: The default friction of 1.0 multiplied with the dango's default friction of 0.5
+    // matches the behaviour from before surfaces had materials.
+    pub const DEFAULT: SurfaceMaterial = SurfaceMaterial {
+        friction: 1.0,
+        restitution: 0.0,
+        drag_multiplier: 1.0,
+    };
+
+    pub const ICE: SurfaceMaterial = SurfaceMaterial {
+        friction: 0.02,
+        restitution: 0.0,
+        drag_multiplier: 0.05,
+    };
+
+    pub const STICKY: SurfaceMaterial = SurfaceMaterial {
+        friction: 3.0,
+        restitution: 0.0,
+        drag_multiplier: 6.0,
+    };
+
+    pub const BOUNCY: SurfaceMaterial = SurfaceMaterial {
+        friction: 1.0,
+        restitution: 0.95,
+        drag_multiplier: 1.0,
+    };
+
+    pub fn material_handle(&self) -> MaterialHandle<RealField> {
+        let mut material = BasicMaterial::new(self.restitution, self.friction);
+        material.friction_combine_mode = MaterialCombineMode::Multiply;
+        material.restitution_combine_mode = MaterialCombineMode::Max;
+        MaterialHandle::new(material)
+    }
+
+    pub fn traction(&self) -> RealField {
+        self.drag_multiplier.min(1.0)
+    }
+}
+
+impl Default for SurfaceMaterial {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
@@ -0,0 +1,155 @@
+use crate::color::Rgb;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TeamId(pub u8);
+
+const TEAM_COLORS: [[f32; 3]; TeamId::MAX_TEAMS] = [
+    [1.0, 0.3, 0.3],
+    [0.3, 0.6, 1.0],
+    [0.3, 0.8, 0.4],
+    [1.0, 0.8, 0.3],
+    [0.7, 0.4, 0.9],
+    [1.0, 0.55, 0.2],
+    [0.2, 0.8, 0.8],
+    [1.0, 0.5, 0.8],
+];
+
+impl TeamId {
+    pub const MAX_TEAMS: usize = 8;
+
+    pub fn index(&self) -> usize {
+        self.0 as usize % Self::MAX_TEAMS
+    }
+
+    /// Team mates get outlined in their team's colour. Their fill keeps their own colour, since
+    /// tinting it towards the team colour would make team mates hard to tell apart.
+    pub fn color(&self) -> Rgb {
+        let [r, g, b] = TEAM_COLORS[self.index()];
+        Rgb::new(r, g, b)
+    }
+
+    /// Picks the team with the fewest members, preferring lower team ids when tied.
+    pub fn balanced<I: IntoIterator<Item = Option<TeamId>>>(
+        team_count: u8,
+        current_teams: I,
+    ) -> Option<TeamId> {
+        if team_count == 0 {
+            return None;
+        }
+        let member_counts = Self::member_counts(team_count, current_teams);
+        (0..team_count)
+            .min_by_key(|team| member_counts[*team as usize])
+            .map(TeamId)
+    }
+
+    /// Switching is only allowed if it doesn't make the target team bigger than the team being
+    /// left behind.
+    pub fn can_switch<I: IntoIterator<Item = Option<TeamId>>>(
+        team_count: u8,
+        current_teams: I,
+        from: Option<TeamId>,
+        to: TeamId,
+    ) -> bool {
+        if to.0 >= team_count || from == Some(to) {
+            return false;
+        }
+        let member_counts = Self::member_counts(team_count, current_teams);
+        // Note: A team that no longer exists counts as no team at all.
+        let from_count = match from.and_then(|from| member_counts.get(from.0 as usize)) {
+            Some(from_count) => *from_count,
+            None => return true,
+        };
+        // Note: The switch takes one from the team left behind and adds one to the target team.
+        member_counts[to.0 as usize] + 2 <= from_count
+    }
+
+    fn member_counts<I: IntoIterator<Item = Option<TeamId>>>(
+        team_count: u8,
+        current_teams: I,
+    ) -> Vec<usize> {
+        let mut member_counts = vec![0; team_count as usize];
+        for team in current_teams.into_iter().flatten() {
+            if let Some(count) = member_counts.get_mut(team.0 as usize) {
+                *count += 1;
+            }
+        }
+        member_counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn teams(member_teams: &[u8]) -> Vec<Option<TeamId>> {
+        member_teams.iter().copied().map(TeamId).map(Some).collect()
+    }
+
+    #[test]
+    fn balanced_picks_the_smallest_team() {
+        assert_eq!(TeamId::balanced(2, teams(&[])), Some(TeamId(0)));
+        assert_eq!(TeamId::balanced(2, teams(&[0])), Some(TeamId(1)));
+        assert_eq!(TeamId::balanced(3, teams(&[0, 1, 1, 2])), Some(TeamId(0)));
+        assert_eq!(TeamId::balanced(0, teams(&[])), None);
+    }
+
+    #[test]
+    fn balanced_ignores_players_without_a_team_and_stale_teams() {
+        let mut current_teams = teams(&[0, 5, 5]);
+        current_teams.push(None);
+        assert_eq!(TeamId::balanced(2, current_teams), Some(TeamId(1)));
+    }
+
+    #[test]
+    fn can_switch_only_when_it_keeps_teams_balanced() {
+        // 2/0 becomes 1/1.
+        assert!(TeamId::can_switch(
+            2,
+            teams(&[0, 0]),
+            Some(TeamId(0)),
+            TeamId(1)
+        ));
+        // 3/2 would become 2/3, which only moves the imbalance over.
+        assert!(!TeamId::can_switch(
+            2,
+            teams(&[0, 0, 0, 1, 1]),
+            Some(TeamId(0)),
+            TeamId(1)
+        ));
+        // 1/1 would become 0/2.
+        assert!(!TeamId::can_switch(
+            2,
+            teams(&[0, 1]),
+            Some(TeamId(0)),
+            TeamId(1)
+        ));
+    }
+
+    #[test]
+    fn can_switch_rejects_staying_put_and_missing_teams() {
+        assert!(!TeamId::can_switch(
+            2,
+            teams(&[0, 0]),
+            Some(TeamId(0)),
+            TeamId(0)
+        ));
+        assert!(!TeamId::can_switch(
+            2,
+            teams(&[0, 0]),
+            Some(TeamId(0)),
+            TeamId(2)
+        ));
+    }
+
+    #[test]
+    fn can_switch_from_no_team_or_a_stale_team() {
+        assert!(TeamId::can_switch(2, teams(&[0, 1]), None, TeamId(1)));
+        assert!(TeamId::can_switch(
+            2,
+            teams(&[0, 1, 7]),
+            Some(TeamId(7)),
+            TeamId(1)
+        ));
+    }
+}
@@ -0,0 +1,294 @@
+//! Runs a server and a handful of clients in one process, with no rendering and no sockets, so
+//! that whole multiplayer sessions can be scripted from a test.
+
+use bevy::{core::CorePlugin, prelude::*, tasks::IoTaskPool};
+use crystalorb_bevy_networking_turbulence::{
+    bevy_networking_turbulence::{LinkConditionerConfig, LoopbackNetwork, NetworkResource},
+    crystalorb::{
+        client::{stage::Stage as ClientStage, Client},
+        server::Server,
+    },
+    CrystalOrbClientPlugin, CrystalOrbServerPlugin,
+};
+use shared::{
+    admin,
+    appearance::PlayerAppearance,
+    input_bindings::InputBindings,
+    physics_multiplayer::{PhysicsDisplayState, PhysicsWorld},
+    physics_multiplayer_server,
+    platform::{Platform, TestPlatform},
+    player::PlayerId,
+    player_input::{self, LocalPlayers, RequestedAppearance, Spectator},
+    session::LocalSessions,
+    settings,
+};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+const SERVER_NAME: &str = "server";
+
+/// How long to wait for something to happen before giving up on it. Generous, since a busy
+/// machine can take a while to get everyone connected.
+pub const WAIT_TIMEOUT_SECONDS: f64 = 30.0;
+
+/// Which keys each client is holding down at a given number of seconds into a
+/// [`Harness::run_for`] call, counted in frames.
+pub type InputScript<'a> = dyn FnMut(usize, f64) -> Vec<KeyCode> + 'a;
+
+pub struct Harness {
+    pub server: App,
+    pub clients: Vec<App>,
+
+    /// Shares its record with the server's platform, e.g. to type in admin commands.
+    pub server_platform: TestPlatform,
+}
+
+impl Harness {
+    pub fn new(client_count: usize) -> Self {
+        Self::with_link_conditioner(client_count, None)
+    }
+
+    /// Every app applies the link conditioner to the packets it receives, so the delay and loss
+    /// apply in both directions.
+    pub fn with_link_conditioner(
+        client_count: usize,
+        link_conditioner: Option<LinkConditionerConfig>,
+    ) -> Self {
+        let network = LoopbackNetwork::default();
+        let server_platform = TestPlatform::default();
+
+        let mut server = App::build();
+        server
+            .add_plugin(CorePlugin::default())
+            .add_plugin(CrystalOrbServerPlugin::<PhysicsWorld>::new(
+                settings::NETWORKED_PHYSICS_CONFIG,
+            ))
+            .insert_resource(Box::new(server_platform.clone()) as Box<dyn Platform>)
+            .add_system(
+                physics_multiplayer_server::physics_multiplayer_server_despawn_system.system(),
+            )
+            .add_system(admin::admin_command_system.system());
+        replace_network_resource(&mut server, link_conditioner.clone());
+        server
+            .world_mut()
+            .get_resource_mut::<NetworkResource>()
+            .unwrap()
+            .listen_loopback(&network, SERVER_NAME);
+
+        let clients = (0..client_count)
+            .map(|index| {
+                let mut client = App::build();
+                client
+                    .add_plugin(CorePlugin::default())
+                    .add_plugin(CrystalOrbClientPlugin::<PhysicsWorld>::new(
+                        settings::NETWORKED_PHYSICS_CONFIG,
+                    ))
+                    .insert_resource(Input::<KeyCode>::default())
+                    .init_resource::<Input<GamepadButton>>()
+                    .init_resource::<Axis<GamepadAxis>>()
+                    .insert_resource(RequestedAppearance(PlayerAppearance {
+                        nickname: format!("client {}", index),
+                        ..Default::default()
+                    }))
+                    .insert_resource(Box::new(TestPlatform::default()) as Box<dyn Platform>)
+                    .init_resource::<LocalPlayers>()
+                    .init_resource::<InputBindings>()
+                    .init_resource::<Spectator>()
+                    .init_resource::<LocalSessions>()
+                    .add_system(player_input::player_join_system.system())
+                    .add_system(player_input::player_input_system.system());
+                replace_network_resource(&mut client, link_conditioner.clone());
+                client
+                    .world_mut()
+                    .get_resource_mut::<NetworkResource>()
+                    .unwrap()
+                    .connect_loopback(&network, SERVER_NAME)
+                    .expect("server should be listening");
+                client.app
+            })
+            .collect();
+
+        Harness {
+            server: server.app,
+            clients,
+            server_platform,
+        }
+    }
+
+    /// Runs every app for the given number of seconds' worth of frames, one [`settings::TIMESTEP`]
+    /// at a time. The script gets told how far into the run each frame is by counting frames
+    /// rather than by looking at the clock, so that every run presses the same keys on the same
+    /// frames.
+    pub fn run_for(&mut self, seconds: f64, script: &mut InputScript) {
+        let frame_count = (seconds / settings::TIMESTEP).round() as usize;
+        for frame in 0..frame_count {
+            self.step(frame as f64 * settings::TIMESTEP, script);
+        }
+    }
+
+    pub fn idle_for(&mut self, seconds: f64) {
+        self.run_for(seconds, &mut |_, _| Vec::new());
+    }
+
+    /// Idles until the check passes, and panics with whatever it last complained about if it
+    /// doesn't pass within [`WAIT_TIMEOUT_SECONDS`]. This keeps tests from depending on how fast
+    /// the machine happens to be.
+    pub fn wait_until(&mut self, mut check: impl FnMut(&Self) -> Result<(), String>) {
+        let frame_count = (WAIT_TIMEOUT_SECONDS / settings::TIMESTEP).round() as usize;
+        let mut result = check(self);
+        for _ in 0..frame_count {
+            if result.is_ok() {
+                return;
+            }
+            self.step(0.0, &mut |_, _| Vec::new());
+            result = check(self);
+        }
+        if let Err(complaint) = result {
+            panic!(
+                "gave up waiting after {} seconds: {}",
+                WAIT_TIMEOUT_SECONDS, complaint
+            );
+        }
+    }
+
+    pub fn wait_until_joined(&mut self) {
+        self.wait_until(Self::joined);
+    }
+
+    pub fn wait_until_converged(&mut self, position_tolerance: f32) {
+        self.wait_until(|harness| harness.converged(position_tolerance));
+    }
+
+    /// Updates every app once.
+    ///
+    /// Note: CrystalOrb's systems go by bevy's `Time`, which only ever reads the real clock in
+    /// bevy 0.5, so each frame still gets paced to take a timestep.
+    fn step(&mut self, seconds: f64, script: &mut InputScript) {
+        let frame_start = Instant::now();
+        let frame_duration = Duration::from_secs_f64(settings::TIMESTEP);
+
+        self.server.update();
+        for (index, client) in self.clients.iter_mut().enumerate() {
+            let held_keys = script(index, seconds);
+            let mut input = client.world.get_resource_mut::<Input<KeyCode>>().unwrap();
+            input.update();
+            let released_keys: Vec<KeyCode> = input
+                .get_pressed()
+                .filter(|key| !held_keys.contains(key))
+                .copied()
+                .collect();
+            for key in released_keys {
+                input.release(key);
+            }
+            for key in held_keys {
+                if !input.pressed(key) {
+                    input.press(key);
+                }
+            }
+            client.update();
+        }
+
+        if let Some(remaining) = frame_duration.checked_sub(frame_start.elapsed()) {
+            thread::sleep(remaining);
+        }
+    }
+
+    pub fn server_state(&self) -> PhysicsDisplayState {
+        let server = self
+            .server
+            .world
+            .get_resource::<Server<PhysicsWorld>>()
+            .unwrap();
+        let display_state: &PhysicsDisplayState = &server.display_state();
+        display_state.clone()
+    }
+
+    /// The player this client controls, and what it currently shows, once it has synced up with
+    /// the server.
+    pub fn client_state(&self, index: usize) -> Option<(PlayerId, PhysicsDisplayState)> {
+        let client = self.clients[index]
+            .world
+            .get_resource::<Client<PhysicsWorld>>()
+            .unwrap();
+        match client.stage() {
+            ClientStage::Ready(ready_client) => {
+                let display_state: &PhysicsDisplayState = ready_client.display_state();
+                Some((PlayerId(ready_client.client_id()), display_state.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Checks that every client has synced up with the server, and has a dango in the world.
+    pub fn joined(&self) -> Result<(), String> {
+        let server_state = self.server_state();
+        for index in 0..self.clients.len() {
+            let (player_id, _) = self
+                .client_state(index)
+                .ok_or_else(|| format!("client {} should be ready", index))?;
+            if !server_state.players().contains_key(&player_id) {
+                return Err(format!(
+                    "client {} should have joined as {:?}",
+                    index, player_id
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that every client has joined, and sees every dango close to where the server has
+    /// it.
+    pub fn converged(&self, position_tolerance: f32) -> Result<(), String> {
+        self.joined()?;
+        let server_state = self.server_state();
+        if server_state.players().len() != self.clients.len() {
+            return Err(format!(
+                "the server should have {} players, but has {}",
+                self.clients.len(),
+                server_state.players().len()
+            ));
+        }
+        for index in 0..self.clients.len() {
+            let (_, client_state) = self
+                .client_state(index)
+                .ok_or_else(|| format!("client {} should be ready", index))?;
+            if client_state.players().len() != server_state.players().len() {
+                return Err(format!("client {} should see everyone", index));
+            }
+            for (player_id, server_player) in server_state.players() {
+                let client_player = client_state
+                    .players()
+                    .get(player_id)
+                    .ok_or_else(|| format!("client {} should see {:?}", index, player_id))?;
+                if client_player.nickname != server_player.nickname
+                    || client_player.color != server_player.color
+                    || client_player.team != server_player.team
+                {
+                    return Err(format!(
+                        "client {} sees {:?} looking different from the server",
+                        index, player_id
+                    ));
+                }
+                let distance = (client_player.measurements.center_of_mass
+                    - server_player.measurements.center_of_mass)
+                    .norm();
+                if distance > position_tolerance {
+                    return Err(format!(
+                        "client {} sees {:?} {} away from where the server has it",
+                        index, player_id, distance
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The CrystalOrb plugins add a [`NetworkResource`] without a link conditioner, so swap it out
+/// before any of their startup systems get to it.
+fn replace_network_resource(app: &mut AppBuilder, link_conditioner: Option<LinkConditionerConfig>) {
+    let task_pool = app.world().get_resource::<IoTaskPool>().unwrap().0.clone();
+    app.insert_resource(NetworkResource::new(task_pool, link_conditioner));
+}
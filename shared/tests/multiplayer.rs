@@ -0,0 +1,95 @@
+mod harness;
+
+use bevy::prelude::*;
+use crystalorb_bevy_networking_turbulence::bevy_networking_turbulence::LinkConditionerConfig;
+use harness::Harness;
+
+/// How long dangos get to come to rest once everyone lets go of their keys.
+const SETTLE_SECONDS: f64 = 3.0;
+
+const POSITION_TOLERANCE: f32 = 0.1;
+
+#[test]
+fn clients_join_and_agree_on_the_world() {
+    let mut harness = Harness::new(3);
+    harness.wait_until_joined();
+    harness.wait_until_converged(POSITION_TOLERANCE);
+}
+
+#[test]
+fn clients_agree_after_scripted_inputs() {
+    let mut harness = Harness::new(2);
+    harness.wait_until_joined();
+    let (walker_id, _) = harness.client_state(0).expect("client 0 should be ready");
+    let start_x = harness.server_state().players()[&walker_id]
+        .measurements
+        .center_of_mass
+        .x;
+
+    harness.run_for(2.0, &mut |client, seconds| match client {
+        0 => vec![KeyCode::D],
+        _ if seconds % 0.5 < 0.25 => vec![KeyCode::Space],
+        _ => vec![],
+    });
+    harness.idle_for(SETTLE_SECONDS);
+
+    harness.wait_until_converged(POSITION_TOLERANCE);
+    let end_x = harness.server_state().players()[&walker_id]
+        .measurements
+        .center_of_mass
+        .x;
+    assert!(
+        end_x > start_x + 0.5,
+        "walking right should move the dango right of {}, but it ended up at {}",
+        start_x,
+        end_x
+    );
+}
+
+#[test]
+fn clients_agree_over_a_lossy_link() {
+    let mut harness =
+        Harness::with_link_conditioner(2, Some(LinkConditionerConfig::new(60, 15, 0.02, 0.0)));
+    harness.wait_until_joined();
+
+    harness.run_for(2.0, &mut |client, seconds| match client {
+        0 if seconds < 1.0 => vec![KeyCode::A, KeyCode::Space],
+        0 => vec![KeyCode::A],
+        _ => vec![KeyCode::D, KeyCode::LShift],
+    });
+    harness.idle_for(SETTLE_SECONDS);
+
+    harness.wait_until_converged(POSITION_TOLERANCE);
+}
+
+#[test]
+fn kicked_clients_stay_connected_but_out_of_the_world() {
+    let mut harness = Harness::new(2);
+    harness.wait_until_joined();
+    let (kicked_id, _) = harness.client_state(0).expect("client 0 should be ready");
+    let (remaining_id, _) = harness.client_state(1).expect("client 1 should be ready");
+
+    harness
+        .server_platform
+        .type_admin_command(&format!("kick {}", kicked_id.0));
+    harness.wait_until(|harness| match harness.client_state(0) {
+        Some((_, client_state)) if client_state.players().contains_key(&kicked_id) => {
+            Err("client 0 should see its dango go".to_string())
+        }
+        Some(_) => Ok(()),
+        None => Err("client 0 should still be connected".to_string()),
+    });
+
+    // Note: The client keeps asking to join, so give it a chance to sneak back in.
+    harness.idle_for(1.0);
+    let server_state = harness.server_state();
+    assert!(!server_state.players().contains_key(&kicked_id));
+    assert!(server_state.players().contains_key(&remaining_id));
+    assert!(server_state.is_turned_away(kicked_id.client_id().unwrap()));
+    assert!(harness
+        .server_platform
+        .record()
+        .admin_output
+        .iter()
+        .any(|output| output.starts_with("Done: kick")));
+}
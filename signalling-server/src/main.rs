@@ -1,9 +1,10 @@
 use actix::prelude::*;
 use actix_cors::Cors;
 use actix_web::{
-    get, post, web, App, Error, HttpRequest, HttpResponse, HttpServer, Responder, Result,
+    get, post, put, web, App, Error, HttpRequest, HttpResponse, HttpServer, Responder, Result,
 };
 use actix_web_actors::ws;
+use sha1::Sha1;
 use std::{
     collections::{HashMap, VecDeque},
     net::SocketAddr,
@@ -14,23 +15,102 @@ use tokio::sync::oneshot;
 const HOST_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
 const HOST_TIMEOUT: Duration = Duration::from_secs(20);
 
+/// How long a room that lost its host stays reserved for a successor to take over.
+const HOST_MIGRATION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often a client waiting on a room without a host checks whether a successor showed up.
+const HOST_MIGRATION_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug)]
+enum Room {
+    Hosted {
+        host_address: Addr<RoomHost>,
+
+        /// Lets the host say whether the room is full. Hosts that don't give one can't.
+        key: Option<String>,
+        is_full: bool,
+
+        /// The hash of the secret that the host's chosen successor takes the room over with.
+        successor_claim: Option<String>,
+    },
+
+    /// The host went away, and nobody has taken over yet.
+    Vacated {
+        vacated_time: Instant,
+        successor_claim: Option<String>,
+    },
+}
+
 #[derive(Default, Debug)]
 struct RoomRegistry {
-    rooms: HashMap<String, Addr<RoomHost>>,
+    rooms: HashMap<String, Room>,
 }
 
 #[derive(Message)]
 #[rtype(result = "()")]
-struct HostConnect(String, Addr<RoomHost>);
+struct HostConnect(String, Addr<RoomHost>, Option<String>);
 
 #[derive(Message)]
 #[rtype(result = "()")]
-struct HostDisconnect(String);
+struct HostDisconnect(String, Addr<RoomHost>);
+
+/// Takes over a vacated room, given the secret behind the claim that the previous host left.
+#[derive(Message)]
+#[rtype(result = "bool")]
+struct ClaimRoom {
+    id: String,
+    host_address: Addr<RoomHost>,
+    key: Option<String>,
+    claim: Option<String>,
+}
+
+/// Lets the host turn away anyone new while every spot in its room is taken.
+#[derive(Message)]
+#[rtype(result = "bool")]
+struct SetRoomFull {
+    id: String,
+    key: String,
+    is_full: bool,
+}
+
+/// Lets the host say who may take over the room if it goes away.
+#[derive(Message)]
+#[rtype(result = "bool")]
+struct SetSuccessorClaim {
+    id: String,
+    key: String,
+    successor_claim: String,
+}
+
+enum RoomStatus {
+    Hosted(Addr<RoomHost>),
+    Full,
+    Vacated,
+    Missing,
+}
 
 #[derive(Message)]
-#[rtype(result = "Option<Addr<RoomHost>>")]
+#[rtype(result = "RoomStatus")]
 struct GetHost(String);
 
+impl RoomRegistry {
+    /// Forgets about rooms that waited for a successor for too long.
+    fn remove_expired_rooms(&mut self) {
+        self.rooms.retain(|_, room| match room {
+            Room::Hosted { .. } => true,
+            Room::Vacated { vacated_time, .. } => vacated_time.elapsed() < HOST_MIGRATION_TIMEOUT,
+        });
+    }
+}
+
+/// Hashes a claim the same way as the game hashes its session secrets.
+fn hash_claim(claim: &str) -> Option<String> {
+    let secret: u64 = claim.parse().ok()?;
+    let mut hasher = Sha1::new();
+    hasher.update(&secret.to_le_bytes());
+    Some(hasher.digest().to_string())
+}
+
 impl Actor for RoomRegistry {
     type Context = Context<Self>;
 }
@@ -38,24 +118,123 @@ impl Actor for RoomRegistry {
 impl Handler<HostConnect> for RoomRegistry {
     type Result = ();
     fn handle(&mut self, msg: HostConnect, ctx: &mut Self::Context) {
-        let HostConnect(id, host_address) = msg;
-        self.rooms.insert(id, host_address);
+        let HostConnect(id, host_address, key) = msg;
+        self.rooms.insert(
+            id,
+            Room::Hosted {
+                host_address,
+                key,
+                is_full: false,
+                successor_claim: None,
+            },
+        );
     }
 }
 
 impl Handler<HostDisconnect> for RoomRegistry {
     type Result = ();
     fn handle(&mut self, msg: HostDisconnect, ctx: &mut Self::Context) {
-        let HostDisconnect(id) = msg;
-        self.rooms.remove(&id);
+        let HostDisconnect(id, host_address) = msg;
+        // Note: A host that failed to claim a room shouldn't vacate it for whoever has it.
+        if let Some(room) = self.rooms.get_mut(&id) {
+            if let Room::Hosted {
+                host_address: existing_address,
+                successor_claim,
+                ..
+            } = room
+            {
+                if *existing_address == host_address {
+                    *room = Room::Vacated {
+                        vacated_time: Instant::now(),
+                        successor_claim: successor_claim.take(),
+                    };
+                }
+            }
+        }
+        self.remove_expired_rooms();
+    }
+}
+
+impl Handler<ClaimRoom> for RoomRegistry {
+    type Result = bool;
+    fn handle(&mut self, msg: ClaimRoom, ctx: &mut Self::Context) -> Self::Result {
+        let ClaimRoom {
+            id,
+            host_address,
+            key,
+            claim,
+        } = msg;
+        self.remove_expired_rooms();
+        // Note: Without a claim from the previous host, nobody can take over, since anyone who
+        // knows the room id could otherwise take everyone's offers.
+        let claim_hash = claim.as_deref().and_then(hash_claim);
+        let is_successor = match self.rooms.get(&id) {
+            Some(Room::Vacated {
+                successor_claim: Some(successor_claim),
+                ..
+            }) => claim_hash.as_ref() == Some(successor_claim),
+            _ => false,
+        };
+        if is_successor {
+            self.rooms.insert(
+                id,
+                Room::Hosted {
+                    host_address,
+                    key,
+                    is_full: false,
+                    successor_claim: None,
+                },
+            );
+        }
+        is_successor
+    }
+}
+
+impl Handler<SetRoomFull> for RoomRegistry {
+    type Result = bool;
+    fn handle(&mut self, msg: SetRoomFull, ctx: &mut Self::Context) -> Self::Result {
+        match self.rooms.get_mut(&msg.id) {
+            Some(Room::Hosted {
+                key: Some(key),
+                is_full,
+                ..
+            }) if *key == msg.key => {
+                *is_full = msg.is_full;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Handler<SetSuccessorClaim> for RoomRegistry {
+    type Result = bool;
+    fn handle(&mut self, msg: SetSuccessorClaim, ctx: &mut Self::Context) -> Self::Result {
+        match self.rooms.get_mut(&msg.id) {
+            Some(Room::Hosted {
+                key: Some(key),
+                successor_claim,
+                ..
+            }) if *key == msg.key => {
+                *successor_claim = Some(msg.successor_claim);
+                true
+            }
+            _ => false,
+        }
     }
 }
 
 impl Handler<GetHost> for RoomRegistry {
-    type Result = Option<Addr<RoomHost>>;
+    type Result = MessageResult<GetHost>;
     fn handle(&mut self, msg: GetHost, ctx: &mut Self::Context) -> Self::Result {
         let GetHost(id) = msg;
-        self.rooms.get(&id).cloned()
+        self.remove_expired_rooms();
+        MessageResult(match self.rooms.get(&id) {
+            Some(Room::Hosted { is_full: true, .. }) => RoomStatus::Full,
+            Some(Room::Hosted { host_address, .. }) => RoomStatus::Hosted(host_address.clone()),
+            Some(Room::Vacated { .. }) => RoomStatus::Vacated,
+            None => RoomStatus::Missing,
+        })
     }
 }
 
@@ -63,6 +242,11 @@ impl Handler<GetHost> for RoomRegistry {
 struct RoomHost {
     answer_queue: VecDeque<oneshot::Sender<String>>,
     id: String,
+
+    /// Whether this host is taking over a room that lost its host, rather than opening a new one.
+    is_claiming: bool,
+    key: Option<String>,
+    claim: Option<String>,
     registry_address: Addr<RoomRegistry>,
     last_received_heartbeat: Instant,
 }
@@ -72,10 +256,18 @@ struct RoomHost {
 struct Offer(String);
 
 impl RoomHost {
-    fn new(registry_address: Addr<RoomRegistry>) -> RoomHost {
+    fn new(
+        registry_address: Addr<RoomRegistry>,
+        claimed_id: Option<String>,
+        key: Option<String>,
+        claim: Option<String>,
+    ) -> RoomHost {
         RoomHost {
             answer_queue: Default::default(),
-            id: nanoid::simple(),
+            is_claiming: claimed_id.is_some(),
+            key,
+            claim,
+            id: claimed_id.unwrap_or_else(nanoid::simple),
             registry_address,
             last_received_heartbeat: Instant::now(),
         }
@@ -86,12 +278,37 @@ impl Actor for RoomHost {
     type Context = ws::WebsocketContext<Self>;
 
     fn started(&mut self, ctx: &mut Self::Context) {
-        println!("Registering new room...");
-        self.registry_address
-            .do_send(HostConnect(self.id.clone(), ctx.address()));
-        // TODO: error handling
-        println!("Room opened");
-        ctx.text(self.id.clone());
+        if self.is_claiming {
+            println!("Taking over room...");
+            self.registry_address
+                .send(ClaimRoom {
+                    id: self.id.clone(),
+                    host_address: ctx.address(),
+                    key: self.key.clone(),
+                    claim: self.claim.clone(),
+                })
+                .into_actor(self)
+                .map(|claimed, act, ctx| {
+                    if let Ok(true) = claimed {
+                        println!("Room taken over");
+                        ctx.text(act.id.clone());
+                    } else {
+                        println!("Room was not waiting for this host");
+                        ctx.stop();
+                    }
+                })
+                .wait(ctx);
+        } else {
+            println!("Registering new room...");
+            self.registry_address.do_send(HostConnect(
+                self.id.clone(),
+                ctx.address(),
+                self.key.clone(),
+            ));
+            // TODO: error handling
+            println!("Room opened");
+            ctx.text(self.id.clone());
+        }
 
         ctx.run_interval(HOST_HEARTBEAT_INTERVAL, |act, ctx| {
             if Instant::now().duration_since(act.last_received_heartbeat) > HOST_TIMEOUT {
@@ -101,10 +318,10 @@ impl Actor for RoomHost {
         });
     }
 
-    fn stopped(&mut self, _ctx: &mut Self::Context) {
+    fn stopped(&mut self, ctx: &mut Self::Context) {
         println!("Deregistering room...");
         self.registry_address
-            .do_send(HostDisconnect(self.id.clone()));
+            .do_send(HostDisconnect(self.id.clone(), ctx.address()));
         println!("Room closed");
     }
 }
@@ -148,18 +365,29 @@ async fn join(
     room_registry_address: web::Data<Addr<RoomRegistry>>,
 ) -> impl Responder {
     let id = req.match_info().query("id").to_string();
-    match room_registry_address.send(GetHost(id)).await {
-        Ok(Some(room_host)) => {
-            let answer = room_host.send(Offer(offer)).await;
+    // Note: If the room is waiting for a successor to take over, hold on to the offer until
+    // they do.
+    let waiting_start = Instant::now();
+    loop {
+        match room_registry_address.send(GetHost(id.clone())).await {
+            Ok(RoomStatus::Hosted(room_host)) => {
+                let answer = room_host.send(Offer(offer)).await;
 
-            match answer {
-                Err(_) => HttpResponse::InternalServerError().body(""), // TODO
-                Ok(Err(_)) => HttpResponse::InternalServerError().body(""), // TODO
-                Ok(Ok(answer_text)) => HttpResponse::Ok().body(answer_text),
+                return match answer {
+                    Err(_) => HttpResponse::InternalServerError().body(""), // TODO
+                    Ok(Err(_)) => HttpResponse::InternalServerError().body(""), // TODO
+                    Ok(Ok(answer_text)) => HttpResponse::Ok().body(answer_text),
+                };
+            }
+            Ok(RoomStatus::Full) => {
+                return HttpResponse::ServiceUnavailable().body("Room is full");
+            }
+            Ok(RoomStatus::Vacated) if waiting_start.elapsed() < HOST_MIGRATION_TIMEOUT => {
+                actix_rt::time::delay_for(HOST_MIGRATION_POLL_INTERVAL).await;
             }
+            Ok(_) => return HttpResponse::NotFound().body("Room with given id not found"),
+            Err(_) => return HttpResponse::InternalServerError().body(""), // TODO
         }
-        Ok(None) => HttpResponse::NotFound().body("Room with given id not found"),
-        Err(_) => HttpResponse::InternalServerError().body(""), // TODO
     }
 }
 
@@ -167,17 +395,117 @@ async fn join(
 async fn host(
     req: HttpRequest,
     stream: web::Payload,
+    query: web::Query<HashMap<String, String>>,
     room_registry_address: web::Data<Addr<RoomRegistry>>,
 ) -> Result<HttpResponse, Error> {
     println!("Starting new host actor...");
     let resp = ws::start(
-        RoomHost::new(room_registry_address.get_ref().clone()),
+        RoomHost::new(
+            room_registry_address.get_ref().clone(),
+            None,
+            query.get("key").cloned(),
+            None,
+        ),
+        &req,
+        stream,
+    )?;
+    Ok(resp)
+}
+
+/// Lets a successor take over a room whose host went away.
+#[get("/host/{id}")]
+async fn rehost(
+    req: HttpRequest,
+    stream: web::Payload,
+    query: web::Query<HashMap<String, String>>,
+    room_registry_address: web::Data<Addr<RoomRegistry>>,
+) -> Result<HttpResponse, Error> {
+    let id = req.match_info().query("id").to_string();
+    println!("Starting new host actor to take over a room...");
+    let resp = ws::start(
+        RoomHost::new(
+            room_registry_address.get_ref().clone(),
+            Some(id),
+            query.get("key").cloned(),
+            query.get("claim").cloned(),
+        ),
         &req,
         stream,
     )?;
     Ok(resp)
 }
 
+/// Takes `true` or `false` from the host, depending on whether every spot in its room is taken.
+#[put("/host/{id}/full")]
+async fn room_full(
+    req: HttpRequest,
+    body: String,
+    query: web::Query<HashMap<String, String>>,
+    room_registry_address: web::Data<Addr<RoomRegistry>>,
+) -> impl Responder {
+    let id = req.match_info().query("id").to_string();
+    let is_full = match body.trim().parse() {
+        Ok(is_full) => is_full,
+        Err(_) => return HttpResponse::BadRequest().body("Expected true or false"),
+    };
+    let key = match query.get("key") {
+        Some(key) => key.clone(),
+        None => return HttpResponse::Forbidden().body("Missing host key"),
+    };
+    match room_registry_address
+        .send(SetRoomFull { id, key, is_full })
+        .await
+    {
+        Ok(true) => HttpResponse::Ok().body(""),
+        Ok(false) => HttpResponse::Forbidden().body("Not the host of this room"),
+        Err(_) => HttpResponse::InternalServerError().body(""), // TODO
+    }
+}
+
+/// Takes the hash of the successor's claim from the host.
+#[put("/host/{id}/successor")]
+async fn room_successor(
+    req: HttpRequest,
+    body: String,
+    query: web::Query<HashMap<String, String>>,
+    room_registry_address: web::Data<Addr<RoomRegistry>>,
+) -> impl Responder {
+    let id = req.match_info().query("id").to_string();
+    let key = match query.get("key") {
+        Some(key) => key.clone(),
+        None => return HttpResponse::Forbidden().body("Missing host key"),
+    };
+    let successor_claim = body.trim().to_lowercase();
+    match room_registry_address
+        .send(SetSuccessorClaim {
+            id,
+            key,
+            successor_claim,
+        })
+        .await
+    {
+        Ok(true) => HttpResponse::Ok().body(""),
+        Ok(false) => HttpResponse::Forbidden().body("Not the host of this room"),
+        Err(_) => HttpResponse::InternalServerError().body(""), // TODO
+    }
+}
+
+/// Lets a successor check that the room is waiting for it before taking it over.
+#[get("/room/{id}")]
+async fn room_status(
+    req: HttpRequest,
+    room_registry_address: web::Data<Addr<RoomRegistry>>,
+) -> impl Responder {
+    let id = req.match_info().query("id").to_string();
+    match room_registry_address.send(GetHost(id)).await {
+        Ok(RoomStatus::Hosted(_)) => HttpResponse::Ok().body("hosted"),
+        Ok(RoomStatus::Full) => HttpResponse::Ok().body("full"),
+        Ok(RoomStatus::Vacated) => HttpResponse::Ok().body("vacated"),
+        Ok(RoomStatus::Missing) => HttpResponse::NotFound().body("Room with given id not found"),
+        Err(_) => HttpResponse::InternalServerError().body(""), // TODO
+    }
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     let room_registry_address = RoomRegistry::default().start();
@@ -198,6 +526,10 @@ async fn main() -> std::io::Result<()> {
             .data(room_registry_address.clone())
             .service(join)
             .service(host)
+            .service(rehost)
+            .service(room_full)
+            .service(room_successor)
+            .service(room_status)
     })
     .bind(socket_address)?
     .run()